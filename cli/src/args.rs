@@ -0,0 +1,304 @@
+use std::path::PathBuf;
+
+use clap::{ArgMatches, Parser, ValueEnum};
+use midi_to_mml::{KeymapPreset, OptionsPreset, TempoPlacement, VelocityCurve};
+
+/// Converts MIDI files to MML, one MML text per track.
+#[derive(Debug, Parser)]
+#[command(
+    name = "midi-to-mml",
+    version,
+    after_help = "Operations run in the order they are given, on every song.\n\n\
+Exit codes:\n  \
+0  every file was converted\n  \
+1  an output could not be written\n  \
+2  invalid arguments\n  \
+3  invalid options or operations\n  \
+4  an input could not be read or converted"
+)]
+pub struct Cli {
+    /// MIDI files, or directories searched for .mid and .midi files
+    #[arg(required = true)]
+    pub inputs: Vec<PathBuf>,
+
+    #[arg(long, value_enum, default_value_t = OutputFormat::Text)]
+    pub format: OutputFormat,
+
+    /// Adds the position, duration and MIDI key of every note to the JSON output
+    #[arg(long)]
+    pub notes: bool,
+
+    /// Writes one file per track, or one JSON file per song, instead of printing
+    /// Files found in a directory keep their path relative to it
+    #[arg(long, short)]
+    pub out_dir: Option<PathBuf>,
+
+    /// JSON options to start from, in the format of `MmlSongOptionsBuilder`
+    #[arg(long)]
+    pub options: Option<PathBuf>,
+
+    /// piano-solo, band or compact
+    #[arg(long, value_parser = parse_preset)]
+    pub preset: Option<OptionsPreset>,
+
+    #[arg(long, alias = "auto-boost-velocity")]
+    pub auto_boot_velocity: Option<bool>,
+
+    #[arg(long)]
+    pub auto_equalize_note_length: Option<bool>,
+
+    #[arg(long)]
+    pub velocity_min: Option<u8>,
+
+    #[arg(long)]
+    pub velocity_max: Option<u8>,
+
+    /// In smallest units
+    #[arg(long)]
+    pub min_gap_for_chord: Option<u8>,
+
+    /// A power of two up to 256
+    #[arg(long)]
+    pub smallest_unit: Option<usize>,
+
+    /// linear, exponential:STRENGTH, logarithmic:STRENGTH, percentile:LOW-HIGH,
+    /// piecewise:MIDI=MML,... or JSON
+    #[arg(long, value_parser = parse_velocity_curve)]
+    pub velocity_curve: Option<VelocityCurve>,
+
+    /// all, first-non-empty, conductor:INDEX or JSON
+    #[arg(long, value_parser = parse_tempo_placement)]
+    pub tempo_placement: Option<TempoPlacement>,
+
+    /// Splits the track at INDEX in two
+    #[arg(long, value_name = "INDEX")]
+    pub split: Vec<usize>,
+
+    /// Merges track B into track A
+    #[arg(long, value_name = "A,B", value_parser = parse_pair)]
+    pub merge: Vec<(usize, usize)>,
+
+    /// Applies a keymap preset in JSON, to the track at INDEX or to every track it is meant for
+    #[arg(long, value_name = "[INDEX:]PRESET", value_parser = parse_keymap)]
+    pub keymap: Vec<(Option<usize>, KeymapPreset)>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum OutputFormat {
+    Text,
+    Json,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Operation {
+    Split(usize),
+    Merge(usize, usize),
+    Keymap(Option<usize>, KeymapPreset),
+}
+
+impl Cli {
+    /// The split, merge and keymap operations in the order of the arguments.
+    pub fn get_operations(&self, matches: &ArgMatches) -> Vec<Operation> {
+        let get_indexes = |id: &str| -> Vec<usize> {
+            matches
+                .indices_of(id)
+                .map(|indexes| indexes.collect())
+                .unwrap_or_default()
+        };
+
+        let mut operations: Vec<(usize, Operation)> = Vec::new();
+        operations.extend(
+            get_indexes("split")
+                .into_iter()
+                .zip(self.split.iter())
+                .map(|(position, index)| (position, Operation::Split(*index))),
+        );
+        operations.extend(
+            get_indexes("merge")
+                .into_iter()
+                .zip(self.merge.iter())
+                .map(|(position, (a, b))| (position, Operation::Merge(*a, *b))),
+        );
+        operations.extend(
+            get_indexes("keymap")
+                .into_iter()
+                .zip(self.keymap.iter())
+                .map(|(position, (index, preset))| {
+                    (position, Operation::Keymap(*index, preset.to_owned()))
+                }),
+        );
+        operations.sort_by_key(|(position, _)| *position);
+
+        operations
+            .into_iter()
+            .map(|(_, operation)| operation)
+            .collect()
+    }
+}
+
+fn parse_preset(value: &str) -> Result<OptionsPreset, String> {
+    OptionsPreset::from_name(&value.replace(['-', '_'], " "))
+        .ok_or_else(|| String::from("expected piano-solo, band or compact"))
+}
+
+fn parse_number<T: std::str::FromStr>(value: &str) -> Result<T, String> {
+    value
+        .trim()
+        .parse()
+        .map_err(|_| format!("`{value}` is not a number"))
+}
+
+fn parse_pair(value: &str) -> Result<(usize, usize), String> {
+    let (a, b) = value
+        .split_once(',')
+        .ok_or_else(|| String::from("expected two indexes like 0,1"))?;
+    Ok((parse_number(a)?, parse_number(b)?))
+}
+
+fn parse_json<T: serde::de::DeserializeOwned>(value: &str) -> Result<T, String> {
+    serde_json::from_str(value).map_err(|error| error.to_string())
+}
+
+fn parse_velocity_curve(value: &str) -> Result<VelocityCurve, String> {
+    if value.starts_with('{') {
+        return parse_json(value);
+    }
+
+    let (kind, parameter) = value.split_once(':').unwrap_or((value, ""));
+    match kind {
+        "linear" => Ok(VelocityCurve::Linear),
+        "exponential" => Ok(VelocityCurve::Exponential {
+            strength: parse_number(parameter)?,
+        }),
+        "logarithmic" => Ok(VelocityCurve::Logarithmic {
+            strength: parse_number(parameter)?,
+        }),
+        "percentile" => {
+            let (low, high) = parameter
+                .split_once('-')
+                .ok_or_else(|| String::from("expected percentile:LOW-HIGH"))?;
+            Ok(VelocityCurve::Percentile {
+                low: parse_number(low)?,
+                high: parse_number(high)?,
+                fitted: None,
+            })
+        }
+        "piecewise" => {
+            let points = parameter
+                .split(',')
+                .map(|point| {
+                    let (midi, mml) = point
+                        .split_once('=')
+                        .ok_or_else(|| String::from("expected piecewise:MIDI=MML,..."))?;
+                    Ok((parse_number(midi)?, parse_number(mml)?))
+                })
+                .collect::<Result<Vec<(u8, u8)>, String>>()?;
+            Ok(VelocityCurve::Piecewise { points })
+        }
+        _ => Err(format!("unknown velocity curve `{kind}`")),
+    }
+}
+
+fn parse_tempo_placement(value: &str) -> Result<TempoPlacement, String> {
+    if value.starts_with('{') {
+        return parse_json(value);
+    }
+
+    match value.split_once(':') {
+        Some(("conductor", index)) => Ok(TempoPlacement::Conductor {
+            index: parse_number(index)?,
+        }),
+        None if value == "all" => Ok(TempoPlacement::AllTracks),
+        None if value == "first-non-empty" => Ok(TempoPlacement::FirstNonEmpty),
+        _ => Err(String::from(
+            "expected all, first-non-empty or conductor:INDEX",
+        )),
+    }
+}
+
+fn parse_keymap(value: &str) -> Result<(Option<usize>, KeymapPreset), String> {
+    if value.starts_with('{') {
+        return Ok((None, parse_json(value)?));
+    }
+
+    let (index, preset) = value
+        .split_once(':')
+        .ok_or_else(|| String::from("expected [INDEX:]PRESET"))?;
+    Ok((Some(parse_number(index)?), parse_json(preset)?))
+}
+
+#[cfg(test)]
+mod tests {
+    use clap::CommandFactory;
+
+    use super::*;
+
+    fn parse_args(args: &[&str]) -> (Cli, Vec<Operation>) {
+        let matches = Cli::command()
+            .try_get_matches_from(["midi-to-mml"].iter().chain(args))
+            .unwrap();
+        let cli = <Cli as clap::FromArgMatches>::from_arg_matches(&matches).unwrap();
+        let operations = cli.get_operations(&matches);
+        (cli, operations)
+    }
+
+    #[test]
+    fn test_operations_in_order() {
+        let (_, operations) = parse_args(&[
+            "song.mid",
+            "--merge",
+            "0,1",
+            "--split",
+            "2",
+            "--keymap",
+            r#"1:{"type": "mirror", "pivot": 60}"#,
+            "--split",
+            "0",
+        ]);
+
+        assert_eq!(
+            operations,
+            vec![
+                Operation::Merge(0, 1),
+                Operation::Split(2),
+                Operation::Keymap(Some(1), KeymapPreset::Mirror { pivot: 60 }),
+                Operation::Split(0),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_option_values() {
+        let (cli, _) = parse_args(&[
+            "song.mid",
+            "--preset",
+            "piano-solo",
+            "--velocity-curve",
+            "percentile:5-95",
+            "--tempo-placement",
+            "conductor:2",
+        ]);
+
+        assert_eq!(cli.preset, Some(OptionsPreset::PianoSolo));
+        assert_eq!(
+            cli.velocity_curve,
+            Some(VelocityCurve::Percentile {
+                low: 5,
+                high: 95,
+                fitted: None
+            })
+        );
+        assert_eq!(
+            cli.tempo_placement,
+            Some(TempoPlacement::Conductor { index: 2 })
+        );
+        assert_eq!(
+            parse_velocity_curve("piecewise:0=0,127=15"),
+            Ok(VelocityCurve::Piecewise {
+                points: vec![(0, 0), (127, 15)]
+            })
+        );
+        assert!(parse_velocity_curve("cubic").is_err());
+        assert!(parse_pair("1").is_err());
+    }
+}
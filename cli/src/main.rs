@@ -0,0 +1,245 @@
+mod args;
+
+use std::{
+    collections::HashSet,
+    fs,
+    io::{self, Write},
+    path::{Path, PathBuf},
+    process::ExitCode,
+};
+
+use clap::{CommandFactory, FromArgMatches};
+use midi_to_mml::{
+    ExportOptions, MmlError, MmlSong, MmlSongOptions, MmlSongOptionsBuilder, SongExport,
+};
+use serde::Serialize;
+
+use crate::args::{Cli, Operation, OutputFormat};
+
+/// Failures of the command, worst last so the exit code of a batch is the highest one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Failure {
+    Output = 1,
+    Invalid = 3,
+    Input = 4,
+}
+
+impl Failure {
+    fn from_error(error: &MmlError) -> Self {
+        match error {
+            MmlError::Options { .. } | MmlError::Index { .. } | MmlError::Constraint(_) => {
+                Self::Invalid
+            }
+            _ => Self::Input,
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct SongOutput<'a> {
+    file: &'a Path,
+
+    #[serde(flatten)]
+    song: SongExport,
+}
+
+fn main() -> ExitCode {
+    let matches = Cli::command().get_matches();
+    let cli = match Cli::from_arg_matches(&matches) {
+        Ok(cli) => cli,
+        Err(error) => error.exit(),
+    };
+    let operations = cli.get_operations(&matches);
+
+    let options = match get_options(&cli) {
+        Ok(options) => options,
+        Err(error) => {
+            eprintln!("error: {error}");
+            return ExitCode::from(Failure::Invalid as u8);
+        }
+    };
+
+    let files = match get_input_files(&cli.inputs) {
+        Ok(files) => files,
+        Err(error) => {
+            eprintln!("error: {error}");
+            return ExitCode::from(Failure::Input as u8);
+        }
+    };
+
+    let mut worst_failure: Option<Failure> = None;
+    let mut output_names: HashSet<&Path> = HashSet::new();
+    for (file, name) in files.iter() {
+        let result = if cli.out_dir.is_some() && !output_names.insert(name) {
+            Err((
+                Failure::Output,
+                format!("the output `{}` is taken by another input", name.display()),
+            ))
+        } else {
+            convert(file, &options, &operations)
+                .map_err(|error| (Failure::from_error(&error), error.to_string()))
+                .and_then(|song| {
+                    write_song(&cli, file, name, &song)
+                        .map_err(|error| (Failure::Output, error.to_string()))
+                })
+        };
+
+        if let Err((failure, message)) = result {
+            eprintln!("error: {}: {message}", file.display());
+            worst_failure = worst_failure.max(Some(failure));
+        }
+    }
+
+    match worst_failure {
+        Some(failure) => ExitCode::from(failure as u8),
+        None => ExitCode::SUCCESS,
+    }
+}
+
+/// The options file with the flags on top, validated.
+fn get_options(cli: &Cli) -> Result<MmlSongOptions, String> {
+    let mut builder = match &cli.options {
+        Some(path) => {
+            let json =
+                fs::read_to_string(path).map_err(|error| format!("{}: {error}", path.display()))?;
+            serde_json::from_str(&json).map_err(|error| format!("{}: {error}", path.display()))?
+        }
+        None => MmlSongOptionsBuilder::default(),
+    };
+
+    if let Some(preset) = cli.preset {
+        builder = builder.preset(preset);
+    }
+    if let Some(value) = cli.auto_boot_velocity {
+        builder = builder.auto_boot_velocity(value);
+    }
+    if let Some(value) = cli.auto_equalize_note_length {
+        builder = builder.auto_equalize_note_length(value);
+    }
+    if let Some(value) = cli.velocity_min {
+        builder = builder.velocity_min(value);
+    }
+    if let Some(value) = cli.velocity_max {
+        builder = builder.velocity_max(value);
+    }
+    if let Some(value) = cli.min_gap_for_chord {
+        builder = builder.min_gap_for_chord(value);
+    }
+    if let Some(value) = cli.smallest_unit {
+        builder = builder.smallest_unit(value);
+    }
+    if let Some(value) = &cli.velocity_curve {
+        builder = builder.velocity_curve(value.to_owned());
+    }
+    if let Some(value) = &cli.tempo_placement {
+        builder = builder.tempo_placement(value.to_owned());
+    }
+
+    builder.build().map_err(|error| error.to_string())
+}
+
+/// Files as they are given, the MIDI files of directories sorted by path.
+/// Each file comes with its output name, the path relative to its directory without the extension.
+fn get_input_files(inputs: &[PathBuf]) -> io::Result<Vec<(PathBuf, PathBuf)>> {
+    let mut files: Vec<(PathBuf, PathBuf)> = Vec::new();
+
+    for input in inputs {
+        if input.is_dir() {
+            let mut dir_files = Vec::new();
+            find_midi_files(input, &mut dir_files)?;
+            dir_files.sort();
+            files.extend(dir_files.into_iter().map(|file| {
+                let name = file.strip_prefix(input).unwrap_or(&file).with_extension("");
+                (file, name)
+            }));
+        } else {
+            let name = input.file_stem().map(PathBuf::from).unwrap_or_default();
+            files.push((input.to_owned(), name));
+        }
+    }
+
+    Ok(files)
+}
+
+fn find_midi_files(dir: &Path, files: &mut Vec<PathBuf>) -> io::Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            find_midi_files(&path, files)?;
+        } else if path.extension().is_some_and(|extension| {
+            extension.eq_ignore_ascii_case("mid") || extension.eq_ignore_ascii_case("midi")
+        }) {
+            files.push(path);
+        }
+    }
+
+    Ok(())
+}
+
+fn convert(
+    file: &Path,
+    options: &MmlSongOptions,
+    operations: &[Operation],
+) -> Result<MmlSong, MmlError> {
+    let mut song = MmlSong::from_path(file, options.to_owned())?;
+
+    for operation in operations {
+        match operation {
+            Operation::Split(index) => song.split_track(*index)?,
+            Operation::Merge(index_a, index_b) => song.merge_tracks(*index_a, *index_b)?,
+            Operation::Keymap(index, preset) => song.apply_keymap_preset(*index, preset)?,
+        }
+    }
+
+    Ok(song)
+}
+
+fn write_song(cli: &Cli, file: &Path, name: &Path, song: &MmlSong) -> io::Result<()> {
+    let output = SongOutput {
+        file,
+        song: song.export(&ExportOptions {
+            include_notes: cli.notes,
+        }),
+    };
+    let out_path = cli.out_dir.as_ref().map(|dir| dir.join(name));
+    if let Some(parent) = out_path.as_ref().and_then(|path| path.parent()) {
+        fs::create_dir_all(parent)?;
+    }
+
+    match (&out_path, cli.format) {
+        (Some(path), OutputFormat::Text) => {
+            for (index, track) in output.song.tracks.iter().enumerate() {
+                fs::write(with_suffix(path, &format!("_{index}.mml")), &track.mml)?;
+            }
+        }
+        (Some(path), OutputFormat::Json) => {
+            fs::write(
+                with_suffix(path, ".json"),
+                serde_json::to_string_pretty(&output)?,
+            )?;
+        }
+        (None, OutputFormat::Text) => {
+            let mut stdout = io::stdout().lock();
+            if cli.inputs.len() > 1 || cli.inputs.iter().any(|input| input.is_dir()) {
+                writeln!(stdout, "== {} ==", file.display())?;
+            }
+            for (index, track) in output.song.tracks.iter().enumerate() {
+                writeln!(stdout, "[{index}] {}\n{}\n", track.name, track.mml)?;
+            }
+        }
+        (None, OutputFormat::Json) => {
+            // One line per song
+            let mut stdout = io::stdout().lock();
+            writeln!(stdout, "{}", serde_json::to_string(&output)?)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// The path with `suffix` added to its file name, which may contain dots.
+fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut path = path.as_os_str().to_owned();
+    path.push(suffix);
+    path.into()
+}
@@ -0,0 +1,115 @@
+use std::process::{Command, Output};
+
+const MIDI_PATH: &str = "../assets/heart_beat-band.mid";
+
+fn run(args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_midi-to-mml"))
+        .args(args)
+        .output()
+        .unwrap()
+}
+
+fn get_json_tracks(output: &Output) -> Vec<serde_json::Value> {
+    let line = String::from_utf8_lossy(&output.stdout);
+    let song: serde_json::Value = serde_json::from_str(line.trim()).unwrap();
+    song["tracks"].as_array().unwrap().to_owned()
+}
+
+#[test]
+fn test_convert_to_stdout() {
+    let output = run(&[MIDI_PATH]);
+    assert!(output.status.success());
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.starts_with("[0] "));
+    assert!(stdout.lines().nth(1).is_some_and(|mml| !mml.is_empty()));
+}
+
+#[test]
+fn test_operations_and_json() {
+    let tracks = get_json_tracks(&run(&[MIDI_PATH, "--format", "json"]));
+
+    // The split adds a track and the merge removes it again
+    let split = get_json_tracks(&run(&[MIDI_PATH, "--format", "json", "--split", "0"]));
+    assert_eq!(split.len(), tracks.len() + 1);
+
+    let merged = get_json_tracks(&run(&[
+        MIDI_PATH, "--format", "json", "--split", "0", "--merge", "0,1",
+    ]));
+    assert_eq!(merged.len(), tracks.len());
+    assert!(merged[0]["mml"].as_str().is_some_and(|mml| !mml.is_empty()));
+    assert!(merged[0].get("notes").is_none());
+
+    let tracks = get_json_tracks(&run(&[MIDI_PATH, "--format", "json", "--notes"]));
+    let note_count = tracks[0]["note_count"].as_u64().unwrap() as usize;
+    assert_eq!(tracks[0]["notes"].as_array().unwrap().len(), note_count);
+}
+
+#[test]
+fn test_out_dir() {
+    let dir = tempfile::tempdir().unwrap();
+    let out_dir = dir.path().to_str().unwrap();
+
+    let output = run(&[MIDI_PATH, "--smallest-unit", "32", "--out-dir", out_dir]);
+    assert!(output.status.success());
+    assert!(dir.path().join("heart_beat-band_0.mml").exists());
+
+    let output = run(&[MIDI_PATH, "--format", "json", "--out-dir", out_dir]);
+    assert!(output.status.success());
+    assert!(dir.path().join("heart_beat-band.json").exists());
+}
+
+#[test]
+fn test_out_dir_names() {
+    let input_dir = tempfile::tempdir().unwrap();
+    for path in ["a/song.mid", "b/song.midi"] {
+        let path = input_dir.path().join(path);
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::copy(MIDI_PATH, path).unwrap();
+    }
+
+    // Files of a directory are named by their path in it
+    let dir = tempfile::tempdir().unwrap();
+    let output = run(&[
+        input_dir.path().to_str().unwrap(),
+        "--out-dir",
+        dir.path().to_str().unwrap(),
+    ]);
+    assert!(output.status.success());
+    assert!(dir.path().join("a/song_0.mml").exists());
+    assert!(dir.path().join("b/song_0.mml").exists());
+
+    // Files given one by one only have their name
+    let dir = tempfile::tempdir().unwrap();
+    let output = run(&[
+        input_dir.path().join("a/song.mid").to_str().unwrap(),
+        input_dir.path().join("b/song.midi").to_str().unwrap(),
+        "--format",
+        "json",
+        "--out-dir",
+        dir.path().to_str().unwrap(),
+    ]);
+    assert_eq!(output.status.code(), Some(1));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("b/song.midi"));
+    assert!(dir.path().join("song.json").exists());
+}
+
+#[test]
+fn test_exit_codes() {
+    assert_eq!(
+        run(&[MIDI_PATH, "--velocity-max", "16"]).status.code(),
+        Some(3)
+    );
+    assert_eq!(
+        run(&[MIDI_PATH, "--smallest-unit", "48"]).status.code(),
+        Some(3)
+    );
+    assert_eq!(run(&[MIDI_PATH, "--split", "99"]).status.code(), Some(3));
+    assert_eq!(run(&["../assets/missing.mid"]).status.code(), Some(4));
+    assert_eq!(run(&[MIDI_PATH, "--merge", "1"]).status.code(), Some(2));
+
+    // The other files are still converted
+    let output = run(&["../assets/missing.mid", MIDI_PATH]);
+    assert_eq!(output.status.code(), Some(4));
+    assert!(!output.stdout.is_empty());
+}
@@ -208,11 +208,7 @@ impl Handler<SignalRenameTrackRequest> for SongActor {
         _: &Context<Self>,
     ) -> Result<SignalUpdateMmlTracks> {
         let song = self.song.as_mut().context("Song is None")?;
-        let track = song
-            .tracks
-            .get_mut(index as usize)
-            .with_context(|| format!("Track at {index} is None"))?;
-        track.name = name;
+        song.rename_track(index as usize, name)?;
         let tracks = signal_converter::mml_song_tracks_to_signal(&song.tracks);
         Ok(SignalUpdateMmlTracks { tracks })
     }
@@ -230,7 +226,7 @@ impl Handler<SignalApplyKeymap> for SongActor {
         _: &Context<Self>,
     ) -> Result<SignalUpdateMmlTracks> {
         let song = self.song.as_mut().context("Song is None")?;
-        song.apply_keymap(track_index as usize, &keymap);
+        song.apply_keymap(track_index as usize, &keymap)?;
         let tracks = signal_converter::mml_song_tracks_to_signal(&song.tracks);
         Ok(SignalUpdateMmlTracks { tracks })
     }
@@ -47,5 +47,6 @@ pub fn signal_to_mml_song_options(options: &SignalMmlSongOptions) -> MmlSongOpti
         velocity_max: options.velocity_max as u8,
         min_gap_for_chord: options.min_gap_for_chord as u8,
         smallest_unit: options.smallest_unit as usize,
+        ..MmlSongOptions::default()
     }
 }
@@ -0,0 +1,308 @@
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    MmlTrack,
+    mml_event::{BridgeEvent, MmlEvent},
+    syntax::DEFAULT_VELOCITY,
+    tempo::{TempoMap, get_tempos},
+    utils,
+    verification::TrackVerification,
+};
+
+/// Number of MML velocities, from 0 to 15
+const MML_VELOCITY_COUNT: usize = 16;
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct TempoSummary {
+    /// Tempo events, the default tempo is counted when the song starts without one
+    pub count: usize,
+    pub min: u32,
+    pub max: u32,
+
+    /// The single tempo that gives the song the same length
+    pub average: f64,
+}
+
+/// Timing of the MML compared with the MIDI notes, see `MmlTrack::verify`.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct QuantizationSummary {
+    pub mean_error_ms: f64,
+    pub max_error_ms: f64,
+    pub dropped_count: usize,
+    pub pitch_error_count: usize,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct TrackAnalysis {
+    pub name: String,
+
+    /// MIDI notes of the track
+    pub note_count: usize,
+
+    /// Characters of `MmlTrack::to_mml`
+    pub char_count: usize,
+
+    /// `(lowest, highest)` MIDI key, `None` without notes
+    pub pitch_range: Option<(u8, u8)>,
+
+    /// Most MIDI notes playing at once
+    pub max_polyphony: usize,
+
+    /// Notes playing at once on average, over the time at least one note plays
+    pub average_polyphony: f64,
+
+    /// Number of notes starting in each bar, from the first bar to the last one with a note
+    pub notes_per_bar: Vec<usize>,
+
+    /// Number of MML notes played at each MML velocity, from 0 to 15
+    pub velocity_distribution: Vec<usize>,
+    pub tempo: TempoSummary,
+
+    /// Until the end of the last note
+    pub duration_seconds: f64,
+    pub quantization: QuantizationSummary,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct SongAnalysis {
+    pub tracks: Vec<TrackAnalysis>,
+    pub note_count: usize,
+    pub char_count: usize,
+    pub pitch_range: Option<(u8, u8)>,
+
+    /// Most MIDI notes playing at once over every track
+    pub max_polyphony: usize,
+    pub tempo: TempoSummary,
+    pub duration_seconds: f64,
+}
+
+/// `verification` is the result of `MmlTrack::verify` or `MmlSong::verify` for the track.
+pub(crate) fn analyze_track(track: &MmlTrack, verification: &TrackVerification) -> TrackAnalysis {
+    let notes = get_note_ranges(&track.bridge_note_events);
+    let (max_polyphony, average_polyphony) = get_polyphony(&notes);
+    let end = notes.iter().map(|(_, end, _)| *end).max().unwrap_or(0);
+    let tempo_map = TempoMap::new(get_tempos(&track.bridge_meta_events), track.ppq as f64);
+
+    TrackAnalysis {
+        name: track.name.to_owned(),
+        note_count: notes.len(),
+        char_count: track.to_mml().chars().count(),
+        pitch_range: get_pitch_range(&notes),
+        max_polyphony,
+        average_polyphony,
+        notes_per_bar: get_notes_per_bar(track, &notes),
+        velocity_distribution: get_velocity_distribution(&track.events),
+        tempo: get_tempo_summary(&track.bridge_meta_events, end, track.ppq),
+        duration_seconds: tempo_map.to_ms(end) / 1000.,
+        quantization: QuantizationSummary {
+            mean_error_ms: verification.mean_drift_ms,
+            max_error_ms: verification.max_drift_ms,
+            dropped_count: verification.dropped_notes.len(),
+            pitch_error_count: verification.pitch_errors.len(),
+        },
+    }
+}
+
+pub(crate) fn analyze_song(tracks: &[MmlTrack], analyses: Vec<TrackAnalysis>) -> SongAnalysis {
+    let notes: Vec<(usize, usize, u8)> = tracks
+        .iter()
+        .flat_map(|track| get_note_ranges(&track.bridge_note_events))
+        .collect();
+    let end = notes.iter().map(|(_, end, _)| *end).max().unwrap_or(0);
+
+    // Every track has the tempo events, the one with the most of them is the reference
+    let tempo = tracks
+        .iter()
+        .max_by_key(|track| get_tempos(&track.bridge_meta_events).len())
+        .map(|track| get_tempo_summary(&track.bridge_meta_events, end, track.ppq))
+        .unwrap_or_default();
+
+    SongAnalysis {
+        note_count: notes.len(),
+        char_count: analyses.iter().map(|analysis| analysis.char_count).sum(),
+        pitch_range: get_pitch_range(&notes),
+        max_polyphony: get_polyphony(&notes).0,
+        tempo,
+        duration_seconds: analyses
+            .iter()
+            .map(|analysis| analysis.duration_seconds)
+            .fold(0., f64::max),
+        tracks: analyses,
+    }
+}
+
+/// `(start, end, key)` of every note.
+fn get_note_ranges(events: &[BridgeEvent]) -> Vec<(usize, usize, u8)> {
+    events
+        .iter()
+        .filter_map(|event| match event {
+            BridgeEvent::Note(note) => {
+                let start = note.midi_state.position_in_tick;
+                Some((start, start + note.midi_state.duration_in_tick, note.key))
+            }
+            _ => None,
+        })
+        .collect()
+}
+
+fn get_pitch_range(notes: &[(usize, usize, u8)]) -> Option<(u8, u8)> {
+    let lowest = notes.iter().map(|(_, _, key)| *key).min()?;
+    let highest = notes.iter().map(|(_, _, key)| *key).max()?;
+    Some((lowest, highest))
+}
+
+/// `(max, average)` number of notes playing at once.
+/// A note ending where another starts does not overlap with it.
+fn get_polyphony(notes: &[(usize, usize, u8)]) -> (usize, f64) {
+    let mut changes: Vec<(usize, isize)> = notes
+        .iter()
+        .filter(|(start, end, _)| end > start)
+        .flat_map(|(start, end, _)| [(*start, 1), (*end, -1)])
+        .collect();
+    changes.sort();
+
+    let mut max = 0usize;
+    let mut count = 0isize;
+    let mut sounding_ticks = 0usize;
+    let mut weighted_ticks = 0usize;
+    let mut previous = 0usize;
+
+    for (position, change) in changes {
+        if count > 0 {
+            sounding_ticks += position - previous;
+            weighted_ticks += (position - previous) * count as usize;
+        }
+
+        count += change;
+        max = max.max(count as usize);
+        previous = position;
+    }
+
+    let average = if sounding_ticks > 0 {
+        weighted_ticks as f64 / sounding_ticks as f64
+    } else {
+        0.
+    };
+
+    (max, average)
+}
+
+fn get_notes_per_bar(track: &MmlTrack, notes: &[(usize, usize, u8)]) -> Vec<usize> {
+    let bar_lengths = utils::get_bar_lengths_in_tick(&track.bridge_meta_events, track.ppq);
+    let mut notes_per_bar: Vec<usize> = Vec::new();
+
+    for (start, _, _) in notes.iter() {
+        let bar = utils::tick_to_bar(&bar_lengths, *start);
+        if bar >= notes_per_bar.len() {
+            notes_per_bar.resize(bar + 1, 0);
+        }
+        notes_per_bar[bar] += 1;
+    }
+
+    notes_per_bar
+}
+
+fn get_velocity_distribution(events: &[MmlEvent]) -> Vec<usize> {
+    let mut distribution = vec![0usize; MML_VELOCITY_COUNT];
+    let mut velocity = DEFAULT_VELOCITY;
+
+    for event in events.iter() {
+        match event {
+            MmlEvent::Velocity(new_velocity) => velocity = *new_velocity,
+            MmlEvent::Note(_) => distribution[(velocity as usize).min(MML_VELOCITY_COUNT - 1)] += 1,
+            _ => (),
+        }
+    }
+
+    distribution
+}
+
+fn get_tempo_summary(meta_events: &[BridgeEvent], end: usize, ppq: u16) -> TempoSummary {
+    let tempos = get_tempos(meta_events);
+    let tempo_map = TempoMap::new(tempos.to_owned(), ppq as f64);
+    let duration_ms = tempo_map.to_ms(end);
+
+    let average = if duration_ms > 0. {
+        end as f64 / ppq as f64 * 60_000. / duration_ms
+    } else {
+        tempos
+            .first()
+            .map(|(_, tempo)| *tempo as f64)
+            .unwrap_or_default()
+    };
+
+    TempoSummary {
+        count: tempos.len(),
+        min: tempos
+            .iter()
+            .map(|(_, tempo)| *tempo)
+            .min()
+            .unwrap_or_default(),
+        max: tempos
+            .iter()
+            .map(|(_, tempo)| *tempo)
+            .max()
+            .unwrap_or_default(),
+        average,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{MmlNote, MmlSongOptions, mml_event::MidiNoteState, test_utils::state};
+
+    fn note() -> MmlEvent {
+        let midi_state = MidiNoteState {
+            key: 60,
+            velocity: 100,
+            midi_state: state(0, 480),
+        };
+        MmlEvent::Note(MmlNote::from_midi_state(
+            midi_state,
+            &MmlSongOptions::default(),
+            480,
+            false,
+        ))
+    }
+
+    #[test]
+    fn test_polyphony() {
+        // Two notes together for a quarter, then one note for a quarter, an empty note is ignored
+        let notes = vec![(0, 960, 60), (0, 480, 64), (1440, 1440, 69)];
+
+        assert_eq!(get_polyphony(&notes), (2, 1.5));
+        assert_eq!(get_polyphony(&[]), (0, 0.));
+    }
+
+    #[test]
+    fn test_velocity_distribution() {
+        let events = vec![
+            MmlEvent::Rest(4),
+            MmlEvent::Velocity(10),
+            note(),
+            note(),
+            MmlEvent::Velocity(20),
+            note(),
+        ];
+        let distribution = get_velocity_distribution(&events);
+
+        assert_eq!(distribution.len(), 16);
+        assert_eq!(distribution[10], 2);
+        assert_eq!(distribution[15], 1);
+    }
+
+    #[test]
+    fn test_tempo_summary() {
+        // 2 quarters at 120 then 2 quarters at 60, 3 seconds for 4 quarters
+        let meta_events = vec![
+            BridgeEvent::Tempo(120, state(0, 0)),
+            BridgeEvent::Tempo(60, state(960, 0)),
+        ];
+        let summary = get_tempo_summary(&meta_events, 1920, 480);
+
+        assert_eq!(summary.count, 2);
+        assert_eq!((summary.min, summary.max), (60, 120));
+        assert!((summary.average - 80.).abs() < 1e-9);
+    }
+}
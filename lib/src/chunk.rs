@@ -0,0 +1,337 @@
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    MmlTrack,
+    error::{ConstraintError, Result},
+    mml_event::MmlEvent,
+    syntax::{DEFAULT_OCTAVE, DEFAULT_TEMPO, DEFAULT_VELOCITY},
+    tempo::{TempoMap, get_tempos},
+    utils,
+};
+
+/// A part of the MML of a track that plays alone, see `MmlTrack::to_mml_chunks`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MmlChunk {
+    pub mml: String,
+    pub position_in_smallest_unit: usize,
+    pub start_seconds: f64,
+}
+
+/// Commands in effect at a point of the MML.
+#[derive(Debug, Clone)]
+struct ChunkState {
+    octave: u8,
+    velocity: u8,
+    tempo: u32,
+    note_length: Option<u8>,
+}
+
+impl ChunkState {
+    fn update(&mut self, event: &MmlEvent) {
+        match event {
+            MmlEvent::Octave(octave) => self.octave = *octave,
+            MmlEvent::IncreOctave => self.octave = self.octave.saturating_add(1),
+            MmlEvent::DecreOctave => self.octave = self.octave.saturating_sub(1),
+            MmlEvent::Velocity(velocity) => self.velocity = *velocity,
+            MmlEvent::Tempo(tempo, _) => self.tempo = *tempo,
+            MmlEvent::NoteLength(length) => self.note_length = Some(*length),
+            _ => (),
+        }
+    }
+
+    fn to_mml(&self) -> String {
+        let mut mml = format!("t{}o{}v{}", self.tempo, self.octave, self.velocity);
+        if let Some(length) = self.note_length {
+            mml.push_str(&format!("l{length}"));
+        }
+        mml
+    }
+}
+
+/// Events that cannot be split, from the end of a note or rest to the end of the next one.
+/// A chord and the commands before it stay in the same segment.
+struct Segment {
+    /// `t` command of a track without tempo commands, when the tempo of the song changed since
+    /// the segment before, left out at the start of a chunk where the state has it
+    tempo: String,
+    mml: String,
+    position: usize,
+    bar: usize,
+    state: ChunkState,
+}
+
+pub(crate) fn chunk_track(track: &MmlTrack, max_chars: usize) -> Result<Vec<MmlChunk>> {
+    let segments = get_segments(track);
+    let tempo_map = TempoMap::new(get_tempos(&track.bridge_meta_events), track.ppq as f64);
+    let smallest_unit = track.song_options.smallest_unit;
+
+    let mut chunks: Vec<MmlChunk> = Vec::new();
+    let mut start = 0usize;
+
+    while start < segments.len() {
+        // Every chunk starts from the default state when played alone
+        let prefix = segments[start].state.to_mml();
+        let mut length = prefix.len() + segments[start].mml.len();
+        if length > max_chars {
+            return Err(ConstraintError::ChunkTooShort {
+                max_chars,
+                needed: length,
+            }
+            .into());
+        }
+
+        let mut end = start + 1;
+        let mut last_bar_start: Option<usize> = None;
+
+        while let Some(segment) = segments.get(end) {
+            let segment_length = segment.tempo.len() + segment.mml.len();
+            if length + segment_length > max_chars {
+                break;
+            }
+            if segment.bar > segments[end - 1].bar {
+                last_bar_start = Some(end);
+            }
+            length += segment_length;
+            end += 1;
+        }
+
+        if end < segments.len()
+            && let Some(bar_start) = last_bar_start
+        {
+            end = bar_start;
+        }
+
+        let mut mml = prefix;
+        mml.push_str(&segments[start].mml);
+        for segment in segments[start + 1..end].iter() {
+            mml.push_str(&segment.tempo);
+            mml.push_str(&segment.mml);
+        }
+
+        let position = segments[start].position;
+        let tick = utils::smallest_unit_to_tick(position, track.ppq, smallest_unit);
+        chunks.push(MmlChunk {
+            mml,
+            position_in_smallest_unit: position,
+            start_seconds: tempo_map.to_ms(tick) / 1000.,
+        });
+        start = end;
+    }
+
+    Ok(chunks)
+}
+
+fn get_segments(track: &MmlTrack) -> Vec<Segment> {
+    let smallest_unit = track.song_options.smallest_unit;
+    let bar_lengths = utils::get_bar_lengths_in_tick(&track.bridge_meta_events, track.ppq);
+    let get_bar = |position: usize| {
+        let tick = utils::smallest_unit_to_tick(position, track.ppq, smallest_unit);
+        utils::tick_to_bar(&bar_lengths, tick)
+    };
+
+    // Tracks without tempo commands follow the tempo of the song, from the first segment
+    // that starts at or after each tempo change
+    let meta_tempos = get_tempos(&track.bridge_meta_events);
+    let get_state = |state: &ChunkState, position: usize| {
+        let mut state = state.to_owned();
+        if track.omit_tempo {
+            let tick = utils::smallest_unit_to_tick(position, track.ppq, smallest_unit);
+            state.tempo = meta_tempos
+                .iter()
+                .take_while(|(tempo_tick, _)| *tempo_tick <= tick)
+                .last()
+                .map(|(_, tempo)| *tempo)
+                .unwrap_or(DEFAULT_TEMPO);
+        }
+        state
+    };
+
+    let mut state = ChunkState {
+        octave: DEFAULT_OCTAVE,
+        velocity: DEFAULT_VELOCITY,
+        tempo: DEFAULT_TEMPO,
+        note_length: None,
+    };
+    let mut segments: Vec<Segment> = Vec::new();
+    let mut segment = Segment {
+        tempo: String::new(),
+        mml: String::new(),
+        position: 0,
+        bar: 0,
+        state: get_state(&state, 0),
+    };
+    let mut position = 0usize;
+    let mut is_after_duration = false;
+
+    for event in track.events.iter() {
+        let is_chord_event = match event {
+            MmlEvent::ConnectChord => true,
+            MmlEvent::Note(note) => note.is_part_of_chord,
+            _ => false,
+        };
+
+        if is_after_duration && !is_chord_event && !segment.mml.is_empty() {
+            let next_state = get_state(&state, position);
+            let tempo = match next_state.tempo != segment.state.tempo && track.omit_tempo {
+                true => format!("t{}", next_state.tempo),
+                false => String::new(),
+            };
+            let next_segment = Segment {
+                tempo,
+                mml: String::new(),
+                position,
+                bar: get_bar(position),
+                state: next_state,
+            };
+            segments.push(std::mem::replace(&mut segment, next_segment));
+        }
+
+        segment.mml.push_str(&event.to_mml(smallest_unit));
+        state.update(event);
+
+        if !event.is_part_of_chord()
+            && let Some(duration) = event.get_duration()
+        {
+            position += duration;
+        }
+        is_after_duration = event.get_duration().is_some();
+    }
+
+    if !segment.mml.is_empty() {
+        segments.push(segment);
+    }
+
+    segments
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        Instrument, MmlError, MmlSong, MmlSongOptions, TempoPlacement,
+        syntax::{parse, timed_notes},
+        test_utils::MIDI_PATHS,
+    };
+
+    /// `(key, velocity, tempo, position, duration)` of every note played by the MML.
+    fn get_notes(mml: &str, offset: usize) -> Vec<(u8, u8, u32, usize, usize)> {
+        timed_notes(&parse(mml).nodes, 64)
+            .into_iter()
+            .filter_map(|note| {
+                note.midi_key.map(|key| {
+                    (
+                        key,
+                        note.velocity,
+                        note.tempo,
+                        note.position_in_smallest_unit + offset,
+                        note.duration_in_smallest_unit,
+                    )
+                })
+            })
+            .collect()
+    }
+
+    fn check_chunks(track: &MmlTrack, max_chars: usize) {
+        let chunks = track.to_mml_chunks(max_chars).unwrap();
+        let mut notes = Vec::new();
+
+        for chunk in chunks.iter() {
+            assert!(chunk.mml.len() <= max_chars, "{}", chunk.mml);
+            assert!(!chunk.mml.ends_with(':'), "{}", chunk.mml);
+            notes.extend(get_notes(&chunk.mml, chunk.position_in_smallest_unit));
+        }
+
+        assert_eq!(notes, get_notes(&track.to_mml(), 0));
+    }
+
+    #[test]
+    fn test_chunks_play_alone() {
+        let mml = "t150v10c4:e4:g4>d8v5e8r4<<b2t90a4:>c4d4e4l8f";
+        let song = MmlSong::from_mml(
+            vec![(String::from(mml), Instrument::default())],
+            MmlSongOptions::default(),
+        )
+        .unwrap();
+        let track = &song.tracks[0];
+
+        for max_chars in [26, 28, 32, 100] {
+            check_chunks(track, max_chars);
+        }
+
+        let chunks = track.to_mml_chunks(26).unwrap();
+        assert_eq!(chunks[0].position_in_smallest_unit, 0);
+        assert_eq!(chunks[0].start_seconds, 0.);
+        assert!(chunks.iter().all(|chunk| chunk.mml.starts_with('t')));
+        assert!(chunks[1].mml.starts_with("t150o"));
+        assert!(
+            chunks
+                .windows(2)
+                .all(|pair| pair[0].start_seconds < pair[1].start_seconds)
+        );
+
+        let chunks = track.to_mml_chunks(1000).unwrap();
+        assert_eq!(chunks.len(), 1);
+        assert_eq!(chunks[0].mml, format!("t120o4v12{}", track.to_mml()));
+
+        assert!(matches!(
+            track.to_mml_chunks(8),
+            Err(MmlError::Constraint(ConstraintError::ChunkTooShort {
+                max_chars: 8,
+                ..
+            }))
+        ));
+    }
+
+    #[test]
+    fn test_chunks_of_songs() {
+        for path in MIDI_PATHS {
+            let song = MmlSong::from_path(path, MmlSongOptions::default()).unwrap();
+            for track in song.tracks.iter() {
+                check_chunks(track, 500);
+            }
+        }
+    }
+
+    #[test]
+    fn test_chunks_prefer_bar_lines() {
+        // Four bars of 4/4, each chunk can hold more than a bar but not two
+        let mml = "c4d4e4f4g4a4b4>c4d4e4f4g4a4b4>c4d4";
+        let song = MmlSong::from_mml(
+            vec![(String::from(mml), Instrument::default())],
+            MmlSongOptions::default(),
+        )
+        .unwrap();
+        let chunks = song.tracks[0].to_mml_chunks(24).unwrap();
+        assert!(chunks.len() > 2);
+
+        for chunk in chunks.iter() {
+            assert_eq!(chunk.position_in_smallest_unit % 64, 0, "{chunk:?}");
+        }
+    }
+
+    #[test]
+    fn test_chunks_follow_the_song_tempo() {
+        let tracks = ["t150c4c4t90c4c4", "e4e4e4e4"]
+            .into_iter()
+            .map(|mml| (String::from(mml), Instrument::default()))
+            .collect();
+        let options = MmlSongOptions {
+            tempo_placement: TempoPlacement::FirstNonEmpty,
+            ..MmlSongOptions::default()
+        };
+        let song = MmlSong::from_mml(tracks, options).unwrap();
+        let track = &song.tracks[1];
+        assert!(track.omit_tempo);
+
+        for max_chars in [16, 18, 100] {
+            let tempos: Vec<u32> = track
+                .to_mml_chunks(max_chars)
+                .unwrap()
+                .iter()
+                .flat_map(|chunk| get_notes(&chunk.mml, chunk.position_in_smallest_unit))
+                .map(|(_, _, tempo, _, _)| tempo)
+                .collect();
+            assert_eq!(tempos, vec![150, 150, 90, 90], "{max_chars}");
+        }
+    }
+}
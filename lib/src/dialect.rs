@@ -0,0 +1,178 @@
+use crate::pitch_class::PitchClass;
+
+/// Describes how an MML flavour spells the events produced by the converter.
+///
+/// Every method has a default matching Revelation's syntax, so a dialect only
+/// needs to override what differs.
+pub trait MmlDialect: Send + Sync {
+    fn name(&self) -> &str;
+
+    /// Letter of the natural note (or rest) a pitch class is written with.
+    fn note_name(&self, pitch_class: &PitchClass) -> &str {
+        match pitch_class {
+            PitchClass::C | PitchClass::Db => "c",
+            PitchClass::D | PitchClass::Eb => "d",
+            PitchClass::E => "e",
+            PitchClass::F | PitchClass::Gb => "f",
+            PitchClass::G | PitchClass::Ab => "g",
+            PitchClass::A | PitchClass::Bb => "a",
+            PitchClass::B => "b",
+            PitchClass::Rest => "r",
+        }
+    }
+
+    /// Accidental appended to the note name of a black key.
+    fn sharp(&self) -> &str {
+        "+"
+    }
+
+    /// Symbol joining the notes of a chord.
+    /// `None` means the dialect has no chords, only the first note of each chord is written.
+    fn chord_connector(&self) -> Option<&str> {
+        Some(":")
+    }
+
+    fn tie(&self) -> &str {
+        "&"
+    }
+
+    /// `None` means the dialect has no dotted lengths, ties are used instead.
+    fn dot(&self) -> Option<&str> {
+        Some(".")
+    }
+
+    /// Note lengths the dialect accepts, e.g. `4` for a quarter note.
+    /// `None` accepts every length down to the smallest unit.
+    fn allowed_lengths(&self) -> Option<&[usize]> {
+        None
+    }
+
+    /// Written once before the first track.
+    fn header(&self) -> &str {
+        ""
+    }
+
+    /// Written between two tracks.
+    fn track_separator(&self) -> &str {
+        "\n"
+    }
+
+    /// Written once after the last track.
+    fn footer(&self) -> &str {
+        ""
+    }
+
+    /// `PitchClass::Db` => `c+` for Revelation
+    fn pitch(&self, pitch_class: &PitchClass) -> String {
+        let name = self.note_name(pitch_class);
+
+        match pitch_class {
+            PitchClass::Db | PitchClass::Eb | PitchClass::Gb | PitchClass::Ab | PitchClass::Bb => {
+                format!("{}{}", name, self.sharp())
+            }
+            _ => name.to_string(),
+        }
+    }
+
+    fn is_length_allowed(&self, length: usize) -> bool {
+        match self.allowed_lengths() {
+            Some(lengths) => lengths.contains(&length),
+            None => true,
+        }
+    }
+
+    fn format_song(&self, tracks: &[String]) -> String {
+        let mut song = String::from(self.header());
+        song.push_str(&tracks.join(self.track_separator()));
+        song.push_str(self.footer());
+        song
+    }
+}
+
+/// The syntax used by Revelation Mobile, this is the default output of the converter.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct RevelationDialect;
+
+impl MmlDialect for RevelationDialect {
+    fn name(&self) -> &str {
+        "Revelation"
+    }
+}
+
+/// Plain MML with `#` sharps and without chords.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct GenericDialect;
+
+impl MmlDialect for GenericDialect {
+    fn name(&self) -> &str {
+        "Generic"
+    }
+
+    fn sharp(&self) -> &str {
+        "#"
+    }
+
+    fn chord_connector(&self) -> Option<&str> {
+        None
+    }
+}
+
+/// Mabinogi's `MML@track1,track2,track3;` format.
+/// Chords are not supported inside a track and lengths are limited to 1-64.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct MabinogiDialect;
+
+impl MmlDialect for MabinogiDialect {
+    fn name(&self) -> &str {
+        "Mabinogi"
+    }
+
+    fn chord_connector(&self) -> Option<&str> {
+        None
+    }
+
+    fn allowed_lengths(&self) -> Option<&[usize]> {
+        Some(&[1, 2, 4, 8, 16, 32, 64])
+    }
+
+    fn header(&self) -> &str {
+        "MML@"
+    }
+
+    fn track_separator(&self) -> &str {
+        ","
+    }
+
+    fn footer(&self) -> &str {
+        ";"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pitch() {
+        assert_eq!(RevelationDialect.pitch(&PitchClass::C), "c");
+        assert_eq!(RevelationDialect.pitch(&PitchClass::Db), "c+");
+        assert_eq!(RevelationDialect.pitch(&PitchClass::Rest), "r");
+        assert_eq!(GenericDialect.pitch(&PitchClass::Bb), "a#");
+        assert_eq!(MabinogiDialect.pitch(&PitchClass::Gb), "f+");
+    }
+
+    #[test]
+    fn test_allowed_lengths() {
+        assert!(RevelationDialect.is_length_allowed(128));
+        assert!(MabinogiDialect.is_length_allowed(64));
+        assert!(!MabinogiDialect.is_length_allowed(128));
+    }
+
+    #[test]
+    fn test_format_song() {
+        let tracks = vec![String::from("c4"), String::from("e4")];
+
+        assert_eq!(RevelationDialect.format_song(&tracks), "c4\ne4");
+        assert_eq!(MabinogiDialect.format_song(&tracks), "MML@c4,e4;");
+    }
+}
@@ -0,0 +1,143 @@
+use std::fmt::Display;
+
+use thiserror::Error;
+
+use crate::syntax::ParseError;
+
+pub type Result<T> = std::result::Result<T, MmlError>;
+
+/// Errors of the library, with the values that caused them so callers can tell them apart.
+#[derive(Debug, Error)]
+pub enum MmlError {
+    #[error("Cannot read the file: {0}")]
+    Io(#[from] std::io::Error),
+
+    /// The bytes are not a MIDI file
+    #[error("Invalid MIDI: {0}")]
+    Midi(#[from] midly::Error),
+
+    /// Syntax errors of every MML track
+    #[error("Invalid MML\n{}", join_lines(.0))]
+    Parse(Vec<TrackParseError>),
+
+    /// A project file or an export that cannot be read or written
+    #[error("Invalid JSON: {0}")]
+    Project(#[from] serde_json::Error),
+
+    /// A tempo the song cannot be played at
+    #[error("Invalid tempo {tempo} at tick {tick}")]
+    Timing { tick: usize, tempo: u32 },
+
+    #[error("Cannot get track by index {index}, the song has {track_count} tracks")]
+    Index { index: usize, track_count: usize },
+
+    /// An option or an argument out of its range, `name` is the name of the field
+    #[error("Invalid {name} {value}, {reason}")]
+    Options {
+        name: &'static str,
+        value: String,
+        reason: &'static str,
+    },
+
+    #[error(transparent)]
+    Constraint(#[from] ConstraintError),
+
+    /// An operation of a project cannot be applied to the song again
+    #[error("Cannot replay operation {index}: {source}")]
+    Replay { index: usize, source: Box<MmlError> },
+}
+
+/// An operation the song cannot do with the given tracks or project.
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum ConstraintError {
+    #[error("Cannot equalize track {index} with itself")]
+    SameTrack { index: usize },
+
+    #[error("At least two tracks are needed to balance, {count} given")]
+    NotEnoughTracks { count: usize },
+
+    #[error("Track {index} is selected more than once")]
+    DuplicateTrack { index: usize },
+
+    /// Two MML tracks disagree on a meta event at the same tick, like a tempo
+    #[error("Tracks {other_index} and {index} have a different {kind} at tick {tick}")]
+    ConflictingMeta {
+        kind: &'static str,
+        tick: usize,
+        index: usize,
+        other_index: usize,
+    },
+
+    /// `MmlTrack::to_mml_chunks` cannot fit a note with the state it needs in a chunk
+    #[error("Chunks of {max_chars} characters are too short, {needed} are needed")]
+    ChunkTooShort { max_chars: usize, needed: usize },
+
+    /// Options read back with custom passes, see `PassPipeline::unrestored_passes`
+    #[error("Custom passes {} were saved but cannot be restored", names.join(", "))]
+    UnrestoredPasses { names: Vec<String> },
+
+    #[error("The MIDI file does not match the project")]
+    SourceMismatch,
+
+    #[error("Project version {version} is newer than the supported version {supported}")]
+    UnsupportedVersion { version: u32, supported: u32 },
+}
+
+/// A syntax error in the MML of the track at `index`.
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+#[error("Track {index}: {error}")]
+pub struct TrackParseError {
+    pub index: usize,
+    pub error: ParseError,
+}
+
+impl MmlError {
+    /// `Index` error when `index` is not below `track_count`.
+    pub(crate) fn check_index(index: usize, track_count: usize) -> Result<()> {
+        if index >= track_count {
+            return Err(Self::Index { index, track_count });
+        }
+
+        Ok(())
+    }
+}
+
+fn join_lines(items: &[impl Display]) -> String {
+    items
+        .iter()
+        .map(|item| item.to_string())
+        .collect::<Vec<String>>()
+        .join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::syntax::parse;
+
+    #[test]
+    fn test_error_messages() {
+        let errors = parse("c4 x")
+            .errors
+            .into_iter()
+            .map(|error| TrackParseError { index: 1, error })
+            .collect();
+
+        assert_eq!(
+            MmlError::Parse(errors).to_string(),
+            "Invalid MML\nTrack 1: Unknown command `x` at 3..4"
+        );
+        assert_eq!(
+            MmlError::from(ConstraintError::SameTrack { index: 2 }).to_string(),
+            "Cannot equalize track 2 with itself"
+        );
+        assert!(matches!(
+            MmlError::check_index(3, 3),
+            Err(MmlError::Index {
+                index: 3,
+                track_count: 3
+            })
+        ));
+        assert!(MmlError::check_index(2, 3).is_ok());
+    }
+}
@@ -0,0 +1,208 @@
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    Instrument, MmlSongOptions, MmlTrack,
+    mml_event::MmlEvent,
+    syntax::DEFAULT_VELOCITY,
+    tempo::{TempoMap, get_tempos},
+    utils,
+};
+
+/// Version of the export format, raised when a field changes meaning or is removed.
+pub const EXPORT_VERSION: u32 = 1;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ExportOptions {
+    /// Adds the position, duration and MIDI key of every MML note
+    pub include_notes: bool,
+}
+
+/// A converted song for other tools, see `MmlSong::to_json`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SongExport {
+    pub version: u32,
+    pub ppq: u16,
+    pub options: MmlSongOptions,
+
+    /// Until the end of the longest track
+    pub duration_seconds: f64,
+    pub tracks: Vec<TrackExport>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TrackExport {
+    pub name: String,
+    pub instrument: Instrument,
+    pub mml: String,
+
+    /// Characters of `mml`
+    pub char_count: usize,
+
+    /// MML notes, chord notes included
+    pub note_count: usize,
+
+    /// Until the end of the MML
+    pub duration_seconds: f64,
+
+    /// Song options with the overrides of the track applied
+    pub options: MmlSongOptions,
+
+    /// Only with `ExportOptions::include_notes`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub notes: Option<Vec<NoteExport>>,
+}
+
+/// A note as it plays in the MML, which may differ from the MIDI note it comes from.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct NoteExport {
+    /// MIDI key
+    pub key: u8,
+
+    /// MML velocity, 0-15
+    pub velocity: u8,
+    pub position_in_smallest_unit: usize,
+    pub duration_in_smallest_unit: usize,
+    pub start_seconds: f64,
+    pub duration_seconds: f64,
+    pub is_part_of_chord: bool,
+}
+
+pub(crate) fn export_track(track: &MmlTrack, options: &ExportOptions) -> TrackExport {
+    let mml = track.to_mml();
+    let smallest_unit = track.song_options.smallest_unit;
+    let tempo_map = TempoMap::new(get_tempos(&track.bridge_meta_events), track.ppq as f64);
+    let to_seconds = |position: usize| {
+        let tick = utils::smallest_unit_to_tick(position, track.ppq, smallest_unit);
+        tempo_map.to_ms(tick) / 1000.
+    };
+
+    let mut notes: Vec<NoteExport> = Vec::new();
+    let mut position = 0usize;
+    let mut note_position = 0usize;
+    let mut velocity = DEFAULT_VELOCITY;
+
+    for event in track.events.iter() {
+        match event {
+            MmlEvent::Velocity(value) => velocity = *value,
+            MmlEvent::Note(note) => {
+                // Chord notes start with the note before them
+                if !note.is_part_of_chord {
+                    note_position = position;
+                }
+
+                let start_seconds = to_seconds(note_position);
+                notes.push(NoteExport {
+                    key: note.midi_state.key,
+                    velocity,
+                    position_in_smallest_unit: note_position,
+                    duration_in_smallest_unit: note.duration_in_smallest_unit,
+                    start_seconds,
+                    duration_seconds: to_seconds(note_position + note.duration_in_smallest_unit)
+                        - start_seconds,
+                    is_part_of_chord: note.is_part_of_chord,
+                });
+            }
+            _ => (),
+        }
+
+        if !event.is_part_of_chord()
+            && let Some(duration) = event.get_duration()
+        {
+            position += duration;
+        }
+    }
+
+    TrackExport {
+        name: track.name.to_owned(),
+        instrument: track.instrument.to_owned(),
+        char_count: mml.chars().count(),
+        mml,
+        note_count: notes.len(),
+        duration_seconds: to_seconds(position),
+        options: track.song_options.to_owned(),
+        notes: options.include_notes.then_some(notes),
+    }
+}
+
+pub(crate) fn export_song(
+    tracks: &[MmlTrack],
+    song_options: &MmlSongOptions,
+    ppq: u16,
+    options: &ExportOptions,
+) -> SongExport {
+    let tracks: Vec<TrackExport> = tracks
+        .iter()
+        .map(|track| export_track(track, options))
+        .collect();
+
+    SongExport {
+        version: EXPORT_VERSION,
+        ppq,
+        options: song_options.to_owned(),
+        duration_seconds: tracks
+            .iter()
+            .map(|track| track.duration_seconds)
+            .fold(0., f64::max),
+        tracks,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{MmlSong, test_utils::MIDI_PATHS};
+
+    #[test]
+    fn test_export_notes() {
+        let tracks = vec![(String::from("t150v10c4:e4r8<v5c8"), Instrument::default())];
+        let song = MmlSong::from_mml(tracks, MmlSongOptions::default()).unwrap();
+        let export = song.export(&ExportOptions {
+            include_notes: true,
+        });
+
+        let track = &export.tracks[0];
+        let notes = track.notes.as_ref().unwrap();
+        assert_eq!(track.note_count, 3);
+        assert_eq!(
+            notes
+                .iter()
+                .map(|note| (note.key, note.velocity, note.position_in_smallest_unit))
+                .collect::<Vec<_>>(),
+            vec![(60, 10, 0), (64, 10, 0), (48, 5, 24)]
+        );
+        assert!(notes[1].is_part_of_chord);
+
+        // A quarter note at 150 BPM
+        assert!((notes[0].duration_seconds - 0.4).abs() < 1e-9);
+        assert!((notes[2].start_seconds - 0.6).abs() < 1e-9);
+        assert!((track.duration_seconds - 0.8).abs() < 1e-9);
+        assert_eq!(export.duration_seconds, track.duration_seconds);
+    }
+
+    #[test]
+    fn test_export_song() {
+        for path in MIDI_PATHS {
+            let song = MmlSong::from_path(path, MmlSongOptions::default()).unwrap();
+            let export = song.export(&ExportOptions::default());
+
+            assert_eq!(export.version, EXPORT_VERSION);
+            assert_eq!(export.tracks.len(), song.tracks.len());
+            for (track, track_export) in song.tracks.iter().zip(export.tracks.iter()) {
+                assert_eq!(track_export.mml, track.to_mml());
+                assert_eq!(track_export.char_count, track_export.mml.len());
+                assert!(track_export.notes.is_none());
+            }
+
+            let json = song.to_json(&ExportOptions::default()).unwrap();
+            assert!(!json.contains("\"notes\""));
+            let parsed: SongExport = serde_json::from_str(&json).unwrap();
+            assert_eq!(parsed.options, export.options);
+            assert!((parsed.duration_seconds - export.duration_seconds).abs() < 1e-9);
+            for (parsed_track, track_export) in parsed.tracks.iter().zip(export.tracks.iter()) {
+                assert_eq!(parsed_track.mml, track_export.mml);
+                assert_eq!(parsed_track.note_count, track_export.note_count);
+            }
+        }
+    }
+}
@@ -0,0 +1,217 @@
+use std::sync::Arc;
+
+use rayon::prelude::*;
+
+use crate::{
+    BridgeEvent, Instrument, MmlSongOptions, MmlTrack, TrackOptionsOverride, project::SongOperation,
+};
+
+/// Number of operations that can be undone, older ones are forgotten.
+pub const MAX_HISTORY_LENGTH: usize = 100;
+
+/// Song state replaced by an operation.
+#[derive(Debug, Clone)]
+pub(crate) struct SongChange {
+    /// Tracks before the operation with their index in the song before the operation.
+    /// For a merge both tracks are kept, so undoing puts every note back in its own track.
+    pub before: Vec<(usize, TrackSnapshot)>,
+
+    /// Index of every track the operation created or changed, in the song after the operation
+    pub after: Vec<usize>,
+
+    /// Options before the operation, only when they changed
+    pub options: Option<MmlSongOptions>,
+
+    /// Velocity boost before the operation
+    pub velocity_diff: Option<u8>,
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct HistoryEntry {
+    pub operation: SongOperation,
+    pub change: SongChange,
+}
+
+#[derive(Debug, Clone, Default)]
+pub(crate) struct History {
+    pub undo: Vec<HistoryEntry>,
+    pub redo: Vec<HistoryEntry>,
+}
+
+impl History {
+    pub fn push(&mut self, entry: HistoryEntry) {
+        if self.undo.len() == MAX_HISTORY_LENGTH {
+            self.undo.remove(0);
+        }
+        self.undo.push(entry);
+        self.redo.clear();
+    }
+}
+
+/// What a track is generated from, the MML events are generated again on undo.
+/// The meta events are shared with the song.
+#[derive(Debug, Clone)]
+pub(crate) struct TrackSnapshot {
+    pub name: String,
+    pub instrument: Instrument,
+    pub song_options: MmlSongOptions,
+    pub options_override: TrackOptionsOverride,
+    pub omit_tempo: bool,
+    pub bridge_meta_events: Arc<Vec<BridgeEvent>>,
+    pub bridge_note_events: Vec<BridgeEvent>,
+    pub ppq: u16,
+}
+
+impl TrackSnapshot {
+    pub fn new(track: &MmlTrack) -> Self {
+        Self {
+            name: track.name.to_owned(),
+            instrument: track.instrument.to_owned(),
+            song_options: track.song_options.to_owned(),
+            options_override: track.options_override.to_owned(),
+            omit_tempo: track.omit_tempo,
+            bridge_meta_events: Arc::clone(&track.bridge_meta_events),
+            bridge_note_events: track.bridge_note_events.to_owned(),
+            ppq: track.ppq,
+        }
+    }
+}
+
+impl SongChange {
+    /// Puts the changed tracks back without their velocity boost,
+    /// `tracks` must be the song right after the operation.
+    pub fn revert(&self, tracks: &mut Vec<MmlTrack>) {
+        let mut after = self.after.to_owned();
+        after.sort_unstable();
+        after.dedup();
+
+        for index in after.into_iter().rev() {
+            tracks.remove(index);
+        }
+
+        let restored: Vec<MmlTrack> = self
+            .before
+            .par_iter()
+            .map(|(_, snapshot)| MmlTrack::from_snapshot(snapshot))
+            .collect();
+        for ((index, _), track) in self.before.iter().zip(restored) {
+            tracks.insert(*index, track);
+        }
+    }
+}
+
+/// Sorted snapshots of the tracks at `indexes`, missing indexes are skipped.
+pub(crate) fn snapshot_tracks(
+    tracks: &[MmlTrack],
+    indexes: &[usize],
+) -> Vec<(usize, TrackSnapshot)> {
+    let mut indexes = indexes.to_owned();
+    indexes.sort_unstable();
+    indexes.dedup();
+
+    indexes
+        .into_iter()
+        .filter_map(|index| {
+            tracks
+                .get(index)
+                .map(|track| (index, TrackSnapshot::new(track)))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{create_track, note, tempo};
+
+    fn track(name: &str) -> MmlTrack {
+        MmlTrack::from_bridge_events(
+            name.to_string(),
+            Vec::new(),
+            Vec::new(),
+            MmlSongOptions::default(),
+            480,
+        )
+    }
+
+    fn names(tracks: &[MmlTrack]) -> Vec<&str> {
+        tracks.iter().map(|track| track.name.as_str()).collect()
+    }
+
+    #[test]
+    fn test_revert_merge() {
+        let tracks = vec![track("a"), track("b"), track("c"), track("d")];
+        let change = SongChange {
+            before: snapshot_tracks(&tracks, &[3, 1]),
+            after: vec![2],
+            options: None,
+            velocity_diff: None,
+        };
+
+        // Track 3 merged into track 1
+        let mut merged = vec![track("a"), track("c"), track("bd")];
+        change.revert(&mut merged);
+
+        assert_eq!(names(&merged), vec!["a", "b", "c", "d"]);
+    }
+
+    #[test]
+    fn test_revert_split() {
+        let tracks = vec![track("a"), track("b")];
+        let change = SongChange {
+            before: snapshot_tracks(&tracks, &[0]),
+            after: vec![0, 1],
+            options: None,
+            velocity_diff: None,
+        };
+
+        let mut split = vec![track("a1"), track("a2"), track("b")];
+        change.revert(&mut split);
+
+        assert_eq!(names(&split), vec!["a", "b"]);
+    }
+
+    #[test]
+    fn test_snapshot_generates_the_track() {
+        let options = MmlSongOptions::default();
+        let mut track = create_track(
+            vec![tempo(90, 0)],
+            vec![note(60, 0, 480), note(64, 0, 480), note(67, 480, 960)],
+            &options,
+        );
+        track.name = String::from("Lead");
+        track.instrument = Instrument::new(40, 2);
+        track.omit_tempo = true;
+        track.generate_mml_events();
+
+        let restored = MmlTrack::from_snapshot(&TrackSnapshot::new(&track));
+        assert_eq!(restored.to_mml(), track.to_mml());
+        assert_eq!(restored.instrument, track.instrument);
+        assert!(Arc::ptr_eq(
+            &restored.bridge_meta_events,
+            &track.bridge_meta_events
+        ));
+    }
+
+    #[test]
+    fn test_history_length() {
+        let mut history = History::default();
+        for index in 0..MAX_HISTORY_LENGTH + 5 {
+            history.push(HistoryEntry {
+                operation: SongOperation::SplitTrack { index },
+                change: SongChange {
+                    before: Vec::new(),
+                    after: Vec::new(),
+                    options: None,
+                    velocity_diff: None,
+                },
+            });
+        }
+
+        assert_eq!(history.undo.len(), MAX_HISTORY_LENGTH);
+        assert_eq!(
+            history.undo.first().map(|entry| &entry.operation),
+            Some(&SongOperation::SplitTrack { index: 5 })
+        );
+    }
+}
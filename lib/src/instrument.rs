@@ -1,6 +1,8 @@
+use serde::{Deserialize, Serialize};
+
 use crate::instrument_map::INSTRUMENT_MAP;
 
-#[derive(Clone, Debug, PartialEq, Eq)]
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
 pub struct Instrument {
     pub name: String,
     pub instrument_id: u8,
@@ -15,6 +17,11 @@ impl Instrument {
             name: match_instrument_name(instrument_id, midi_channel),
         }
     }
+
+    /// Channel 10 is the percussion channel of General MIDI.
+    pub fn is_drum(&self) -> bool {
+        self.midi_channel == 9
+    }
 }
 
 impl Default for Instrument {
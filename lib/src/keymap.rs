@@ -0,0 +1,302 @@
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::Instrument;
+
+/// Pitch classes of a scale, as semitones above its root.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Scale {
+    Major,
+    Minor,
+    MajorPentatonic,
+    MinorPentatonic,
+
+    /// Semitones above the root, from 0 to 11
+    Custom(Vec<u8>),
+}
+
+impl Scale {
+    pub fn intervals(&self) -> Vec<u8> {
+        match self {
+            Self::Major => vec![0, 2, 4, 5, 7, 9, 11],
+            Self::Minor => vec![0, 2, 3, 5, 7, 8, 10],
+            Self::MajorPentatonic => vec![0, 2, 4, 7, 9],
+            Self::MinorPentatonic => vec![0, 3, 5, 7, 10],
+            Self::Custom(intervals) => intervals.iter().map(|interval| interval % 12).collect(),
+        }
+    }
+}
+
+/// Keys the basic pieces of a drum kit are mapped to, General MIDI by default.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DrumKit {
+    pub kick: u8,
+    pub snare: u8,
+    pub closed_hi_hat: u8,
+    pub open_hi_hat: u8,
+    pub low_tom: u8,
+    pub mid_tom: u8,
+    pub high_tom: u8,
+    pub crash: u8,
+    pub ride: u8,
+}
+
+impl Default for DrumKit {
+    fn default() -> Self {
+        Self {
+            kick: 36,
+            snare: 38,
+            closed_hi_hat: 42,
+            open_hi_hat: 46,
+            low_tom: 45,
+            mid_tom: 47,
+            high_tom: 50,
+            crash: 49,
+            ride: 51,
+        }
+    }
+}
+
+impl DrumKit {
+    /// The kit piece closest to a General MIDI percussion key, `None` outside of 35-82.
+    /// 35-81 are the General MIDI 1 keys, 82 is the shaker of General MIDI 2.
+    fn get_piece(&self, key: u8) -> Option<u8> {
+        let piece = match key {
+            35 | 36 => self.kick,
+            37..=40 => self.snare,
+            42 | 44 | 54 | 69 | 70 | 82 => self.closed_hi_hat,
+            46 | 58 => self.open_hi_hat,
+            41 | 43 | 45 | 64 | 66 | 68 | 74 | 78 => self.low_tom,
+            47 | 48 | 63 | 65 | 73 | 77 => self.mid_tom,
+            50 | 60..=62 | 67 | 71 | 72 | 75 | 76 | 79 => self.high_tom,
+            49 | 52 | 55 | 57 => self.crash,
+            51 | 53 | 56 | 59 | 80 | 81 => self.ride,
+            _ => return None,
+        };
+
+        Some(piece)
+    }
+}
+
+/// A keymap described by how it is generated, so it can be saved and applied again.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum KeymapPreset {
+    /// Moves every note outside of the scale to the nearest note of the scale, down on a tie.
+    /// `root` is a pitch class, 0 for C.
+    SnapToScale { root: u8, scale: Scale },
+
+    /// Moves notes outside of the range by whole octaves until they fit.
+    FoldIntoRange { lowest: u8, highest: u8 },
+
+    /// Inverts the intervals around the pivot key, notes going out of 0-127 are folded back.
+    Mirror { pivot: u8 },
+
+    /// Maps the General MIDI percussion keys to the pieces of a basic kit.
+    GmDrums { kit: DrumKit },
+
+    /// `(from, to)` pairs
+    Custom { keymap: Vec<(u8, u8)> },
+}
+
+impl KeymapPreset {
+    /// Every key the preset changes, mapped to its new key.
+    pub fn to_keymap(&self) -> HashMap<u8, u8> {
+        if let Self::Custom { keymap } = self {
+            return keymap.iter().copied().collect();
+        }
+
+        (0..=127u8)
+            .filter_map(|key| {
+                let new_key = match self {
+                    Self::SnapToScale { root, scale } => snap_to_scale(key, *root, scale),
+                    Self::FoldIntoRange { lowest, highest } => {
+                        fold_into_range(key as i16, *lowest, *highest)
+                    }
+                    Self::Mirror { pivot } => {
+                        fold_into_range(2 * *pivot as i16 - key as i16, 0, 127)
+                    }
+                    Self::GmDrums { kit } => kit.get_piece(key).unwrap_or(key),
+                    Self::Custom { .. } => key,
+                };
+
+                (new_key != key).then_some((key, new_key))
+            })
+            .collect()
+    }
+
+    /// Whether the preset is meant for the instrument when applied to the whole song.
+    /// Drum keys are not pitches, so only drum presets and custom keymaps change drum tracks.
+    pub fn applies_to(&self, instrument: &Instrument) -> bool {
+        match self {
+            Self::GmDrums { .. } => instrument.is_drum(),
+            Self::Custom { .. } => true,
+            _ => !instrument.is_drum(),
+        }
+    }
+}
+
+fn snap_to_scale(key: u8, root: u8, scale: &Scale) -> u8 {
+    let intervals = scale.intervals();
+    if intervals.is_empty() {
+        return key;
+    }
+
+    let is_in_scale = |key: i16| {
+        let interval = (key - root as i16).rem_euclid(12) as u8;
+        intervals.contains(&interval)
+    };
+
+    let key = key as i16;
+    for distance in 0..12i16 {
+        for candidate in [key - distance, key + distance] {
+            if (0..=127).contains(&candidate) && is_in_scale(candidate) {
+                return candidate as u8;
+            }
+        }
+    }
+
+    key as u8
+}
+
+fn fold_into_range(key: i16, lowest: u8, highest: u8) -> u8 {
+    let (lowest, highest) = (lowest.min(highest).min(127), highest.max(lowest).min(127));
+
+    // A range under an octave cannot hold every pitch class, clamp instead
+    if highest - lowest < 11 {
+        return key.clamp(lowest as i16, highest as i16) as u8;
+    }
+
+    let mut key = key;
+    while key < lowest as i16 {
+        key += 12;
+    }
+    while key > highest as i16 {
+        key -= 12;
+    }
+
+    key as u8
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_snap_to_scale() {
+        let keymap = KeymapPreset::SnapToScale {
+            root: 0,
+            scale: Scale::Major,
+        }
+        .to_keymap();
+
+        // C# and D# go down, F# goes down to F
+        assert_eq!(keymap.get(&61), Some(&60));
+        assert_eq!(keymap.get(&63), Some(&62));
+        assert_eq!(keymap.get(&66), Some(&65));
+        assert_eq!(keymap.get(&60), None);
+        assert_eq!(keymap.len(), 53);
+    }
+
+    #[test]
+    fn test_snap_to_custom_scale() {
+        let keymap = KeymapPreset::SnapToScale {
+            root: 2,
+            scale: Scale::Custom(vec![0, 7]),
+        }
+        .to_keymap();
+
+        // D and A only
+        assert_eq!(keymap.get(&60), Some(&62));
+        assert_eq!(keymap.get(&64), Some(&62));
+        assert_eq!(keymap.get(&66), Some(&69));
+        assert_eq!(keymap.get(&69), None);
+    }
+
+    #[test]
+    fn test_fold_into_range() {
+        let keymap = KeymapPreset::FoldIntoRange {
+            lowest: 48,
+            highest: 72,
+        }
+        .to_keymap();
+
+        assert_eq!(keymap.get(&36), Some(&48));
+        assert_eq!(keymap.get(&47), Some(&59));
+        assert_eq!(keymap.get(&85), Some(&61));
+        assert_eq!(keymap.get(&72), None);
+        assert_eq!(fold_into_range(80, 60, 65), 65);
+        assert_eq!(fold_into_range(-7, 0, 127), 5);
+    }
+
+    #[test]
+    fn test_mirror() {
+        let keymap = KeymapPreset::Mirror { pivot: 60 }.to_keymap();
+
+        assert_eq!(keymap.get(&64), Some(&56));
+        assert_eq!(keymap.get(&55), Some(&65));
+        assert_eq!(keymap.get(&60), None);
+        // 2 * 60 - 0 = 120, 2 * 60 - 127 = -7 folded up by an octave
+        assert_eq!(keymap.get(&0), Some(&120));
+        assert_eq!(keymap.get(&127), Some(&5));
+    }
+
+    #[test]
+    fn test_gm_drums() {
+        let kit = DrumKit {
+            kick: 24,
+            ..DrumKit::default()
+        };
+        let keymap = KeymapPreset::GmDrums { kit }.to_keymap();
+
+        assert_eq!(keymap.get(&35), Some(&24));
+        assert_eq!(keymap.get(&40), Some(&38));
+        assert_eq!(keymap.get(&57), Some(&49));
+        assert_eq!(keymap.get(&38), None);
+        assert_eq!(keymap.get(&20), None);
+        assert_eq!(keymap.get(&82), Some(&42));
+        assert_eq!(keymap.get(&83), None);
+    }
+
+    #[test]
+    fn test_applies_to() {
+        let piano = Instrument::new(0, 0);
+        let drums = Instrument::new(0, 9);
+        let drum_preset = KeymapPreset::GmDrums {
+            kit: DrumKit::default(),
+        };
+        let fold = KeymapPreset::FoldIntoRange {
+            lowest: 48,
+            highest: 72,
+        };
+
+        assert!(drum_preset.applies_to(&drums));
+        assert!(!drum_preset.applies_to(&piano));
+        assert!(fold.applies_to(&piano));
+        assert!(!fold.applies_to(&drums));
+    }
+
+    #[test]
+    fn test_preset_json() {
+        let presets = vec![
+            KeymapPreset::SnapToScale {
+                root: 9,
+                scale: Scale::Custom(vec![0, 3, 7]),
+            },
+            KeymapPreset::GmDrums {
+                kit: DrumKit::default(),
+            },
+            KeymapPreset::Custom {
+                keymap: vec![(60, 62)],
+            },
+        ];
+
+        let json = serde_json::to_string(&presets).unwrap();
+        let deserialized: Vec<KeymapPreset> = serde_json::from_str(&json).unwrap();
+
+        assert_eq!(deserialized, presets);
+    }
+}
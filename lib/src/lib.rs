@@ -1,20 +1,52 @@
+mod analysis;
+mod chunk;
+mod dialect;
+mod error;
+mod export;
+mod history;
 mod instrument;
 mod instrument_map;
+mod keymap;
 mod mml_event;
 mod mml_note;
 mod mml_song;
 mod mml_track;
 mod parser;
+mod pass;
 mod pitch_class;
+mod pretty;
+mod project;
+mod song_options;
+mod tempo;
+mod velocity_curve;
+mod verification;
 
 #[cfg(test)]
 mod test_utils;
 
+pub mod syntax;
 pub mod utils;
 
+pub use analysis::{QuantizationSummary, SongAnalysis, TempoSummary, TrackAnalysis};
+pub use chunk::MmlChunk;
+pub use dialect::{GenericDialect, MabinogiDialect, MmlDialect, RevelationDialect};
+pub use error::{ConstraintError, MmlError, TrackParseError};
+pub use export::{EXPORT_VERSION, ExportOptions, NoteExport, SongExport, TrackExport};
+pub use history::MAX_HISTORY_LENGTH;
 pub use instrument::Instrument;
+pub use keymap::{DrumKit, KeymapPreset, Scale};
 pub use mml_event::{BridgeEvent, MidiNoteState, MidiState, MmlEvent};
 pub use mml_note::MmlNote;
-pub use mml_song::{MmlSong, MmlSongOptions};
+pub use mml_song::{MmlSong, MmlSongOptions, TrackOptionsOverride};
 pub use mml_track::MmlTrack;
+pub use pass::{
+    BridgePass, FixEventsPosition, MmlPass, NormalizeEvents, PassContext, PassPipeline,
+    UpdateChordDuration, UpdateNoteMml, UpdateVelocities,
+};
 pub use pitch_class::PitchClass;
+pub use pretty::{PrettyMmlOptions, compact_pretty_mml};
+pub use project::{MmlProject, PROJECT_VERSION, SongOperation};
+pub use song_options::{MAX_MML_VELOCITY, MmlSongOptionsBuilder, OptionsPreset};
+pub use tempo::{TempoPlacement, TempoReport, TempoTransform};
+pub use velocity_curve::VelocityCurve;
+pub use verification::{BarVerification, NoteOffset, TrackVerification, VerificationOptions};
@@ -1,11 +1,12 @@
-use crate::{Instrument, mml_note::MmlNote, pitch_class::PitchClass, utils};
+use crate::{Instrument, dialect::MmlDialect, mml_note::MmlNote, pitch_class::PitchClass, utils};
+use serde::{Deserialize, Serialize};
 use std::cmp::Ordering;
 
 // --------------------------------
 // Midi state
 // --------------------------------
 
-#[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct MidiState {
     pub position_in_tick: usize,
     pub duration_in_tick: usize,
@@ -24,7 +25,7 @@ impl PartialOrd for MidiState {
     }
 }
 
-#[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct MidiNoteState {
     pub key: u8,
     pub velocity: u8,
@@ -47,11 +48,14 @@ impl PartialOrd for MidiNoteState {
 // Bridge
 // --------------------------------
 
-#[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub enum BridgeEvent {
     Note(MidiNoteState),
     Tempo(u32, MidiState),
     ProgramChange(Instrument, MidiState),
+
+    /// (numerator, denominator, state), 6/8 => (6, 8, state)
+    TimeSignature(u8, u8, MidiState),
 }
 
 impl Ord for BridgeEvent {
@@ -60,12 +64,14 @@ impl Ord for BridgeEvent {
             Self::Note(state) => state.midi_state.position_in_tick,
             Self::Tempo(_, pos) => pos.position_in_tick,
             Self::ProgramChange(_, pos) => pos.position_in_tick,
+            Self::TimeSignature(_, _, pos) => pos.position_in_tick,
         };
 
         let other_position = match other {
             Self::Note(state) => state.midi_state.position_in_tick,
             Self::Tempo(_, pos) => pos.position_in_tick,
             Self::ProgramChange(_, pos) => pos.position_in_tick,
+            Self::TimeSignature(_, _, pos) => pos.position_in_tick,
         };
 
         let order = self_position.cmp(&other_position);
@@ -97,7 +103,7 @@ impl PartialOrd for BridgeEvent {
 // MML
 // --------------------------------
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum MmlEvent {
     Note(MmlNote),
     Rest(usize),
@@ -130,30 +136,32 @@ impl MmlEvent {
         }
     }
 
-    // TODO: Whitespace might cause errors in the game
-    // pub fn to_mml_debug(&self, smallest_unit: usize) -> String {
-    //     match self {
-    //         Self::ConnectChord => String::from(":"),
-    //         Self::IncreOctave => String::from(">"),
-    //         Self::DecreOctave => String::from("<"),
-    //         Self::Tempo(tempo) => format!("\nt{tempo}\n"),
-    //         Self::Octave(octave) => format!(" o{octave} "),
-    //         Self::Note(note) => {
-    //             format!(
-    //                 "{} ",
-    //                 utils::get_display_mml(note.duration_in_smallest_unit, &note.pitch_class, smallest_unit)
-    //             )
-    //         }
-    //         Self::Rest(rest) => {
-    //             format!(
-    //                 "{} ",
-    //                 utils::get_display_mml(rest.to_owned().into(), &PitchClass::Rest, smallest_unit)
-    //             )
-    //         },
-    //         Self::Velocity(vel) => format!(" v{} ", vel),
-    //         Self::NoteLength(length) => format!(" l{} ", length),
-    //     }
-    // }
+    /// Same as `to_mml` but spelled in the given dialect.
+    /// Chord notes are omitted when the dialect has no chord connector.
+    pub fn to_mml_with_dialect(&self, smallest_unit: usize, dialect: &dyn MmlDialect) -> String {
+        match self {
+            Self::ConnectChord => dialect.chord_connector().unwrap_or_default().to_string(),
+            Self::Note(note) => {
+                if note.is_part_of_chord && dialect.chord_connector().is_none() {
+                    return String::new();
+                }
+
+                utils::get_display_mml_with_dialect(
+                    note.duration_in_smallest_unit,
+                    &note.pitch_class,
+                    smallest_unit,
+                    dialect,
+                )
+            }
+            Self::Rest(rest) => utils::get_display_mml_with_dialect(
+                rest.to_owned(),
+                &PitchClass::Rest,
+                smallest_unit,
+                dialect,
+            ),
+            _ => self.to_mml(smallest_unit),
+        }
+    }
 
     /// Get duration in smallest unit
     pub fn get_duration(&self) -> Option<usize> {
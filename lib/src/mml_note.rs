@@ -1,6 +1,8 @@
+use serde::{Deserialize, Serialize};
+
 use crate::{mml_event::MidiNoteState, mml_song::MmlSongOptions, pitch_class::PitchClass, utils};
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct MmlNote {
     pub midi_state: MidiNoteState,
     pub pitch_class: PitchClass,
@@ -25,11 +27,7 @@ impl MmlNote {
             utils::midi_key_to_octave(midi_state.key),
         );
 
-        let velocity = utils::midi_velocity_to_mml_velocity(
-            midi_state.velocity,
-            options.velocity_min,
-            options.velocity_max,
-        );
+        let velocity = get_mml_velocity(&midi_state, options);
 
         let position_in_smallest_unit = utils::tick_to_smallest_unit(
             midi_state.midi_state.position_in_tick,
@@ -56,6 +54,11 @@ impl MmlNote {
         }
     }
 
+    /// Converts the MIDI velocity again, with other velocity options.
+    pub fn update_velocity(&mut self, options: &MmlSongOptions) {
+        self.velocity = get_mml_velocity(&self.midi_state, options);
+    }
+
     pub fn update_mml_string(&mut self, smallest_unit: usize) {
         self.mml_string = utils::get_display_mml(
             self.duration_in_smallest_unit,
@@ -73,6 +76,14 @@ impl MmlNote {
     }
 }
 
+fn get_mml_velocity(midi_state: &MidiNoteState, options: &MmlSongOptions) -> u8 {
+    options.velocity_curve.to_mml_velocity(
+        midi_state.velocity,
+        options.velocity_min,
+        options.velocity_max,
+    )
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -1,17 +1,41 @@
-use std::{collections::HashMap, fs, path::Path};
+use std::{
+    cmp::Ordering,
+    collections::{BTreeMap, HashMap},
+    fs,
+    path::Path,
+    sync::Arc,
+};
 
-use anyhow::{Context, Result};
-use midly::{Smf, Timing, TrackEvent};
+use midly::{Format, Header, Smf, Timing, TrackEvent, num::u15};
 use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
 
 use crate::{
-    MmlTrack,
-    mml_event::BridgeEvent,
-    parser::{bridge_meta_from_midi_track, bridge_notes_from_midi_track},
-    utils,
+    Instrument, MmlTrack,
+    analysis::{SongAnalysis, TrackAnalysis, analyze_song, analyze_track},
+    dialect::MmlDialect,
+    error::{ConstraintError, MmlError, Result, TrackParseError},
+    export::{ExportOptions, SongExport, export_song},
+    history::{History, HistoryEntry, SongChange, snapshot_tracks},
+    keymap::KeymapPreset,
+    mml_event::{BridgeEvent, MmlEvent},
+    parser::{
+        bridge_meta_from_midi_track, bridge_meta_from_mml_nodes, bridge_notes_from_midi_track,
+        bridge_notes_from_mml_nodes, mml_tempos_to_midi_track,
+    },
+    pass::PassPipeline,
+    project::{MmlProject, PROJECT_VERSION, SongOperation},
+    syntax::{ParseOptions, parse, parse_with_options, timed_tempos},
+    tempo::{TempoPlacement, TempoReport, TempoTransform, get_end_position},
+    utils::{self, BalanceMetric},
+    velocity_curve::VelocityCurve,
+    verification::{TrackVerification, VerificationOptions, verify_track_with_tempos},
 };
 
-#[derive(Debug, Clone)]
+/// Checked by `validate`, the song constructors and setters reject invalid options.
+/// `MmlSongOptions::builder` starts from a preset and checks the result.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
 pub struct MmlSongOptions {
     ///  Automatically increases the velocity to the highest level within the defined range.
     /// The boost is calculated from the current maximum velocity to the highest note velocity.
@@ -34,11 +58,24 @@ pub struct MmlSongOptions {
     /// 1. If the start point of two notes is less than or equal to the min gap for chord, these notes will be combined into a chord.
     /// 1. If the start point of the following note minus the start point of the preceding note is greater than the min gap for chord, the preceding note will be shortened so that the position of the following note is accurate.
     ///
-    /// The min gap for chord acts as a threshold condition, measured in the smallest unit.
+    /// The min gap for chord acts as a threshold condition, measured in the smallest unit:
+    /// a gap of 2 with a smallest unit of 64 is a 1/32 note.
     pub min_gap_for_chord: u8,
 
     /// The smallest unit in the process of converting MIDI to MML, by default, is a 1/64 note.
+    /// A power of two up to 256.
     pub smallest_unit: usize,
+
+    /// How MIDI velocities are spread between `velocity_min` and `velocity_max`, linear by default.
+    /// The auto boost is applied after the curve.
+    pub velocity_curve: VelocityCurve,
+
+    /// Tracks that get the tempo commands, every track by default.
+    pub tempo_placement: TempoPlacement,
+
+    /// Passes every track is converted with, the built-in ones by default.
+    /// Saved as the names of the passes, custom passes cannot be read back.
+    pub pipeline: PassPipeline,
 }
 impl Default for MmlSongOptions {
     fn default() -> Self {
@@ -49,16 +86,79 @@ impl Default for MmlSongOptions {
             velocity_max: 15,
             min_gap_for_chord: 0,
             smallest_unit: 64,
+            velocity_curve: VelocityCurve::default(),
+            tempo_placement: TempoPlacement::default(),
+            pipeline: PassPipeline::default(),
         }
     }
 }
 
-#[derive(Debug, Clone)]
+impl MmlSongOptions {
+    /// Whether the MML converted with the other options would be different.
+    /// The velocity boost and the split options are applied after the conversion.
+    fn is_conversion_changed(&self, other: &Self) -> bool {
+        self.velocity_min != other.velocity_min
+            || self.velocity_max != other.velocity_max
+            || self.min_gap_for_chord != other.min_gap_for_chord
+            || self.smallest_unit != other.smallest_unit
+            || self.velocity_curve != other.velocity_curve
+            || self.pipeline != other.pipeline
+    }
+}
+
+/// Options of one track that replace the song options, `None` keeps the song option.
+/// Options about the whole song, like the velocity boost, cannot be overridden.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TrackOptionsOverride {
+    pub velocity_min: Option<u8>,
+    pub velocity_max: Option<u8>,
+    pub min_gap_for_chord: Option<u8>,
+    pub smallest_unit: Option<usize>,
+    pub velocity_curve: Option<VelocityCurve>,
+}
+
+impl TrackOptionsOverride {
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+
+    /// The song options with the overridden ones replaced.
+    pub fn apply(&self, song_options: &MmlSongOptions) -> MmlSongOptions {
+        MmlSongOptions {
+            velocity_min: self.velocity_min.unwrap_or(song_options.velocity_min),
+            velocity_max: self.velocity_max.unwrap_or(song_options.velocity_max),
+            min_gap_for_chord: self
+                .min_gap_for_chord
+                .unwrap_or(song_options.min_gap_for_chord),
+            smallest_unit: self.smallest_unit.unwrap_or(song_options.smallest_unit),
+            velocity_curve: self
+                .velocity_curve
+                .to_owned()
+                .unwrap_or_else(|| song_options.velocity_curve.to_owned()),
+            ..song_options.to_owned()
+        }
+    }
+}
+
+/// Beats per minute at one microsecond per beat.
+const MAX_TEMPO: u32 = 60_000_000;
+
+/// PPQ of songs read from MML, every length down to a 1/256 note is a whole number of ticks.
+const MML_PPQ: u16 = 960;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct MmlSong {
     pub ppq: u16,
     pub tracks: Vec<MmlTrack>,
     pub options: MmlSongOptions,
     velocity_diff: Option<u8>,
+
+    /// `None` for songs read from MML
+    project: Option<MmlProject>,
+
+    #[serde(skip)]
+    history: History,
 }
 
 impl MmlSong {
@@ -70,13 +170,19 @@ impl MmlSong {
         Self::from_bytes(bytes, options)
     }
 
-    pub fn from_bytes(bytes: Vec<u8>, options: MmlSongOptions) -> Result<Self> {
+    pub fn from_bytes(bytes: Vec<u8>, mut options: MmlSongOptions) -> Result<Self> {
+        options.validate()?;
+        let project = MmlProject::new(&bytes, options.clone());
         let smf = Smf::parse(&bytes)?;
         let ppq = get_ppq_from_smf(&smf).unwrap_or(480);
 
         let meta_events = get_bridge_meta_events(&smf.tracks);
+        check_tempos(&meta_events)?;
         let bridge_note_events = get_bridge_note_events(&smf.tracks);
 
+        options
+            .velocity_curve
+            .fit(bridge_note_events.iter().flatten());
         let tracks = bridge_events_to_tracks(meta_events, bridge_note_events, &options, ppq);
 
         let mut song = Self {
@@ -84,54 +190,175 @@ impl MmlSong {
             tracks,
             options,
             velocity_diff: None,
+            project: Some(project),
+            history: History::default(),
         };
+        song.place_tempo();
         song.appy_song_options();
 
         Ok(song)
     }
 
-    pub fn merge_tracks(&mut self, index_a: usize, index_b: usize) -> Result<()> {
-        let mut track_b = self
-            .tracks
-            .get(index_b)
-            .with_context(|| format!("Cannot get track by index_b = {}", index_b))?
-            .to_owned();
+    /// Converts the MIDI again and replays the edits of the project.
+    /// Fails when the MIDI is not the one the project was created from.
+    pub fn from_project(project: &MmlProject, bytes: Vec<u8>) -> Result<Self> {
+        if project.version > PROJECT_VERSION {
+            return Err(ConstraintError::UnsupportedVersion {
+                version: project.version,
+                supported: PROJECT_VERSION,
+            }
+            .into());
+        }
+        if !project.matches_source(&bytes) {
+            return Err(ConstraintError::SourceMismatch.into());
+        }
 
-        let track_a = self
-            .tracks
-            .get_mut(index_a)
-            .with_context(|| format!("Cannot get track by index_a = {}", index_a))?;
+        let mut song = Self::from_bytes(bytes, project.options.clone())?;
+        for (index, operation) in project.operations.iter().enumerate() {
+            song.apply_operation(operation)
+                .map_err(|error| MmlError::Replay {
+                    index,
+                    source: Box::new(error),
+                })?;
+        }
+
+        Ok(song)
+    }
+
+    /// The source and the edits of the song, `None` for songs read from MML.
+    pub fn project(&self) -> Option<&MmlProject> {
+        self.project.as_ref()
+    }
+
+    pub fn apply_operation(&mut self, operation: &SongOperation) -> Result<()> {
+        match operation {
+            SongOperation::SplitTrack { index } => self.split_track(*index),
+            SongOperation::MergeTracks { index_a, index_b } => {
+                self.merge_tracks(*index_a, *index_b)
+            }
+            SongOperation::EqualizeTracks { index_a, index_b } => {
+                self.equalize_tracks(*index_a, *index_b)
+            }
+            SongOperation::BalanceTracks { indexes, metric } => {
+                self.balance_tracks(indexes, *metric)
+            }
+            SongOperation::RenameTrack { index, name } => self.rename_track(*index, name.clone()),
+            SongOperation::ApplyKeymap { index, keymap } => {
+                let keymap: HashMap<u8, u8> = keymap.iter().copied().collect();
+                self.apply_keymap(*index, &keymap)
+            }
+            SongOperation::ApplyKeymapPreset { index, preset } => {
+                self.apply_keymap_preset(*index, preset)
+            }
+            SongOperation::SetTrackOptions { index, options } => {
+                self.set_track_options(*index, options.clone())
+            }
+            SongOperation::SetSongOptions { options } => self.set_song_options(options.clone()),
+            SongOperation::TransformTempo { transform } => {
+                self.transform_tempo(transform).map(|_| ())
+            }
+        }
+    }
+
+    /// Reads existing MML, one `(mml, instrument)` per track.
+    /// Fails with the byte offsets of every unknown command or invalid syntax.
+    /// Comments are skipped, so the output of `MmlTrack::to_pretty_mml` can be read back.
+    pub fn from_mml(
+        tracks: Vec<(String, Instrument)>,
+        mut options: MmlSongOptions,
+    ) -> Result<Self> {
+        options.validate()?;
+        let ppq = MML_PPQ;
+        let mut meta_events: BTreeMap<(usize, &'static str), (usize, BridgeEvent)> =
+            BTreeMap::new();
+        let mut bridge_note_events: Vec<Vec<BridgeEvent>> = Vec::with_capacity(tracks.len());
+        let mut errors: Vec<TrackParseError> = Vec::new();
+
+        for (index, (mml, instrument)) in tracks.iter().enumerate() {
+            let parsed = parse_with_options(mml, &ParseOptions { comments: true });
+
+            errors.extend(parsed.errors.iter().map(|error| TrackParseError {
+                index,
+                error: error.to_owned(),
+            }));
+
+            add_meta_events(
+                &mut meta_events,
+                index,
+                bridge_meta_from_mml_nodes(&parsed.nodes, ppq),
+            )?;
+
+            bridge_note_events.push(bridge_notes_from_mml_nodes(&parsed.nodes, instrument, ppq));
+        }
+
+        if !errors.is_empty() {
+            return Err(MmlError::Parse(errors));
+        }
+        let meta_events: Vec<BridgeEvent> =
+            meta_events.into_values().map(|(_, event)| event).collect();
+        check_tempos(&meta_events)?;
 
-        track_a.merge(&mut track_b);
+        options
+            .velocity_curve
+            .fit(bridge_note_events.iter().flatten());
+        let tracks = bridge_events_to_tracks(meta_events, bridge_note_events, &options, ppq);
+
+        let mut song = Self {
+            ppq,
+            tracks,
+            options,
+            velocity_diff: None,
+            project: None,
+            history: History::default(),
+        };
+        song.place_tempo();
+        song.appy_song_options();
+
+        Ok(song)
+    }
+
+    pub fn merge_tracks(&mut self, index_a: usize, index_b: usize) -> Result<()> {
+        MmlError::check_index(index_b, self.tracks.len())?;
+        MmlError::check_index(index_a, self.tracks.len())?;
+
+        let mut change = self.snapshot(&[index_a, index_b]);
+        let mut track_b = self.tracks[index_b].to_owned();
+        self.tracks[index_a].merge(&mut track_b);
         self.tracks.remove(index_b);
 
+        change.after = match index_a.cmp(&index_b) {
+            Ordering::Less => vec![index_a],
+            Ordering::Equal => vec![],
+            Ordering::Greater => vec![index_a - 1],
+        };
+        self.update_tracks(&change.after, self.get_boot_velocity());
+        self.record(SongOperation::MergeTracks { index_a, index_b }, change);
+
         Ok(())
     }
 
     pub fn split_track(&mut self, index: usize) -> Result<()> {
-        let track = self
-            .tracks
-            .get_mut(index)
-            .with_context(|| format!("Cannot get track by index {}", index))?;
-        let (mut track_a, mut track_b) = track.split();
+        MmlError::check_index(index, self.tracks.len())?;
 
-        if self.options.auto_boot_velocity
-            && let Some(velocity_diff) = self.velocity_diff
-        {
-            track_a.apply_boot_velocity(velocity_diff);
-            track_b.apply_boot_velocity(velocity_diff);
-        }
+        let mut change = self.snapshot(&[index]);
+        let (track_a, track_b) = self.tracks[index].split();
 
-        *track = track_a;
+        self.tracks[index] = track_a;
         self.tracks.insert(index + 1, track_b);
 
+        change.after = vec![index, index + 1];
+        self.update_tracks(&change.after, self.get_boot_velocity());
+        self.record(SongOperation::SplitTrack { index }, change);
+
         Ok(())
     }
 
     pub fn equalize_tracks(&mut self, index_a: usize, index_b: usize) -> Result<()> {
         if index_a == index_b {
-            return Err(anyhow::anyhow!("Cannot equalize the same track"));
+            return Err(ConstraintError::SameTrack { index: index_a }.into());
         }
+        let mut change = self.snapshot(&[index_a, index_b]);
+        let track_count = self.tracks.len();
 
         let (slice_a, slice_b) = if index_a < index_b {
             self.tracks.split_at_mut(index_a + 1)
@@ -139,32 +366,549 @@ impl MmlSong {
             self.tracks.split_at_mut(index_b + 1)
         };
 
-        let track_a = slice_a
-            .get_mut(index_a)
-            .with_context(|| format!("Cannot get track by index {}", index_a))?;
+        let track_a = slice_a.get_mut(index_a).ok_or(MmlError::Index {
+            index: index_a,
+            track_count,
+        })?;
 
         let track_b = slice_b
             .get_mut(index_b - if index_a < index_b { index_a + 1 } else { 0 })
-            .with_context(|| format!("Cannot get track by index {}", index_b))?;
+            .ok_or(MmlError::Index {
+                index: index_b,
+                track_count,
+            })?;
 
         utils::equalize_tracks(track_a, track_b);
+
+        change.after = vec![index_a, index_b];
+        self.update_tracks(&change.after, self.get_boot_velocity());
+        self.record(SongOperation::EqualizeTracks { index_a, index_b }, change);
+
         Ok(())
     }
 
-    pub fn apply_keymap(&mut self, track_index: usize, keymap: &HashMap<u8, u8>) {
-        if let Some(track) = self.tracks.get_mut(track_index) {
-            track.apply_keymap(keymap);
+    /// Balances the MML length of several tracks, see `utils::balance_tracks`.
+    pub fn balance_tracks(&mut self, indexes: &[usize], metric: BalanceMetric) -> Result<()> {
+        if indexes.len() < 2 {
+            return Err(ConstraintError::NotEnoughTracks {
+                count: indexes.len(),
+            }
+            .into());
+        }
+        for (position, index) in indexes.iter().enumerate() {
+            MmlError::check_index(*index, self.tracks.len())?;
+            if indexes[..position].contains(index) {
+                return Err(ConstraintError::DuplicateTrack { index: *index }.into());
+            }
         }
+
+        let mut change = self.snapshot(indexes);
+        let mut tracks: Vec<&mut MmlTrack> = self
+            .tracks
+            .iter_mut()
+            .enumerate()
+            .filter(|(index, _)| indexes.contains(index))
+            .map(|(_, track)| track)
+            .collect();
+        utils::balance_tracks(&mut tracks, metric);
+
+        change.after = indexes.to_owned();
+        self.update_tracks(&change.after, self.get_boot_velocity());
+        self.record(
+            SongOperation::BalanceTracks {
+                indexes: indexes.to_owned(),
+                metric,
+            },
+            change,
+        );
+
+        Ok(())
+    }
+
+    pub fn rename_track(&mut self, index: usize, name: String) -> Result<()> {
+        MmlError::check_index(index, self.tracks.len())?;
+
+        let mut change = self.snapshot(&[index]);
+        self.tracks[index].name = name.clone();
+
+        change.after = vec![index];
+        self.record(SongOperation::RenameTrack { index, name }, change);
+
+        Ok(())
+    }
+
+    pub fn apply_keymap(&mut self, track_index: usize, keymap: &HashMap<u8, u8>) -> Result<()> {
+        MmlError::check_index(track_index, self.tracks.len())?;
+
+        let mut change = self.snapshot(&[track_index]);
+        self.tracks[track_index].apply_keymap(keymap);
+
+        let mut pairs: Vec<(u8, u8)> = keymap.iter().map(|(from, to)| (*from, *to)).collect();
+        pairs.sort_unstable();
+
+        change.after = vec![track_index];
+        self.update_tracks(&change.after, self.get_boot_velocity());
+        self.record(
+            SongOperation::ApplyKeymap {
+                index: track_index,
+                keymap: pairs,
+            },
+            change,
+        );
+
+        Ok(())
     }
 
+    /// Applies a preset to one track, or to every track it is meant for when `track_index` is `None`.
+    pub fn apply_keymap_preset(
+        &mut self,
+        track_index: Option<usize>,
+        preset: &KeymapPreset,
+    ) -> Result<()> {
+        let indexes: Vec<usize> = match track_index {
+            Some(index) => {
+                MmlError::check_index(index, self.tracks.len())?;
+                vec![index]
+            }
+            None => self
+                .tracks
+                .iter()
+                .enumerate()
+                .filter(|(_, track)| preset.applies_to(&track.instrument))
+                .map(|(index, _)| index)
+                .collect(),
+        };
+
+        let mut change = self.snapshot(&indexes);
+        let keymap = preset.to_keymap();
+
+        self.tracks
+            .par_iter_mut()
+            .enumerate()
+            .filter(|(index, _)| indexes.contains(index))
+            .for_each(|(_, track)| track.apply_keymap(&keymap));
+
+        change.after = indexes;
+        self.update_tracks(&change.after, self.get_boot_velocity());
+        self.record(
+            SongOperation::ApplyKeymapPreset {
+                index: track_index,
+                preset: preset.to_owned(),
+            },
+            change,
+        );
+
+        Ok(())
+    }
+
+    /// Regenerates only the tracks whose options change, overridden options are kept.
     pub fn set_song_options(&mut self, options: MmlSongOptions) -> Result<()> {
-        self.options = options.clone();
-        self.tracks.par_iter_mut().for_each(|track| {
-            track.song_options = options.clone();
-            track.generate_mml_events();
+        options.validate()?;
+        let indexes: Vec<usize> = (0..self.tracks.len()).collect();
+        let mut change = self.snapshot(&indexes);
+        change.after = indexes;
+        change.options = Some(self.options.clone());
+        let boot_velocity = self.get_boot_velocity();
+
+        let mut fitted_options = options.clone();
+        fitted_options.velocity_curve.fit(self.get_bridge_notes());
+        let regenerated = self.regenerate_tracks(&fitted_options, None);
+        self.options = fitted_options;
+
+        self.update_tracks(&regenerated, boot_velocity);
+        self.record(SongOperation::SetSongOptions { options }, change);
+
+        Ok(())
+    }
+
+    /// Replaces the overridden options of a track, the track is regenerated when its options change.
+    pub fn set_track_options(
+        &mut self,
+        index: usize,
+        options_override: TrackOptionsOverride,
+    ) -> Result<()> {
+        MmlError::check_index(index, self.tracks.len())?;
+        options_override.apply(&self.options).validate()?;
+
+        let mut change = self.snapshot(&[index]);
+        change.after = vec![index];
+        let boot_velocity = self.get_boot_velocity();
+
+        self.tracks[index].options_override = options_override.clone();
+        let song_options = self.options.clone();
+        let regenerated = self.regenerate_tracks(&song_options, Some(index));
+
+        self.update_tracks(&regenerated, boot_velocity);
+        self.record(
+            SongOperation::SetTrackOptions {
+                index,
+                options: options_override,
+            },
+            change,
+        );
+
+        Ok(())
+    }
+
+    /// Changes the tempo events of the song and moves the notes of every track with them,
+    /// see `TempoTransform`.
+    /// Returns how far the notes moved from the time they should play at.
+    pub fn transform_tempo(&mut self, transform: &TempoTransform) -> Result<TempoReport> {
+        match transform {
+            TempoTransform::Scale { factor } if !factor.is_finite() || *factor <= 0. => {
+                return Err(MmlError::Options {
+                    name: "factor",
+                    value: factor.to_string(),
+                    reason: "the tempo factor must be above zero",
+                });
+            }
+            TempoTransform::Flatten { tempo: Some(0) } => {
+                return Err(MmlError::Options {
+                    name: "tempo",
+                    value: String::from("0"),
+                    reason: "cannot flatten to a zero tempo",
+                });
+            }
+            _ => (),
+        }
+
+        let indexes: Vec<usize> = (0..self.tracks.len()).collect();
+        let mut change = self.snapshot(&indexes);
+        change.after = indexes.to_owned();
+        let boot_velocity = self.get_boot_velocity();
+
+        // One tempo map for the whole song, from the meta events shared by the tracks
+        // and the latest note end, so tracks of different lengths stay in sync
+        let Some(meta_events) = self
+            .tracks
+            .first()
+            .map(|track| Arc::clone(&track.bridge_meta_events))
+        else {
+            return Ok(TempoReport::default());
+        };
+        let end = self
+            .tracks
+            .iter()
+            .map(|track| get_end_position(&[], &track.bridge_note_events))
+            .max()
+            .unwrap_or(0);
+        let plan = transform.plan(&meta_events, end, self.ppq);
+        let new_meta_events = Arc::new(plan.move_meta_events(&meta_events));
+        let tempo_counts = [(
+            count_tempo_events(&meta_events),
+            count_tempo_events(&new_meta_events),
+        )];
+
+        let errors: Vec<Vec<f64>> = self
+            .tracks
+            .par_iter_mut()
+            .map(|track| {
+                let errors = plan.move_notes(
+                    &mut track.bridge_note_events,
+                    track.song_options.smallest_unit,
+                );
+                track.bridge_meta_events = Arc::clone(&new_meta_events);
+                track.generate_mml_events();
+                errors
+            })
+            .collect();
+
+        self.update_tracks(&indexes, boot_velocity);
+        self.record(
+            SongOperation::TransformTempo {
+                transform: transform.to_owned(),
+            },
+            change,
+        );
+
+        Ok(TempoReport::new(&tempo_counts, &errors.concat()))
+    }
+
+    /// All tracks joined into a single text with the header and separators of the dialect.
+    pub fn to_mml_with_dialect(&self, dialect: &dyn MmlDialect) -> String {
+        let tracks: Vec<String> = self
+            .tracks
+            .par_iter()
+            .map(|track| track.to_mml_with_dialect(dialect))
+            .collect();
+
+        dialect.format_song(&tracks)
+    }
+
+    /// The quantized song as a MIDI file.
+    /// Tempo events are written to the first track, followed by one track per MML track.
+    pub fn to_smf(&self) -> Smf<'static> {
+        let header = Header::new(Format::Parallel, Timing::Metrical(u15::new(self.ppq)));
+        let mut smf = Smf::new(header);
+
+        let events: Vec<&[MmlEvent]> = self
+            .tracks
+            .iter()
+            .map(|track| track.events.as_slice())
+            .collect();
+        smf.tracks.push(mml_tempos_to_midi_track(
+            &events,
+            self.ppq,
+            self.options.smallest_unit,
+        ));
+
+        let tracks: Vec<Vec<TrackEvent<'static>>> = self
+            .tracks
+            .par_iter()
+            .map(|track| track.to_smf_track(false))
+            .collect();
+        smf.tracks.extend(tracks);
+
+        smf
+    }
+
+    /// Compares the timing of the MML with the MIDI notes, one result per track.
+    /// Tracks without tempo commands are played with the ones of the conductor track.
+    pub fn verify(&self, options: &VerificationOptions) -> Vec<TrackVerification> {
+        let conductor = self
+            .tracks
+            .iter()
+            .find(|track| !track.omit_tempo)
+            .filter(|_| self.tracks.iter().any(|track| track.omit_tempo));
+        let conductor_tempos = conductor.map(|track| {
+            let smallest_unit = track.song_options.smallest_unit;
+            let tempos = timed_tempos(&parse(&track.to_mml()).nodes, smallest_unit);
+            (tempos, smallest_unit)
         });
+
+        self.tracks
+            .par_iter()
+            .map(|track| {
+                let tempos = conductor_tempos.as_ref().map(|(tempos, smallest_unit)| {
+                    tempos
+                        .iter()
+                        .map(|(position, tempo)| {
+                            (
+                                position * track.song_options.smallest_unit / smallest_unit,
+                                *tempo,
+                            )
+                        })
+                        .collect()
+                });
+                verify_track_with_tempos(track, options, tempos)
+            })
+            .collect()
+    }
+
+    /// Statistics of every track and of the whole song,
+    /// tracks without tempo commands are verified with the ones of the conductor track.
+    pub fn export(&self, options: &ExportOptions) -> SongExport {
+        export_song(&self.tracks, &self.options, self.ppq, options)
+    }
+
+    /// The export of the song as pretty printed JSON.
+    pub fn to_json(&self, options: &ExportOptions) -> Result<String> {
+        Ok(serde_json::to_string_pretty(&self.export(options))?)
+    }
+
+    pub fn analyze(&self) -> SongAnalysis {
+        let verifications = self.verify(&VerificationOptions::default());
+        let analyses: Vec<TrackAnalysis> = self
+            .tracks
+            .par_iter()
+            .zip(verifications.par_iter())
+            .map(|(track, verification)| analyze_track(track, verification))
+            .collect();
+
+        analyze_song(&self.tracks, analyses)
+    }
+
+    pub fn can_undo(&self) -> bool {
+        !self.history.undo.is_empty()
+    }
+
+    pub fn can_redo(&self) -> bool {
+        !self.history.redo.is_empty()
+    }
+
+    /// Operations that can be undone, the most recent last.
+    pub fn history(&self) -> impl Iterator<Item = &SongOperation> {
+        self.history.undo.iter().map(|entry| &entry.operation)
+    }
+
+    /// Reverts the last operation and returns it, `None` when there is nothing to undo.
+    pub fn undo(&mut self) -> Option<SongOperation> {
+        let entry = self.history.undo.pop()?;
+
+        let boot_velocity = self.get_boot_velocity();
+        entry.change.revert(&mut self.tracks);
+        if let Some(options) = entry.change.options.to_owned() {
+            self.options = options;
+        }
+        self.velocity_diff = entry.change.velocity_diff;
+
+        // The restored and placed tracks are generated again and get the boost from scratch,
+        // the others get it back
+        let previous_boot_velocity = self.get_boot_velocity();
+        let placed = self.place_tempo();
+        let restored: Vec<usize> = entry
+            .change
+            .before
+            .iter()
+            .map(|(index, _)| *index)
+            .collect();
+        self.tracks
+            .par_iter_mut()
+            .enumerate()
+            .for_each(|(index, track)| {
+                let is_generated = placed.contains(&index) || restored.contains(&index);
+                if is_generated || previous_boot_velocity != boot_velocity {
+                    if let Some(velocity_diff) = boot_velocity.filter(|_| !is_generated) {
+                        track.revert_boot_velocity(velocity_diff);
+                    }
+                    if let Some(velocity_diff) = previous_boot_velocity {
+                        track.apply_boot_velocity(velocity_diff);
+                    }
+                }
+            });
+        if let Some(project) = self.project.as_mut() {
+            project.operations.pop();
+        }
+
+        let operation = entry.operation.to_owned();
+        self.history.redo.push(entry);
+        Some(operation)
+    }
+
+    /// Applies the last undone operation again and returns it, `None` when there is nothing to redo.
+    pub fn redo(&mut self) -> Result<Option<SongOperation>> {
+        let Some(entry) = self.history.redo.pop() else {
+            return Ok(None);
+        };
+
+        // Applying the operation records it and clears the redo stack
+        let redo = std::mem::take(&mut self.history.redo);
+        let result = self.apply_operation(&entry.operation);
+        self.history.redo = redo;
+
+        if let Err(error) = result {
+            self.history.redo.push(entry);
+            return Err(error);
+        }
+
+        Ok(Some(entry.operation))
+    }
+
+    fn record(&mut self, operation: SongOperation, change: SongChange) {
+        if let Some(project) = self.project.as_mut() {
+            project.operations.push(operation.to_owned());
+        }
+        self.history.push(HistoryEntry { operation, change });
+    }
+
+    /// Copies the tracks about to change, `after` is filled once the operation is done.
+    fn snapshot(&self, indexes: &[usize]) -> SongChange {
+        SongChange {
+            before: snapshot_tracks(&self.tracks, indexes),
+            after: Vec::new(),
+            options: None,
+            velocity_diff: self.velocity_diff,
+        }
+    }
+
+    fn get_bridge_notes(&self) -> impl Iterator<Item = &BridgeEvent> {
+        self.tracks
+            .iter()
+            .flat_map(|track| track.bridge_note_events.iter())
+    }
+
+    /// Applies the song options and the overrides of each track (or only of `only_index`),
+    /// and returns the index of every track that had to be regenerated.
+    fn regenerate_tracks(
+        &mut self,
+        song_options: &MmlSongOptions,
+        only_index: Option<usize>,
+    ) -> Vec<usize> {
+        // (options, whether the MML must be generated again)
+        let track_options: Vec<Option<(MmlSongOptions, bool)>> = self
+            .tracks
+            .iter()
+            .enumerate()
+            .map(|(index, track)| {
+                if only_index.is_some_and(|only_index| only_index != index) {
+                    return None;
+                }
+
+                let mut options = track.options_override.apply(song_options);
+                if track.options_override.velocity_curve.is_some() {
+                    options.velocity_curve.fit(self.get_bridge_notes());
+                }
+                let is_changed = options.is_conversion_changed(&track.song_options);
+                Some((options, is_changed))
+            })
+            .collect();
+
+        self.tracks
+            .par_iter_mut()
+            .zip(track_options.par_iter())
+            .for_each(|(track, options)| {
+                if let Some((options, is_changed)) = options {
+                    let previous_options =
+                        std::mem::replace(&mut track.song_options, options.to_owned());
+                    if *is_changed {
+                        track.regenerate_mml_events(&previous_options);
+                    }
+                }
+            });
+
+        track_options
+            .iter()
+            .enumerate()
+            .filter(|(_, options)| options.as_ref().is_some_and(|(_, is_changed)| *is_changed))
+            .map(|(index, _)| index)
+            .collect()
+    }
+
+    /// The boost applied to every track, `None` when the boost is off.
+    fn get_boot_velocity(&self) -> Option<u8> {
+        self.velocity_diff
+            .filter(|_| self.options.auto_boot_velocity)
+    }
+
+    /// Moves the tempo commands and computes the boost again after the tracks at `regenerated`
+    /// lost theirs, `boot_velocity` is the boost the other tracks still have.
+    fn update_tracks(&mut self, regenerated: &[usize], boot_velocity: Option<u8>) {
+        let placed = self.place_tempo();
+
+        if let Some(velocity_diff) = boot_velocity {
+            self.tracks
+                .par_iter_mut()
+                .enumerate()
+                .filter(|(index, _)| !regenerated.contains(index) && !placed.contains(index))
+                .for_each(|(_, track)| track.revert_boot_velocity(velocity_diff));
+        }
+
+        self.velocity_diff = None;
         self.appy_song_options();
-        Ok(())
+    }
+
+    /// Keeps the tempo commands in the tracks chosen by the options,
+    /// and returns the index of every track that had to be regenerated.
+    fn place_tempo(&mut self) -> Vec<usize> {
+        let conductor_index = self
+            .options
+            .tempo_placement
+            .get_conductor_index(&self.tracks);
+
+        self.tracks
+            .par_iter_mut()
+            .enumerate()
+            .filter_map(|(index, track)| {
+                let omit_tempo = conductor_index.is_some_and(|conductor| conductor != index);
+                if track.omit_tempo == omit_tempo {
+                    return None;
+                }
+
+                track.omit_tempo = omit_tempo;
+                track.generate_mml_events();
+                Some(index)
+            })
+            .collect()
     }
 
     fn appy_song_options(&mut self) {
@@ -177,22 +921,86 @@ impl MmlSong {
 }
 
 fn bridge_events_to_tracks(
-    bridge_meta_events: Vec<BridgeEvent>,
+    mut bridge_meta_events: Vec<BridgeEvent>,
     bridge_events: Vec<Vec<BridgeEvent>>,
     song_options: &MmlSongOptions,
     ppq: u16,
 ) -> Vec<MmlTrack> {
+    bridge_meta_events.sort();
+    let bridge_meta_events = Arc::new(bridge_meta_events);
+
     bridge_events
         .into_par_iter()
         .enumerate()
         .map(|(index, events)| {
             let options = song_options.to_owned();
-            let meta_events = bridge_meta_events.to_owned();
+            let meta_events = Arc::clone(&bridge_meta_events);
             MmlTrack::from_bridge_events(index.to_string(), meta_events, events, options, ppq)
         })
         .collect()
 }
 
+/// Adds the meta events of the track at `index`, one per kind and tick.
+/// Tracks usually repeat the same tempos, a track disagreeing with an earlier one is an error.
+/// Within a track the last event at a tick wins, like when the MML is played.
+fn add_meta_events(
+    meta_events: &mut BTreeMap<(usize, &'static str), (usize, BridgeEvent)>,
+    index: usize,
+    events: Vec<BridgeEvent>,
+) -> Result<()> {
+    for event in events {
+        let (kind, state) = match &event {
+            BridgeEvent::Tempo(_, state) => ("tempo", state),
+            BridgeEvent::TimeSignature(_, _, state) => ("time signature", state),
+            BridgeEvent::ProgramChange(_, state) => ("program change", state),
+            BridgeEvent::Note(note) => ("note", &note.midi_state),
+        };
+        let tick = state.position_in_tick;
+
+        match meta_events.get(&(tick, kind)) {
+            Some((other_index, other)) if *other_index != index && *other != event => {
+                return Err(ConstraintError::ConflictingMeta {
+                    kind,
+                    tick,
+                    index,
+                    other_index: *other_index,
+                }
+                .into());
+            }
+            Some((other_index, _)) if *other_index != index => (),
+            _ => {
+                meta_events.insert((tick, kind), (index, event));
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Notes after a tempo of zero would never start, and MIDI cannot store a faster tempo than
+/// one microsecond per beat.
+fn check_tempos(meta_events: &[BridgeEvent]) -> Result<()> {
+    for event in meta_events.iter() {
+        if let BridgeEvent::Tempo(tempo, state) = event
+            && (*tempo == 0 || *tempo > MAX_TEMPO)
+        {
+            return Err(MmlError::Timing {
+                tick: state.position_in_tick,
+                tempo: *tempo,
+            });
+        }
+    }
+
+    Ok(())
+}
+
+fn count_tempo_events(meta_events: &[BridgeEvent]) -> usize {
+    meta_events
+        .iter()
+        .filter(|event| matches!(event, BridgeEvent::Tempo(_, _)))
+        .count()
+}
+
 fn get_bridge_note_events(smf_tracks: &Vec<Vec<TrackEvent>>) -> Vec<Vec<BridgeEvent>> {
     smf_tracks
         .par_iter()
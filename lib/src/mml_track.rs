@@ -1,64 +1,150 @@
-use std::collections::HashMap;
+use std::{collections::HashMap, sync::Arc};
 
-use rayon::prelude::*;
+use midly::{Format, Header, Smf, Timing, TrackEvent, num::u15};
+use serde::{Deserialize, Serialize};
 
 use crate::{
     Instrument,
+    analysis::{TrackAnalysis, analyze_track},
+    chunk::{MmlChunk, chunk_track},
+    dialect::MmlDialect,
+    error::Result,
+    export::{ExportOptions, TrackExport, export_track},
+    history::TrackSnapshot,
     mml_event::{BridgeEvent, MidiNoteState, MmlEvent},
-    mml_song::MmlSongOptions,
-    parser::bridge_events_to_mml_events,
+    mml_song::{MmlSongOptions, TrackOptionsOverride},
+    parser::{bridge_events_to_raw_mml_events, mml_events_to_midi_track},
+    pass::PassContext,
+    pretty::{PrettyMmlOptions, pretty_track},
     utils,
+    verification::{TrackVerification, VerificationOptions, verify_track},
 };
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct MmlTrack {
     pub name: String,
     pub instrument: Instrument,
     pub events: Vec<MmlEvent>,
+    /// Song options with the overrides of this track applied
     pub song_options: MmlSongOptions,
-    pub bridge_meta_events: Vec<BridgeEvent>,
+
+    #[serde(default)]
+    pub options_override: TrackOptionsOverride,
+
+    /// Tempo commands are left to another track of the song, see `TempoPlacement`
+    #[serde(default)]
+    pub omit_tempo: bool,
+
+    /// Sorted by position and shared by the tracks of a song
+    pub bridge_meta_events: Arc<Vec<BridgeEvent>>,
+
+    /// Sorted by position, every change to the notes keeps them sorted
     pub bridge_note_events: Vec<BridgeEvent>,
-    pub bridge_events: Vec<BridgeEvent>,
     pub ppq: u16,
     pub mml_note_length: usize,
+
+    /// `None` until the events are generated and with custom passes, see `regenerate_mml_events`
+    #[serde(skip)]
+    stages: Option<StageEvents>,
+}
+
+/// Stages of the conversion, each one starts from the events of the one before it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Stage {
+    /// MML events at the positions of the bridge events, after the bridge passes
+    Raw,
+
+    /// Empty notes removed and chord notes connected
+    Normalized,
+
+    /// Every note at its position, with its MML written
+    PositionFixed,
+
+    /// Velocity commands where the velocity changes
+    Velocity,
 }
 
+/// Events of a track after each stage but the last one, which gives `MmlTrack::events`.
+/// Only kept with the default passes.
+#[derive(Debug, Clone, Default)]
+struct StageEvents {
+    raw: Vec<MmlEvent>,
+    normalized: Vec<MmlEvent>,
+    position_fixed: Vec<MmlEvent>,
+}
+
+/// MML passes of the default pipeline that give the normalized events,
+/// the ones after them fix the positions until the last one, which updates the velocities.
+const NORMALIZE_PASS_COUNT: usize = 1;
+
 impl MmlTrack {
     pub fn from_bridge_events(
         name: String,
-        bridge_meta_events: Vec<BridgeEvent>,
-        bridge_note_events: Vec<BridgeEvent>,
+        bridge_meta_events: impl Into<Arc<Vec<BridgeEvent>>>,
+        mut bridge_note_events: Vec<BridgeEvent>,
         song_options: MmlSongOptions,
         ppq: u16,
     ) -> Self {
+        let mut bridge_meta_events = bridge_meta_events.into();
+        if !bridge_meta_events.is_sorted() {
+            Arc::make_mut(&mut bridge_meta_events).sort();
+        }
+
+        if !bridge_note_events.is_sorted() {
+            bridge_note_events.sort();
+        }
+
         let mut mml_track = Self {
             name,
             events: Vec::new(),
             instrument: Instrument::default(),
             bridge_meta_events,
             bridge_note_events,
-            bridge_events: Vec::new(),
             song_options,
+            options_override: TrackOptionsOverride::default(),
+            omit_tempo: false,
             ppq,
             mml_note_length: 0,
+            stages: None,
+        };
+
+        mml_track.generate_mml_events();
+        mml_track
+    }
+
+    /// The track a snapshot was taken of, with its MML generated again.
+    pub(crate) fn from_snapshot(snapshot: &TrackSnapshot) -> Self {
+        let mut mml_track = Self {
+            name: snapshot.name.to_owned(),
+            events: Vec::new(),
+            instrument: Instrument::default(),
+            bridge_meta_events: Arc::clone(&snapshot.bridge_meta_events),
+            bridge_note_events: snapshot.bridge_note_events.to_owned(),
+            song_options: snapshot.song_options.to_owned(),
+            options_override: snapshot.options_override.to_owned(),
+            omit_tempo: snapshot.omit_tempo,
+            ppq: snapshot.ppq,
+            mml_note_length: 0,
+            stages: None,
         };
 
         mml_track.generate_mml_events();
+        mml_track.instrument = snapshot.instrument.to_owned();
         mml_track
     }
 
+    /// Changes the keys of the MIDI notes and generates the MML again,
+    /// so octave commands follow and the keymap survives later regenerations.
     pub fn apply_keymap(&mut self, keymap: &HashMap<u8, u8>) {
-        self.events
-            .par_chunks_mut(num_cpus::get())
-            .for_each(|events| {
-                for e in events.iter_mut() {
-                    if let MmlEvent::Note(note) = e
-                        && let Some(new_midi_key) = keymap.get(&note.midi_state.key)
-                    {
-                        note.apply_keymap(*new_midi_key, self.song_options.smallest_unit);
-                    }
-                }
-            });
+        for event in self.bridge_note_events.iter_mut() {
+            if let BridgeEvent::Note(note) = event
+                && let Some(new_midi_key) = keymap.get(&note.key)
+            {
+                note.key = (*new_midi_key).min(127);
+            }
+        }
+
+        self.generate_mml_events();
     }
 
     pub fn split(&self) -> (Self, Self) {
@@ -83,14 +169,18 @@ impl MmlTrack {
 
         track_a.instrument = self.instrument.to_owned();
         track_b.instrument = self.instrument.to_owned();
+        track_a.options_override = self.options_override.to_owned();
+        track_b.options_override = self.options_override.to_owned();
 
         (track_a, track_b)
     }
 
     pub fn merge(&mut self, other: &mut Self) {
-        self.bridge_note_events
-            .append(&mut other.bridge_note_events);
-        self.bridge_note_events.sort();
+        self.bridge_note_events = utils::merge_sorted(
+            std::mem::take(&mut self.bridge_note_events),
+            std::mem::take(&mut other.bridge_note_events),
+        )
+        .collect();
 
         self.name = format!("{}+{}", &self.name, other.name);
         self.generate_mml_events();
@@ -106,24 +196,95 @@ impl MmlTrack {
         mml
     }
 
-    // TODO: Whitespace might cause errors in the game
-    // pub fn to_mml_debug(&self) -> String {
-    //     let mut mml = String::new();
-    //     let mut notes_on_row: usize = 0;
-    //
-    //     for event in self.events.iter() {
-    //         let current_mml = &event.to_mml_debug(self.song_options.smallest_unit);
-    //         mml.push_str(&current_mml);
-    //
-    //         notes_on_row += event.get_duration();
-    //         if notes_on_row >= 64 {
-    //             notes_on_row = 0;
-    //             mml.push('\n');
-    //         }
-    //     }
-    //
-    //     mml
-    // }
+    /// Lengths the dialect cannot write are rounded, and the rounding is carried into the next
+    /// note or rest so the track does not drift.
+    pub fn to_mml_with_dialect(&self, dialect: &dyn MmlDialect) -> String {
+        let smallest_unit = self.song_options.smallest_unit;
+        let shortest = utils::get_shortest_length_in_smallest_unit(smallest_unit, dialect);
+        let mut mml = String::new();
+
+        // (position in the track, position in the written MML)
+        let mut position = (0usize, 0usize);
+        let mut chord_position = (0usize, 0usize);
+
+        for event in self.events.iter() {
+            let Some(duration) = event.get_duration() else {
+                mml.push_str(&event.to_mml_with_dialect(smallest_unit, dialect));
+                continue;
+            };
+
+            let (start, written_start) = match event.is_part_of_chord() {
+                true => chord_position,
+                false => position,
+            };
+            let end = start + duration;
+            let mut written_duration =
+                utils::round_to_multiple(end.saturating_sub(written_start), shortest);
+
+            // A note is never dropped, the next note or rest is shortened instead
+            if matches!(event, MmlEvent::Note(_)) {
+                written_duration = written_duration.max(shortest);
+            }
+
+            let mut written_event = event.to_owned();
+            written_event.set_duration(written_duration);
+            mml.push_str(&written_event.to_mml_with_dialect(smallest_unit, dialect));
+
+            if !event.is_part_of_chord() {
+                chord_position = position;
+                position = (end, written_start + written_duration);
+            }
+        }
+
+        mml
+    }
+
+    /// The MML in chunks of at most `max_chars` characters with their start times, for inputs
+    /// with a length limit. Chunks end after a note or a chord, at a bar line when one fits,
+    /// and restate the tempo, octave and velocity so each one plays correctly alone.
+    /// Tracks without tempo commands get the tempo changes of the song.
+    /// Fails when a note does not fit with the state restated before it.
+    pub fn to_mml_chunks(&self, max_chars: usize) -> Result<Vec<MmlChunk>> {
+        chunk_track(self, max_chars)
+    }
+
+    /// The MML laid out in bars and lines with comments, for reading and editing by hand.
+    pub fn to_pretty_mml(&self, options: &PrettyMmlOptions) -> String {
+        pretty_track(self, options)
+    }
+
+    /// The quantized notes of this track as a single track MIDI file.
+    pub fn to_smf(&self) -> Smf<'static> {
+        let header = Header::new(Format::SingleTrack, Timing::Metrical(u15::new(self.ppq)));
+        let mut smf = Smf::new(header);
+        smf.tracks.push(self.to_smf_track(true));
+        smf
+    }
+
+    /// The quantized notes of this track as MIDI track events.
+    pub fn to_smf_track(&self, include_tempo: bool) -> Vec<TrackEvent<'static>> {
+        mml_events_to_midi_track(
+            &self.events,
+            &self.instrument,
+            self.ppq,
+            self.song_options.smallest_unit,
+            include_tempo,
+        )
+    }
+
+    /// Statistics of the notes and the MML of this track.
+    pub fn analyze(&self) -> TrackAnalysis {
+        analyze_track(self, &self.verify(&VerificationOptions::default()))
+    }
+
+    pub fn export(&self, options: &ExportOptions) -> TrackExport {
+        export_track(self, options)
+    }
+
+    /// Compares the timing of `to_mml()` with the MIDI notes of this track.
+    pub fn verify(&self, options: &VerificationOptions) -> TrackVerification {
+        verify_track(self, options)
+    }
 
     pub fn apply_boot_velocity(&mut self, velocity_diff: u8) {
         if velocity_diff > 0 {
@@ -135,28 +296,121 @@ impl MmlTrack {
         }
     }
 
+    pub fn revert_boot_velocity(&mut self, velocity_diff: u8) {
+        for event in self.events.iter_mut() {
+            if let MmlEvent::Velocity(velocity) = event {
+                *velocity = velocity.saturating_sub(velocity_diff);
+            }
+        }
+    }
+
+    /// The note and meta events sorted by position, without copying them.
+    /// Tempo events are left out when `omit_tempo` is set.
+    pub fn bridge_events(&self) -> impl Iterator<Item = &BridgeEvent> {
+        let meta_events = self
+            .bridge_meta_events
+            .iter()
+            .filter(|event| !self.omit_tempo || !matches!(event, BridgeEvent::Tempo(_, _)));
+
+        utils::merge_sorted(self.bridge_note_events.iter(), meta_events)
+    }
+
     pub fn generate_mml_events(&mut self) {
-        self.apply_meta_events();
+        self.run_stages(Stage::Raw);
+    }
+
+    /// Generates the MML again after `song_options` changed from `previous_options`,
+    /// running only the stages that depend on the options that changed.
+    /// A velocity change only updates the velocity commands.
+    pub fn regenerate_mml_events(&mut self, previous_options: &MmlSongOptions) {
+        // Tracks converted with custom passes have no stages
+        let is_staged = self.stages.is_some() && self.song_options.pipeline.is_default();
+
+        let stage = if is_staged
+            && self.song_options.smallest_unit == previous_options.smallest_unit
+            && self.song_options.min_gap_for_chord == previous_options.min_gap_for_chord
+        {
+            Stage::Velocity
+        } else {
+            Stage::Raw
+        };
 
-        let (events, instrument) =
-            bridge_events_to_mml_events(&self.bridge_events, &self.song_options, self.ppq);
+        self.run_stages(stage);
+    }
+
+    /// Runs the stages from `first` on, starting from the events of the stage before it.
+    fn run_stages(&mut self, first: Stage) {
+        let pipeline = &self.song_options.pipeline;
+        let context = PassContext {
+            options: &self.song_options,
+            ppq: self.ppq,
+        };
 
-        if let Some(instrument) = instrument {
-            self.instrument = instrument;
+        // Custom passes cannot be split into stages, they run in one go
+        if !pipeline.is_default() {
+            let (mut events, instrument) = self.get_raw_events();
+            pipeline.run_mml_passes(&mut events, &context);
+
+            if let Some(instrument) = instrument {
+                self.instrument = instrument;
+            }
+            self.events = events;
+            self.stages = None;
+            self.update_mml_note_length();
+            return;
         }
 
+        let mut stages = self.stages.take().unwrap_or_default();
+        let velocity_pass = pipeline.mml_pass_count() - 1;
+
+        if first <= Stage::Raw {
+            let (raw_events, instrument) = self.get_raw_events();
+            if let Some(instrument) = instrument {
+                self.instrument = instrument;
+            }
+            stages.raw = raw_events;
+        }
+        if first <= Stage::Normalized {
+            stages.normalized = stages.raw.to_owned();
+            pipeline.run_mml_passes_in(..NORMALIZE_PASS_COUNT, &mut stages.normalized, &context);
+        }
+        if first <= Stage::PositionFixed {
+            stages.position_fixed = stages.normalized.to_owned();
+            pipeline.run_mml_passes_in(
+                NORMALIZE_PASS_COUNT..velocity_pass,
+                &mut stages.position_fixed,
+                &context,
+            );
+        }
+
+        let mut events = stages.position_fixed.to_owned();
+        pipeline.run_mml_passes_in(velocity_pass.., &mut events, &context);
         self.events = events;
+        self.stages = Some(stages);
         self.update_mml_note_length();
     }
 
-    fn apply_meta_events(&mut self) {
-        self.bridge_events =
-            Vec::with_capacity(self.bridge_meta_events.len() + self.bridge_note_events.len());
-        self.bridge_events
-            .extend(self.bridge_note_events.to_owned());
-        self.bridge_events
-            .extend(self.bridge_meta_events.to_owned());
-        self.bridge_events.sort();
+    /// MML events at the positions of the bridge events, after the bridge passes.
+    fn get_raw_events(&self) -> (Vec<MmlEvent>, Option<Instrument>) {
+        let pipeline = &self.song_options.pipeline;
+        if !pipeline.has_bridge_passes() {
+            return bridge_events_to_raw_mml_events(
+                self.bridge_events(),
+                &self.song_options,
+                self.ppq,
+            );
+        }
+
+        let mut bridge_events: Vec<BridgeEvent> = self.bridge_events().cloned().collect();
+        pipeline.run_bridge_passes(&mut bridge_events, &self.get_pass_context());
+        bridge_events_to_raw_mml_events(&bridge_events, &self.song_options, self.ppq)
+    }
+
+    fn get_pass_context(&self) -> PassContext<'_> {
+        PassContext {
+            options: &self.song_options,
+            ppq: self.ppq,
+        }
     }
 
     fn update_mml_note_length(&mut self) {
@@ -214,7 +468,7 @@ impl MmlTrack {
 
         let track_a = Self::from_bridge_events(
             format!("{}.0", self.name),
-            self.bridge_meta_events.to_owned(),
+            Arc::clone(&self.bridge_meta_events),
             bridges_a,
             self.song_options.to_owned(),
             self.ppq,
@@ -222,7 +476,7 @@ impl MmlTrack {
 
         let track_b = Self::from_bridge_events(
             format!("{}.1", self.name),
-            self.bridge_meta_events.to_owned(),
+            Arc::clone(&self.bridge_meta_events),
             bridges_b,
             self.song_options.to_owned(),
             self.ppq,
@@ -308,6 +562,116 @@ mod tests {
         assert!(mml_string.contains("c4"));
     }
 
+    #[test]
+    fn test_mml_track_apply_keymap() {
+        let bridge_note_events = vec![
+            BridgeEvent::Note(create_test_midi_note_state(60, 64, 0, 480)),
+            BridgeEvent::Note(create_test_midi_note_state(62, 64, 480, 480)),
+        ];
+        let mut track = MmlTrack::from_bridge_events(
+            "test".to_string(),
+            vec![],
+            bridge_note_events,
+            MmlSongOptions::default(),
+            480,
+        );
+
+        track.apply_keymap(&HashMap::from([(62, 50)]));
+
+        // The octave commands follow the new key
+        assert_eq!(track.to_mml(), "v7o4c4<d4");
+
+        // The keymap is kept when the MML is generated again
+        track.generate_mml_events();
+        assert_eq!(track.to_mml(), "v7o4c4<d4");
+    }
+
+    #[test]
+    fn test_mml_track_to_mml_with_dialect() {
+        use crate::{GenericDialect, RevelationDialect};
+
+        let options = MmlSongOptions::default();
+        let ppq = 480;
+
+        let bridge_note_events = vec![
+            BridgeEvent::Note(create_test_midi_note_state(60, 64, 0, 480)),
+            BridgeEvent::Note(create_test_midi_note_state(66, 64, 0, 480)),
+        ];
+
+        let track = MmlTrack::from_bridge_events(
+            "chord".to_string(),
+            vec![],
+            bridge_note_events,
+            options,
+            ppq,
+        );
+
+        assert_eq!(
+            track.to_mml_with_dialect(&RevelationDialect),
+            track.to_mml()
+        );
+        assert_eq!(track.to_mml(), "v7o4c4:f+4");
+        assert_eq!(track.to_mml_with_dialect(&GenericDialect), "v7o4c4");
+    }
+
+    #[test]
+    fn test_mml_track_dialect_keeps_duration() {
+        use crate::{
+            GenericDialect, MabinogiDialect, RevelationDialect,
+            syntax::{parse, timed_notes},
+        };
+
+        let options = MmlSongOptions {
+            smallest_unit: 128,
+            ..Default::default()
+        };
+
+        // 1/128 is 15 ticks, every note lasts 5/128 and is followed by a 1/128 rest
+        let bridge_note_events: Vec<BridgeEvent> = (0..16)
+            .map(|index| {
+                BridgeEvent::Note(create_test_midi_note_state(
+                    60 + index as u8,
+                    64,
+                    index * 90,
+                    75,
+                ))
+            })
+            .collect();
+        let track = MmlTrack::from_bridge_events(
+            "odd".to_string(),
+            vec![],
+            bridge_note_events,
+            options,
+            480,
+        );
+
+        let dialects: [&dyn MmlDialect; 3] =
+            [&RevelationDialect, &GenericDialect, &MabinogiDialect];
+        for dialect in dialects {
+            let shortest = utils::get_shortest_length_in_smallest_unit(128, dialect);
+            let notes = timed_notes(&parse(&track.to_mml_with_dialect(dialect)).nodes, 128);
+            let starts: Vec<usize> = notes
+                .iter()
+                .filter(|note| note.midi_key.is_some())
+                .map(|note| note.position_in_smallest_unit)
+                .collect();
+
+            assert_eq!(starts.len(), 16, "{}", dialect.name());
+            for (index, start) in starts.iter().enumerate() {
+                assert!(start.abs_diff(index * 6) < shortest, "{}", dialect.name());
+            }
+
+            let end = notes
+                .iter()
+                .map(|note| note.position_in_smallest_unit + note.duration_in_smallest_unit)
+                .max();
+            assert_eq!(
+                end,
+                Some(15 * 6 + 5).map(|end| utils::round_to_multiple(end, shortest))
+            );
+        }
+    }
+
     #[test]
     fn test_mml_track_merge() {
         let options = MmlSongOptions::default();
@@ -388,6 +752,46 @@ mod tests {
         // track_b might be empty if all notes went to track_a due to split logic
     }
 
+    #[test]
+    fn test_mml_track_notes_stay_sorted() {
+        let options = MmlSongOptions {
+            auto_equalize_note_length: true,
+            ..MmlSongOptions::default()
+        };
+        let notes = |positions: &[usize]| -> Vec<BridgeEvent> {
+            positions
+                .iter()
+                .map(|position| {
+                    BridgeEvent::Note(create_test_midi_note_state(60, 64, *position, 960))
+                })
+                .collect()
+        };
+
+        let mut track = MmlTrack::from_bridge_events(
+            "a".to_string(),
+            vec![],
+            notes(&[1440, 0, 480]),
+            options.clone(),
+            480,
+        );
+        assert!(track.bridge_note_events.is_sorted());
+
+        let mut other =
+            MmlTrack::from_bridge_events("b".to_string(), vec![], notes(&[240, 960]), options, 480);
+        track.merge(&mut other);
+        assert!(track.bridge_note_events.is_sorted());
+
+        let (track_a, track_b) = track.split();
+        assert!(track_a.bridge_note_events.is_sorted());
+        assert!(track_b.bridge_note_events.is_sorted());
+        assert!(
+            track
+                .bridge_events()
+                .collect::<Vec<&BridgeEvent>>()
+                .is_sorted()
+        );
+    }
+
     #[test]
     fn test_mml_track_apply_boot_velocity() {
         let options = MmlSongOptions::default();
@@ -424,6 +828,44 @@ mod tests {
         assert_eq!(new_velocity, original_velocity + 3);
     }
 
+    #[test]
+    fn test_mml_track_regenerate_velocities_only() {
+        let options = MmlSongOptions::default();
+        let bridge_events = vec![
+            BridgeEvent::Note(create_test_midi_note_state(60, 20, 0, 480)),
+            BridgeEvent::Note(create_test_midi_note_state(64, 120, 960, 480)),
+            BridgeEvent::Note(create_test_midi_note_state(67, 20, 1920, 480)),
+        ];
+        let mut track =
+            MmlTrack::from_bridge_events("test".to_string(), vec![], bridge_events, options, 480);
+        assert_eq!(track.to_mml(), "v2o4c4r4v14e4r4v2g4");
+
+        // A rest changed by hand in the cached stage shows whether the positions were fixed again
+        let stages = track.stages.as_mut().unwrap();
+        let velocity_count = stages
+            .raw
+            .iter()
+            .filter(|event| matches!(event, MmlEvent::Velocity(_)))
+            .count();
+        assert_eq!(velocity_count, 3);
+        let rest = stages
+            .position_fixed
+            .iter_mut()
+            .find(|event| matches!(event, MmlEvent::Rest(_)))
+            .unwrap();
+        *rest = MmlEvent::Rest(8);
+
+        let previous_options = track.song_options.clone();
+        track.song_options.velocity_max = 10;
+        track.regenerate_mml_events(&previous_options);
+        assert_eq!(track.to_mml(), "v1o4c4r8v9e4r4v1g4");
+
+        let previous_options = track.song_options.clone();
+        track.song_options.smallest_unit = 32;
+        track.regenerate_mml_events(&previous_options);
+        assert_eq!(track.to_mml(), "v1o4c4r4v9e4r4v1g4");
+    }
+
     #[test]
     fn test_mml_track_with_tempo_events() {
         let options = MmlSongOptions::default();
@@ -533,17 +975,22 @@ mod tests {
         assert_eq!(track.bridge_note_events.len(), 2);
 
         // Check that events are sorted by position
-        for i in 1..track.bridge_events.len() {
-            let prev_pos = match &track.bridge_events[i - 1] {
+        let bridge_events: Vec<&BridgeEvent> = track.bridge_events().collect();
+        assert_eq!(bridge_events.len(), 2);
+
+        for i in 1..bridge_events.len() {
+            let prev_pos = match bridge_events[i - 1] {
                 BridgeEvent::Note(note) => note.midi_state.position_in_tick,
                 BridgeEvent::Tempo(_, state) => state.position_in_tick,
                 BridgeEvent::ProgramChange(_, state) => state.position_in_tick,
+                BridgeEvent::TimeSignature(_, _, state) => state.position_in_tick,
             };
 
-            let curr_pos = match &track.bridge_events[i] {
+            let curr_pos = match bridge_events[i] {
                 BridgeEvent::Note(note) => note.midi_state.position_in_tick,
                 BridgeEvent::Tempo(_, state) => state.position_in_tick,
                 BridgeEvent::ProgramChange(_, state) => state.position_in_tick,
+                BridgeEvent::TimeSignature(_, _, state) => state.position_in_tick,
             };
 
             assert!(curr_pos >= prev_pos, "Events should be sorted by position");
@@ -5,27 +5,52 @@ use crate::{
     mml_event::{BridgeEvent, MmlEvent},
     mml_note::MmlNote,
     mml_song::MmlSongOptions,
-    utils::{compute_position_in_smallest_unit, tick_to_smallest_unit},
+    utils::tick_to_smallest_unit,
 };
 
-pub fn bridge_events_to_mml_events(
-    bridge_events: &[BridgeEvent],
-    options: &MmlSongOptions,
-    ppq: u16,
-) -> (Vec<MmlEvent>, Option<Instrument>) {
-    let (mut mml_events, instrument) = bridge_events_to_raw_mml_events(bridge_events, options, ppq);
+/// Converts the velocities of the notes again and keeps only the velocity commands that change
+/// the velocity, each one set to the velocity of the note after it.
+/// Raw events have a velocity command before every note, so the velocities can change
+/// without fixing the positions again.
+pub fn update_velocities(events: &mut Vec<MmlEvent>, options: &MmlSongOptions) {
+    // Velocity of the first note at or after each event
+    let mut next_velocity: Option<u8> = None;
+    let mut next_velocities: Vec<Option<u8>> = Vec::with_capacity(events.len());
+
+    for event in events.iter_mut().rev() {
+        if let MmlEvent::Note(note) = event {
+            note.update_velocity(options);
+            next_velocity = Some(note.velocity);
+        }
+        next_velocities.push(next_velocity);
+    }
+    next_velocities.reverse();
+
+    let mut velocity: Option<u8> = None;
+    let mut output: Vec<MmlEvent> = Vec::with_capacity(events.len());
+
+    for (event, next_velocity) in events.drain(..).zip(next_velocities) {
+        if let MmlEvent::Velocity(_) = event {
+            if let Some(next_velocity) = next_velocity
+                && velocity != Some(next_velocity)
+            {
+                velocity = Some(next_velocity);
+                output.push(MmlEvent::Velocity(next_velocity));
+            }
+            continue;
+        }
 
-    normalize_events(&mut mml_events);
-    fix_events_position(&mut mml_events);
-    normalize_events(&mut mml_events);
-    update_chord_duration(&mut mml_events);
-    update_note_mml(&mut mml_events, options.smallest_unit);
+        output.push(event);
+    }
 
-    (mml_events, instrument)
+    *events = output;
 }
 
-fn bridge_events_to_raw_mml_events(
-    bridge_events: &[BridgeEvent],
+/// MML events at the positions of the MIDI notes, the rests and chords may not line up yet.
+/// Every note has a velocity command before it, `update_velocities` removes the ones that
+/// do not change the velocity.
+pub fn bridge_events_to_raw_mml_events<'a>(
+    bridge_events: impl IntoIterator<Item = &'a BridgeEvent>,
     options: &MmlSongOptions,
     ppq: u16,
 ) -> (Vec<MmlEvent>, Option<Instrument>) {
@@ -40,7 +65,7 @@ fn bridge_events_to_raw_mml_events(
 
     let mut instrument = None;
 
-    for event in bridge_events.iter() {
+    for event in bridge_events {
         match event {
             BridgeEvent::Tempo(tempo, state) => {
                 let pos = tick_to_smallest_unit(state.position_in_tick, ppq, options.smallest_unit);
@@ -49,6 +74,7 @@ fn bridge_events_to_raw_mml_events(
             BridgeEvent::ProgramChange(dest_instrument, _) => {
                 instrument = Some(dest_instrument.to_owned());
             }
+            BridgeEvent::TimeSignature(..) => (),
             BridgeEvent::Note(midi_state) => {
                 let mut note = MmlNote::from_midi_state(midi_state.to_owned(), options, ppq, false);
 
@@ -73,10 +99,7 @@ fn bridge_events_to_raw_mml_events(
                         mml_events.push(MmlEvent::Octave(note.octave));
                     }
 
-                    // Velocity
-                    if note.velocity != before_note.velocity {
-                        mml_events.push(MmlEvent::Velocity(note.velocity));
-                    }
+                    mml_events.push(MmlEvent::Velocity(note.velocity));
                 } else {
                     if note.position_in_smallest_unit > 0 {
                         mml_events.push(MmlEvent::Rest(note.position_in_smallest_unit));
@@ -168,7 +191,7 @@ fn handle_bridge_note_events(
     }
 }
 
-fn update_note_mml(events: &mut [MmlEvent], smallest_unit: usize) {
+pub fn update_note_mml(events: &mut [MmlEvent], smallest_unit: usize) {
     for event in events.iter_mut() {
         if let MmlEvent::Note(note) = event {
             note.update_mml_string(smallest_unit);
@@ -176,47 +199,45 @@ fn update_note_mml(events: &mut [MmlEvent], smallest_unit: usize) {
     }
 }
 
+/// Moves every note and tempo to its expected position, in one pass.
+/// Rests are added before an event that comes too early, and the events before one that comes
+/// too late are shortened.
 pub fn fix_events_position(events: &mut Vec<MmlEvent>) {
-    let mut i = 0;
-    while i < events.len() {
-        let current_event = events.get(i).unwrap();
+    let mut output: Vec<MmlEvent> = Vec::with_capacity(events.len());
 
-        match &current_event {
-            MmlEvent::Note(note) => {
-                if !note.is_part_of_chord {
-                    let new_i = fix_event_position(events, i);
-                    if new_i != i {
-                        i = new_i;
-                    }
-                }
-            }
-            MmlEvent::Tempo(_, _) => {
-                let new_i = fix_event_position(events, i);
-                if new_i != i {
-                    i = new_i;
-                }
-            }
-            _ => (),
+    // Position of the end of `output`, chord notes start with the note before them
+    let mut position = 0;
+
+    for event in events.drain(..) {
+        let should_fix = match &event {
+            MmlEvent::Note(note) => !note.is_part_of_chord,
+            MmlEvent::Tempo(_, _) => true,
+            _ => false,
         };
 
-        i += 1;
+        if should_fix && let Some(expect_pos) = event.get_position() {
+            fix_event_position(&mut output, &mut position, expect_pos);
+        }
+
+        if !event.is_part_of_chord()
+            && let Some(duration) = event.get_duration()
+        {
+            position += duration;
+        }
+        output.push(event);
     }
-}
 
-fn fix_event_position(events: &mut Vec<MmlEvent>, event_index: usize) -> usize {
-    let Some(event) = events.get(event_index) else {
-        return event_index;
-    };
-    let Some(expect_pos) = event.get_position() else {
-        return event_index;
-    };
-    let mut current_pos = compute_position_in_smallest_unit(events, event_index);
+    *events = output;
+}
 
-    if expect_pos == current_pos {
-        return event_index;
+/// Updates the end of `events`, the events before the one to fix, so that it ends at `expect_pos`.
+/// `position` is the end of `events` and is kept up to date.
+fn fix_event_position(events: &mut Vec<MmlEvent>, position: &mut usize, expect_pos: usize) {
+    if expect_pos == *position {
+        return;
     }
 
-    let mut i = event_index;
+    let mut i = events.len();
     while i > 0 {
         i -= 1;
 
@@ -225,85 +246,83 @@ fn fix_event_position(events: &mut Vec<MmlEvent>, event_index: usize) -> usize {
             continue;
         }
 
-        if expect_pos > current_pos {
-            let to_incre = expect_pos - current_pos;
+        if expect_pos > *position {
+            let to_incre = expect_pos - *position;
             events.insert(i + 1, MmlEvent::Rest(to_incre));
-            return event_index + 1;
+            *position = expect_pos;
+            return;
         } else {
-            let to_decre = current_pos - expect_pos;
+            let to_decre = *position - expect_pos;
             let Some(e_dur) = e.get_duration() else {
                 continue;
             };
 
             if e_dur > to_decre {
                 e.set_duration(e_dur - to_decre);
+                *position = expect_pos;
+                return;
             } else if let MmlEvent::Note(note) = e {
                 note.is_part_of_chord = true;
+                *position -= e_dur;
             } else if let MmlEvent::Rest(_) = e {
                 events.remove(i);
-                return event_index - 1;
+                *position -= e_dur;
+                return;
             }
 
-            current_pos = compute_position_in_smallest_unit(events, event_index);
-            if expect_pos == current_pos {
-                return event_index;
+            if expect_pos == *position {
+                return;
             }
         }
     }
 
-    if expect_pos > current_pos {
-        events.insert(0, MmlEvent::Rest(expect_pos - current_pos));
-        return event_index + 1;
+    if expect_pos > *position {
+        events.insert(0, MmlEvent::Rest(expect_pos - *position));
+        *position = expect_pos;
     }
-
-    event_index
 }
 
-fn normalize_events(events: &mut Vec<MmlEvent>) {
-    let mut i = 0;
+/// Removes empty notes and rests and the first of two tempos in a row,
+/// and connects chord notes with `:`, in one pass.
+pub fn normalize_events(events: &mut Vec<MmlEvent>) {
+    let mut output: Vec<MmlEvent> = Vec::with_capacity(events.len());
     let mut before_note_i: Option<usize> = None;
 
-    while i < events.len() {
-        let current_e = events.get(i).unwrap().clone();
-
-        match current_e {
+    for event in events.drain(..) {
+        match &event {
             MmlEvent::Note(note) => {
                 if note.is_part_of_chord {
-                    if !has_a_connect_chord_event(events, i) {
+                    if !has_a_connect_chord_event(&output, output.len()) {
                         if let Some(before_note_i) = before_note_i {
-                            events.insert(before_note_i + 1, MmlEvent::ConnectChord);
-                            i += 1;
+                            output.insert(before_note_i + 1, MmlEvent::ConnectChord);
                         } else {
                             // TODO: Handle case where chord note has no preceding note
-                            error!("Note {note:?} at {i} has no preceding note");
+                            error!("Note {note:?} at {} has no preceding note", output.len());
                         }
                     }
                 } else if note.duration_in_smallest_unit == 0 {
-                    events.remove(i);
-                    i -= 1;
+                    continue;
                 } else {
-                    before_note_i = Some(i);
+                    before_note_i = Some(output.len());
                 }
             }
             MmlEvent::Rest(rest) => {
-                if rest == 0 {
-                    events.remove(i);
-                    i -= 1;
+                if *rest == 0 {
+                    continue;
                 }
             }
             MmlEvent::Tempo(_, _) => {
-                if i > 0
-                    && let Some(MmlEvent::Tempo(_, _)) = events.get(i - 1)
-                {
-                    events.remove(i - 1);
-                    i -= 1;
+                if let Some(MmlEvent::Tempo(_, _)) = output.last() {
+                    output.pop();
                 }
             }
             _ => {}
         }
 
-        i += 1;
+        output.push(event);
     }
+
+    *events = output;
 }
 
 pub fn has_a_connect_chord_event(events: &[MmlEvent], index: usize) -> bool {
@@ -318,7 +337,7 @@ pub fn has_a_connect_chord_event(events: &[MmlEvent], index: usize) -> bool {
     false
 }
 
-fn update_chord_duration(events: &mut [MmlEvent]) {
+pub fn update_chord_duration(events: &mut [MmlEvent]) {
     let mut before_note: Option<MmlNote> = None;
 
     for i in 0..events.len() {
@@ -345,7 +364,7 @@ mod tests {
         MmlEvent, MmlNote,
         parser::bridge_to_mml::{
             bridge_events_to_raw_mml_events, fix_event_position, fix_events_position,
-            has_a_connect_chord_event, normalize_events, update_chord_duration,
+            has_a_connect_chord_event, normalize_events, update_chord_duration, update_velocities,
         },
         test_utils::{self, MIDI_PATHS},
         utils::compute_position_in_smallest_unit,
@@ -418,12 +437,19 @@ mod tests {
         let (mut events, _) = bridge_events_to_raw_mml_events(&bridge_events, &options, ppq);
         normalize_events(&mut events);
 
-        let i = fix_event_position(&mut events, 112);
-        let e = events.get(i).unwrap();
-        debug!("Event: {e:?} at {i}");
-        let computed = compute_position_in_smallest_unit(&events, i);
+        let e = events.get(112).unwrap().to_owned();
+        debug!("Event: {e:?} at 112");
         let expected = e.get_position().unwrap();
-        assert_eq!(computed, expected);
+
+        let mut before = events[..112].to_vec();
+        let mut position = compute_position_in_smallest_unit(&before, before.len());
+        fix_event_position(&mut before, &mut position, expected);
+
+        assert_eq!(position, expected);
+        assert_eq!(
+            compute_position_in_smallest_unit(&before, before.len()),
+            expected
+        );
     }
 
     #[test]
@@ -497,4 +523,35 @@ mod tests {
             }
         }
     }
+
+    #[test]
+    fn test_update_velocities() {
+        for path in MIDI_PATHS {
+            let (bridge_events, options, ppq) = test_utils::setup_bridge_events(path);
+            let (mut events, _) = bridge_events_to_raw_mml_events(&bridge_events, &options, ppq);
+            normalize_events(&mut events);
+            fix_events_position(&mut events);
+            update_velocities(&mut events, &options);
+
+            // Every velocity command changes the velocity to the one of the next note
+            let mut velocity: Option<u8> = None;
+            let mut command: Option<u8> = None;
+            for event in events.iter() {
+                match event {
+                    MmlEvent::Velocity(new_velocity) => {
+                        assert_ne!(velocity, Some(*new_velocity), "{path}");
+                        assert_eq!(command, None, "{path}");
+                        command = Some(*new_velocity);
+                    }
+                    MmlEvent::Note(note) => {
+                        if let Some(command) = command.take() {
+                            velocity = Some(command);
+                        }
+                        assert_eq!(velocity, Some(note.velocity), "{path}");
+                    }
+                    _ => (),
+                }
+            }
+        }
+    }
 }
@@ -13,19 +13,25 @@ pub fn bridge_meta_from_midi_track(midi_track: &MidiTrack) -> Vec<BridgeEvent> {
         let delta = midi_event.delta.as_int() as usize;
         current_ticks += delta;
 
-        if let TrackEventKind::Meta(message) = midi_event.kind
-            && let MetaMessage::Tempo(tempo) = message
-        {
-            let tempo = 60_000_000 / tempo.as_int();
-
-            meta_events.push(BridgeEvent::Tempo(
-                tempo,
-                MidiState {
-                    position_in_tick: current_ticks,
-                    duration_in_tick: 0,
-                    channel: 0,
-                },
-            ));
+        let state = MidiState {
+            position_in_tick: current_ticks,
+            duration_in_tick: 0,
+            channel: 0,
+        };
+
+        match midi_event.kind {
+            TrackEventKind::Meta(MetaMessage::Tempo(tempo)) => {
+                // A tempo of zero microseconds is rejected by `MmlSong`
+                let tempo = 60_000_000u32
+                    .checked_div(tempo.as_int())
+                    .unwrap_or_default();
+                meta_events.push(BridgeEvent::Tempo(tempo, state));
+            }
+            TrackEventKind::Meta(MetaMessage::TimeSignature(numerator, denominator, _, _)) => {
+                let denominator = 1u8.checked_shl(denominator as u32).unwrap_or(4);
+                meta_events.push(BridgeEvent::TimeSignature(numerator, denominator, state));
+            }
+            _ => (),
         }
     }
 
@@ -168,6 +174,30 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_bridge_meta_from_midi_track_time_signature() {
+        let time_signature = create_midi_event(
+            960,
+            TrackEventKind::Meta(MetaMessage::TimeSignature(6, 3, 24, 8)),
+        );
+
+        let track = vec![time_signature];
+        let meta_events = bridge_meta_from_midi_track(&track);
+
+        assert_eq!(
+            meta_events,
+            vec![BridgeEvent::TimeSignature(
+                6,
+                8,
+                MidiState {
+                    position_in_tick: 960,
+                    duration_in_tick: 0,
+                    channel: 0,
+                }
+            )]
+        );
+    }
+
     #[test]
     fn test_bridge_meta_from_midi_track_no_tempo() {
         // Track with no tempo events
@@ -0,0 +1,143 @@
+use crate::{
+    Instrument,
+    mml_event::{BridgeEvent, MidiNoteState, MidiState},
+    syntax::{MAX_SMALLEST_UNIT, MmlNode, timed_notes, timed_tempos},
+    utils,
+};
+
+pub fn bridge_meta_from_mml_nodes(nodes: &[MmlNode], ppq: u16) -> Vec<BridgeEvent> {
+    timed_tempos(nodes, MAX_SMALLEST_UNIT)
+        .into_iter()
+        .map(|(position, tempo)| {
+            BridgeEvent::Tempo(
+                tempo,
+                MidiState {
+                    position_in_tick: utils::smallest_unit_to_tick(
+                        position,
+                        ppq,
+                        MAX_SMALLEST_UNIT,
+                    ),
+                    duration_in_tick: 0,
+                    channel: 0,
+                },
+            )
+        })
+        .collect()
+}
+
+pub fn bridge_notes_from_mml_nodes(
+    nodes: &[MmlNode],
+    instrument: &Instrument,
+    ppq: u16,
+) -> Vec<BridgeEvent> {
+    let channel = instrument.midi_channel;
+    let mut note_events: Vec<BridgeEvent> = vec![BridgeEvent::ProgramChange(
+        instrument.to_owned(),
+        MidiState {
+            position_in_tick: 0,
+            duration_in_tick: 0,
+            channel,
+        },
+    )];
+
+    for note in timed_notes(nodes, MAX_SMALLEST_UNIT).into_iter() {
+        if let Some(key) = note.midi_key
+            && note.duration_in_smallest_unit > 0
+        {
+            let start = note.position_in_smallest_unit;
+            let end = start + note.duration_in_smallest_unit;
+            let position_in_tick = utils::smallest_unit_to_tick(start, ppq, MAX_SMALLEST_UNIT);
+            let end_in_tick = utils::smallest_unit_to_tick(end, ppq, MAX_SMALLEST_UNIT);
+
+            note_events.push(BridgeEvent::Note(MidiNoteState {
+                key,
+                velocity: utils::mml_velocity_to_midi_velocity(note.velocity),
+                midi_state: MidiState {
+                    position_in_tick,
+                    duration_in_tick: end_in_tick - position_in_tick,
+                    channel,
+                },
+            }));
+        }
+    }
+
+    note_events
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::syntax::parse;
+
+    #[test]
+    fn test_bridge_notes_from_mml_nodes() {
+        let parsed = parse("v15o4c4:e8r8>c16.&c32");
+        let instrument = Instrument::new(24, 1);
+        let events = bridge_notes_from_mml_nodes(&parsed.nodes, &instrument, 480);
+
+        let summary: Vec<(u8, u8, usize, usize)> = events
+            .iter()
+            .filter_map(|event| match event {
+                BridgeEvent::Note(note) => Some((
+                    note.key,
+                    note.velocity,
+                    note.midi_state.position_in_tick,
+                    note.midi_state.duration_in_tick,
+                )),
+                _ => None,
+            })
+            .collect();
+
+        assert_eq!(
+            summary,
+            vec![(60, 127, 0, 480), (64, 127, 0, 240), (72, 127, 720, 240)]
+        );
+        assert_eq!(
+            events.first(),
+            Some(&BridgeEvent::ProgramChange(
+                instrument,
+                MidiState {
+                    position_in_tick: 0,
+                    duration_in_tick: 0,
+                    channel: 1,
+                },
+            ))
+        );
+    }
+
+    #[test]
+    fn test_bridge_notes_from_chord_with_rest() {
+        let parsed = parse("d+4&d+16.&d+64:r64f+4&f+16.&f+64c4");
+        let events = bridge_notes_from_mml_nodes(&parsed.nodes, &Instrument::default(), 480);
+
+        let summary: Vec<(u8, usize, usize)> = events
+            .iter()
+            .filter_map(|event| match event {
+                BridgeEvent::Note(note) => Some((
+                    note.key,
+                    note.midi_state.position_in_tick,
+                    note.midi_state.duration_in_tick,
+                )),
+                _ => None,
+            })
+            .collect();
+
+        assert_eq!(summary, vec![(63, 0, 690), (66, 0, 690), (60, 720, 480)]);
+    }
+
+    #[test]
+    fn test_bridge_meta_from_mml_nodes() {
+        let parsed = parse("t150c4t90c8");
+        let events = bridge_meta_from_mml_nodes(&parsed.nodes, 480);
+
+        let tempos: Vec<(u32, usize)> = events
+            .iter()
+            .filter_map(|event| match event {
+                BridgeEvent::Tempo(tempo, state) => Some((*tempo, state.position_in_tick)),
+                _ => None,
+            })
+            .collect();
+
+        assert_eq!(tempos, vec![(150, 0), (90, 480)]);
+    }
+}
@@ -0,0 +1,257 @@
+use std::collections::BTreeMap;
+
+use midly::{
+    MetaMessage, MidiMessage, TrackEvent, TrackEventKind,
+    num::{u4, u7, u24, u28},
+};
+
+use crate::{Instrument, mml_event::MmlEvent, utils};
+
+/// Writes the quantized events of a track as MIDI events.
+/// Tempo events are written only when `include_tempo` is true.
+pub fn mml_events_to_midi_track(
+    events: &[MmlEvent],
+    instrument: &Instrument,
+    ppq: u16,
+    smallest_unit: usize,
+    include_tempo: bool,
+) -> Vec<TrackEvent<'static>> {
+    let channel = u4::new(instrument.midi_channel);
+
+    // (tick, is note on, event), note offs are written before note ons at the same tick
+    let mut timed_events: Vec<(usize, bool, TrackEventKind<'static>)> = vec![(
+        0,
+        false,
+        TrackEventKind::Midi {
+            channel,
+            message: MidiMessage::ProgramChange {
+                program: u7::new(instrument.instrument_id),
+            },
+        },
+    )];
+
+    let mut position = 0usize;
+    let mut chord_position = 0usize;
+    let mut velocity = 0u8;
+
+    for event in events.iter() {
+        match event {
+            MmlEvent::Note(note) => {
+                if !note.is_part_of_chord {
+                    chord_position = position;
+                    position += note.duration_in_smallest_unit;
+                }
+
+                let key = u7::new(note.midi_state.key);
+                let start = utils::smallest_unit_to_tick(chord_position, ppq, smallest_unit);
+                let end = utils::smallest_unit_to_tick(
+                    chord_position + note.duration_in_smallest_unit,
+                    ppq,
+                    smallest_unit,
+                );
+
+                // A note on with velocity 0 is a note off
+                let vel = u7::new(utils::mml_velocity_to_midi_velocity(velocity).max(1));
+
+                timed_events.push((
+                    start,
+                    true,
+                    TrackEventKind::Midi {
+                        channel,
+                        message: MidiMessage::NoteOn { key, vel },
+                    },
+                ));
+                timed_events.push((
+                    end,
+                    false,
+                    TrackEventKind::Midi {
+                        channel,
+                        message: MidiMessage::NoteOff { key, vel },
+                    },
+                ));
+            }
+            MmlEvent::Rest(rest) => position += rest,
+            MmlEvent::Velocity(vel) => velocity = *vel,
+            _ => (),
+        }
+    }
+
+    if include_tempo {
+        for (tick, tempo) in get_tempo_ticks(events, ppq, smallest_unit) {
+            timed_events.push((tick, false, tempo_event_kind(tempo)));
+        }
+    }
+
+    timed_events.sort_by_key(|(tick, is_note_on, _)| (*tick, *is_note_on));
+    to_delta_track(timed_events)
+}
+
+/// Writes a conductor track with the tempo events of all given tracks.
+/// A tempo written by several tracks at the same tick is written once.
+pub fn mml_tempos_to_midi_track(
+    tracks: &[&[MmlEvent]],
+    ppq: u16,
+    smallest_unit: usize,
+) -> Vec<TrackEvent<'static>> {
+    let mut tempos: BTreeMap<usize, u32> = BTreeMap::new();
+
+    for events in tracks.iter() {
+        for (tick, tempo) in get_tempo_ticks(events, ppq, smallest_unit) {
+            tempos.entry(tick).or_insert(tempo);
+        }
+    }
+
+    let timed_events = tempos
+        .into_iter()
+        .map(|(tick, tempo)| (tick, false, tempo_event_kind(tempo)))
+        .collect();
+
+    to_delta_track(timed_events)
+}
+
+fn get_tempo_ticks(events: &[MmlEvent], ppq: u16, smallest_unit: usize) -> Vec<(usize, u32)> {
+    let mut tempos: Vec<(usize, u32)> = Vec::new();
+    let mut position = 0usize;
+
+    for event in events.iter() {
+        match event {
+            MmlEvent::Note(note) if !note.is_part_of_chord => {
+                position += note.duration_in_smallest_unit
+            }
+            MmlEvent::Rest(rest) => position += rest,
+            MmlEvent::Tempo(tempo, _) if *tempo > 0 => {
+                let tick = utils::smallest_unit_to_tick(position, ppq, smallest_unit);
+                tempos.push((tick, *tempo));
+            }
+            _ => (),
+        }
+    }
+
+    tempos
+}
+
+/// Slowest tempo a MIDI tempo event can hold, slower tempos are written as this one.
+const MIN_SMF_TEMPO: u32 = 4;
+
+fn tempo_event_kind(tempo: u32) -> TrackEventKind<'static> {
+    // Microseconds per quarter note, on 24 bits
+    let tempo = tempo.max(MIN_SMF_TEMPO);
+    TrackEventKind::Meta(MetaMessage::Tempo(u24::new(60_000_000 / tempo)))
+}
+
+fn to_delta_track(
+    timed_events: Vec<(usize, bool, TrackEventKind<'static>)>,
+) -> Vec<TrackEvent<'static>> {
+    let mut track: Vec<TrackEvent<'static>> = Vec::with_capacity(timed_events.len() + 1);
+    let mut current_tick = 0usize;
+
+    for (tick, _, kind) in timed_events.into_iter() {
+        track.push(TrackEvent {
+            delta: u28::new((tick - current_tick) as u32),
+            kind,
+        });
+        current_tick = tick;
+    }
+
+    track.push(TrackEvent {
+        delta: u28::new(0),
+        kind: TrackEventKind::Meta(MetaMessage::EndOfTrack),
+    });
+
+    track
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        MmlSongOptions,
+        test_utils::{create_track, note, tempo},
+    };
+
+    fn summarize(events: &[TrackEvent]) -> Vec<(u32, String)> {
+        events
+            .iter()
+            .map(|event| {
+                let kind = match event.kind {
+                    TrackEventKind::Midi {
+                        message: MidiMessage::NoteOn { key, .. },
+                        ..
+                    } => format!("on {key}"),
+                    TrackEventKind::Midi {
+                        message: MidiMessage::NoteOff { key, .. },
+                        ..
+                    } => format!("off {key}"),
+                    TrackEventKind::Midi {
+                        message: MidiMessage::ProgramChange { program },
+                        ..
+                    } => format!("program {program}"),
+                    TrackEventKind::Meta(MetaMessage::Tempo(tempo)) => format!("tempo {tempo}"),
+                    TrackEventKind::Meta(MetaMessage::EndOfTrack) => String::from("end"),
+                    _ => String::from("?"),
+                };
+                (event.delta.as_int(), kind)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_mml_events_to_midi_track() {
+        let track = create_track(
+            vec![tempo(120, 0)],
+            vec![note(60, 0, 470), note(64, 0, 480), note(67, 960, 240)],
+            &MmlSongOptions::default(),
+        );
+
+        let events = mml_events_to_midi_track(&track.events, &track.instrument, 480, 64, true);
+        let summary = summarize(&events);
+
+        assert_eq!(
+            summary,
+            vec![
+                (0, String::from("program 0")),
+                (0, String::from("tempo 500000")),
+                (0, String::from("on 60")),
+                (0, String::from("on 64")),
+                (480, String::from("off 60")),
+                (0, String::from("off 64")),
+                (480, String::from("on 67")),
+                (240, String::from("off 67")),
+                (0, String::from("end")),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_mml_tempos_to_midi_track() {
+        let meta_events = vec![tempo(120, 0), tempo(90, 960)];
+        let options = MmlSongOptions::default();
+
+        let track_a = create_track(meta_events.to_owned(), vec![note(60, 0, 1920)], &options);
+        let track_b = create_track(
+            meta_events,
+            vec![note(64, 0, 480), note(65, 480, 1440)],
+            &options,
+        );
+
+        let events = mml_tempos_to_midi_track(&[&track_a.events, &track_b.events], 480, 64);
+
+        assert_eq!(
+            summarize(&events),
+            vec![
+                (0, String::from("tempo 500000")),
+                (960, String::from("tempo 666666")),
+                (0, String::from("end")),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_slow_tempo_is_clamped() {
+        let TrackEventKind::Meta(MetaMessage::Tempo(tempo)) = tempo_event_kind(1) else {
+            panic!("not a tempo event");
+        };
+        assert_eq!(tempo.as_int(), 60_000_000 / MIN_SMF_TEMPO);
+        assert!(tempo.as_int() <= u24::max_value().as_int());
+    }
+}
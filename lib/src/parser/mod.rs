@@ -1,5 +1,12 @@
 mod bridge_to_mml;
 mod midi_to_bridge;
+mod mml_to_bridge;
+mod mml_to_midi;
 
-pub use self::bridge_to_mml::bridge_events_to_mml_events;
+pub use self::bridge_to_mml::{
+    bridge_events_to_raw_mml_events, fix_events_position, normalize_events, update_chord_duration,
+    update_note_mml, update_velocities,
+};
 pub use self::midi_to_bridge::{bridge_meta_from_midi_track, bridge_notes_from_midi_track};
+pub use self::mml_to_bridge::{bridge_meta_from_mml_nodes, bridge_notes_from_mml_nodes};
+pub use self::mml_to_midi::{mml_events_to_midi_track, mml_tempos_to_midi_track};
@@ -0,0 +1,457 @@
+use std::{fmt, ops::RangeBounds, sync::Arc};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    MmlSongOptions,
+    mml_event::{BridgeEvent, MmlEvent},
+    parser::{
+        fix_events_position, normalize_events, update_chord_duration, update_note_mml,
+        update_velocities,
+    },
+};
+
+/// What a pass knows about the track it runs on.
+#[derive(Debug, Clone, Copy)]
+pub struct PassContext<'a> {
+    /// Song options with the overrides of the track applied
+    pub options: &'a MmlSongOptions,
+    pub ppq: u16,
+}
+
+/// Changes the MIDI events of a track before they are converted to MML,
+/// like removing grace notes or quantizing.
+/// The events are sorted by position again after every pass.
+pub trait BridgePass: Send + Sync {
+    /// Saved in projects, see `PassPipeline::unrestored_passes`.
+    fn name(&self) -> &str;
+
+    /// Settings of the pass, passes with the same name and key run the same way.
+    /// `None`, the default, when they cannot be compared: the pass only equals itself,
+    /// so a pass built again with other settings converts the song again.
+    fn config_key(&self) -> Option<String> {
+        None
+    }
+
+    fn run(&self, events: &mut Vec<BridgeEvent>, context: &PassContext);
+}
+
+/// Changes the MML events of a track, the built-in passes fix the positions and write the notes.
+pub trait MmlPass: Send + Sync {
+    /// Saved in projects, see `PassPipeline::unrestored_passes`.
+    fn name(&self) -> &str;
+
+    /// Settings of the pass, see `BridgePass::config_key`.
+    fn config_key(&self) -> Option<String> {
+        None
+    }
+
+    fn run(&self, events: &mut Vec<MmlEvent>, context: &PassContext);
+}
+
+/// Removes empty notes and rests and the first of two tempos in a row, and connects chord notes.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct NormalizeEvents;
+
+impl MmlPass for NormalizeEvents {
+    fn name(&self) -> &str {
+        "normalize_events"
+    }
+
+    fn config_key(&self) -> Option<String> {
+        Some(String::new())
+    }
+
+    fn run(&self, events: &mut Vec<MmlEvent>, _context: &PassContext) {
+        normalize_events(events);
+    }
+}
+
+/// Adds rests and shortens notes so every note and tempo starts at its position.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct FixEventsPosition;
+
+impl MmlPass for FixEventsPosition {
+    fn name(&self) -> &str {
+        "fix_events_position"
+    }
+
+    fn config_key(&self) -> Option<String> {
+        Some(String::new())
+    }
+
+    fn run(&self, events: &mut Vec<MmlEvent>, _context: &PassContext) {
+        fix_events_position(events);
+    }
+}
+
+/// Gives the notes of a chord the duration of its first note.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct UpdateChordDuration;
+
+impl MmlPass for UpdateChordDuration {
+    fn name(&self) -> &str {
+        "update_chord_duration"
+    }
+
+    fn config_key(&self) -> Option<String> {
+        Some(String::new())
+    }
+
+    fn run(&self, events: &mut Vec<MmlEvent>, _context: &PassContext) {
+        update_chord_duration(events);
+    }
+}
+
+/// Writes the MML of the notes from their durations, after the positions are fixed.
+/// Every pipeline needs it, the notes have no MML without it.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct UpdateNoteMml;
+
+impl MmlPass for UpdateNoteMml {
+    fn name(&self) -> &str {
+        "update_note_mml"
+    }
+
+    fn config_key(&self) -> Option<String> {
+        Some(String::new())
+    }
+
+    fn run(&self, events: &mut Vec<MmlEvent>, context: &PassContext) {
+        update_note_mml(events, context.options.smallest_unit);
+    }
+}
+
+/// Converts the velocities of the notes with the song options and keeps only the velocity
+/// commands that change the velocity, the last built-in pass.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct UpdateVelocities;
+
+impl MmlPass for UpdateVelocities {
+    fn name(&self) -> &str {
+        "update_velocities"
+    }
+
+    fn config_key(&self) -> Option<String> {
+        Some(String::new())
+    }
+
+    fn run(&self, events: &mut Vec<MmlEvent>, context: &PassContext) {
+        update_velocities(events, context.options);
+    }
+}
+
+/// The passes a track is converted with, in order.
+/// The default has no bridge pass and the built-in MML passes.
+/// Saved as the names of its passes, only the built-in ones can be read back.
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(from = "PassNames", into = "PassNames")]
+pub struct PassPipeline {
+    bridge_passes: Vec<Arc<dyn BridgePass>>,
+    mml_passes: Vec<Arc<dyn MmlPass>>,
+
+    /// Names of the passes that were saved but are not built-in
+    unrestored: Vec<String>,
+}
+
+/// How a pipeline is saved, the built-in MML passes when they are left out.
+#[derive(Serialize, Deserialize)]
+struct PassNames {
+    #[serde(default)]
+    bridge_passes: Vec<String>,
+
+    #[serde(default = "default_mml_pass_names")]
+    mml_passes: Vec<String>,
+}
+
+fn default_mml_pass_names() -> Vec<String> {
+    PassNames::from(PassPipeline::default()).mml_passes
+}
+
+impl Default for PassPipeline {
+    fn default() -> Self {
+        Self {
+            bridge_passes: Vec::new(),
+            mml_passes: vec![
+                Arc::new(NormalizeEvents),
+                Arc::new(FixEventsPosition),
+                Arc::new(NormalizeEvents),
+                Arc::new(UpdateChordDuration),
+                Arc::new(UpdateNoteMml),
+                Arc::new(UpdateVelocities),
+            ],
+            unrestored: Vec::new(),
+        }
+    }
+}
+
+impl From<PassNames> for PassPipeline {
+    fn from(names: PassNames) -> Self {
+        let mut pipeline = Self::empty();
+        pipeline.unrestored = names.bridge_passes;
+
+        for name in names.mml_passes {
+            let pass: Arc<dyn MmlPass> = match name.as_str() {
+                "normalize_events" => Arc::new(NormalizeEvents),
+                "fix_events_position" => Arc::new(FixEventsPosition),
+                "update_chord_duration" => Arc::new(UpdateChordDuration),
+                "update_note_mml" => Arc::new(UpdateNoteMml),
+                "update_velocities" => Arc::new(UpdateVelocities),
+                _ => {
+                    pipeline.unrestored.push(name);
+                    continue;
+                }
+            };
+            pipeline.mml_passes.push(pass);
+        }
+
+        pipeline
+    }
+}
+
+impl From<PassPipeline> for PassNames {
+    fn from(pipeline: PassPipeline) -> Self {
+        let to_strings = |names: Vec<&str>| names.into_iter().map(String::from).collect();
+
+        Self {
+            bridge_passes: to_strings(pipeline.bridge_pass_names()),
+            mml_passes: to_strings(pipeline.mml_pass_names()),
+        }
+    }
+}
+
+impl PassPipeline {
+    /// No pass at all, the MML of the notes is not written.
+    pub fn empty() -> Self {
+        Self {
+            bridge_passes: Vec::new(),
+            mml_passes: Vec::new(),
+            unrestored: Vec::new(),
+        }
+    }
+
+    pub fn is_default(&self) -> bool {
+        *self == Self::default()
+    }
+
+    pub fn bridge_pass_names(&self) -> Vec<&str> {
+        self.bridge_passes.iter().map(|pass| pass.name()).collect()
+    }
+
+    pub fn mml_pass_names(&self) -> Vec<&str> {
+        self.mml_passes.iter().map(|pass| pass.name()).collect()
+    }
+
+    pub(crate) fn mml_pass_count(&self) -> usize {
+        self.mml_passes.len()
+    }
+
+    pub fn push_bridge_pass(&mut self, pass: impl BridgePass + 'static) {
+        self.bridge_passes.push(Arc::new(pass));
+    }
+
+    pub fn push_mml_pass(&mut self, pass: impl MmlPass + 'static) {
+        self.mml_passes.push(Arc::new(pass));
+    }
+
+    /// Panics when `index` is above the number of MML passes.
+    pub fn insert_mml_pass(&mut self, index: usize, pass: impl MmlPass + 'static) {
+        self.mml_passes.insert(index, Arc::new(pass));
+    }
+
+    /// Removes every MML pass named `name`, returns whether one was removed.
+    pub fn remove_mml_pass(&mut self, name: &str) -> bool {
+        let count = self.mml_passes.len();
+        self.mml_passes.retain(|pass| pass.name() != name);
+        self.mml_passes.len() != count
+    }
+
+    /// Custom passes of a saved pipeline, left out when it was read back.
+    /// Options with such a pipeline are rejected by `MmlSongOptions::validate`.
+    pub fn unrestored_passes(&self) -> &[String] {
+        &self.unrestored
+    }
+
+    pub fn has_bridge_passes(&self) -> bool {
+        !self.bridge_passes.is_empty()
+    }
+
+    pub fn run_bridge_passes(&self, events: &mut Vec<BridgeEvent>, context: &PassContext) {
+        for pass in self.bridge_passes.iter() {
+            pass.run(events, context);
+            events.sort();
+        }
+    }
+
+    pub fn run_mml_passes(&self, events: &mut Vec<MmlEvent>, context: &PassContext) {
+        self.run_mml_passes_in(.., events, context);
+    }
+
+    /// Runs the MML passes at the indexes of `passes` only.
+    pub(crate) fn run_mml_passes_in(
+        &self,
+        passes: impl RangeBounds<usize>,
+        events: &mut Vec<MmlEvent>,
+        context: &PassContext,
+    ) {
+        let passes = (passes.start_bound().cloned(), passes.end_bound().cloned());
+        for pass in self.mml_passes[passes].iter() {
+            pass.run(events, context);
+        }
+    }
+}
+
+/// The same pass, or passes with the same name and settings.
+fn is_same_pass<T: ?Sized>(
+    pass: &Arc<T>,
+    other: &Arc<T>,
+    get_key: impl Fn(&T) -> (String, Option<String>),
+) -> bool {
+    if Arc::ptr_eq(pass, other) {
+        return true;
+    }
+
+    let (name, key) = get_key(pass);
+    let (other_name, other_key) = get_key(other);
+    name == other_name && key.is_some() && key == other_key
+}
+
+impl PartialEq for PassPipeline {
+    fn eq(&self, other: &Self) -> bool {
+        self.bridge_passes.len() == other.bridge_passes.len()
+            && self.mml_passes.len() == other.mml_passes.len()
+            && self.unrestored == other.unrestored
+            && (self.bridge_passes.iter())
+                .zip(other.bridge_passes.iter())
+                .all(|(pass, other)| {
+                    is_same_pass(pass, other, |pass| {
+                        (pass.name().to_owned(), pass.config_key())
+                    })
+                })
+            && (self.mml_passes.iter())
+                .zip(other.mml_passes.iter())
+                .all(|(pass, other)| {
+                    is_same_pass(pass, other, |pass| {
+                        (pass.name().to_owned(), pass.config_key())
+                    })
+                })
+    }
+}
+
+impl Eq for PassPipeline {}
+
+impl fmt::Debug for PassPipeline {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("PassPipeline")
+            .field("bridge_passes", &self.bridge_pass_names())
+            .field("mml_passes", &self.mml_pass_names())
+            .field("unrestored", &self.unrestored)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct RemoveRests;
+
+    impl MmlPass for RemoveRests {
+        fn name(&self) -> &str {
+            "remove_rests"
+        }
+
+        fn run(&self, events: &mut Vec<MmlEvent>, _context: &PassContext) {
+            events.retain(|event| !matches!(event, MmlEvent::Rest(_)));
+        }
+    }
+
+    #[test]
+    fn test_pass_pipeline() {
+        let mut pipeline = PassPipeline::default();
+        assert!(pipeline.is_default());
+        assert_eq!(
+            pipeline.mml_pass_names(),
+            vec![
+                "normalize_events",
+                "fix_events_position",
+                "normalize_events",
+                "update_chord_duration",
+                "update_note_mml",
+                "update_velocities"
+            ]
+        );
+
+        pipeline.insert_mml_pass(4, RemoveRests);
+        assert!(!pipeline.is_default());
+        assert_eq!(pipeline.mml_pass_names()[4], "remove_rests");
+
+        let options = MmlSongOptions::default();
+        let context = PassContext {
+            options: &options,
+            ppq: 480,
+        };
+        let mut events = vec![MmlEvent::Rest(4), MmlEvent::Octave(5), MmlEvent::Rest(0)];
+        pipeline.run_mml_passes(&mut events, &context);
+        assert!(matches!(events[..], [MmlEvent::Octave(5)]));
+
+        assert!(pipeline.remove_mml_pass("remove_rests"));
+        assert!(!pipeline.remove_mml_pass("remove_rests"));
+        assert_eq!(pipeline, PassPipeline::default());
+        assert_ne!(PassPipeline::empty(), PassPipeline::default());
+    }
+
+    struct Transpose(i8);
+
+    impl MmlPass for Transpose {
+        fn name(&self) -> &str {
+            "transpose"
+        }
+
+        fn run(&self, events: &mut Vec<MmlEvent>, context: &PassContext) {
+            for event in events.iter_mut() {
+                if let MmlEvent::Note(note) = event {
+                    let key = (note.midi_state.key as i16 + self.0 as i16).clamp(0, 127);
+                    note.apply_keymap(key as u8, context.options.smallest_unit);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_pipeline_eq() {
+        let mut pipeline = PassPipeline::default();
+        pipeline.push_mml_pass(Transpose(2));
+        assert_eq!(pipeline.clone(), pipeline);
+
+        // The same custom pass built again may have other settings
+        let mut other = PassPipeline::default();
+        other.push_mml_pass(Transpose(2));
+        assert_ne!(other, pipeline);
+    }
+
+    #[test]
+    fn test_pipeline_json() {
+        let json = serde_json::to_string(&PassPipeline::default()).unwrap();
+        let pipeline: PassPipeline = serde_json::from_str(&json).unwrap();
+        assert!(pipeline.is_default());
+        assert!(pipeline.unrestored_passes().is_empty());
+
+        // Left out MML passes are the built-in ones, an empty list is kept
+        let pipeline: PassPipeline = serde_json::from_str("{}").unwrap();
+        assert!(pipeline.is_default());
+        let pipeline: PassPipeline = serde_json::from_str(r#"{"mml_passes": []}"#).unwrap();
+        assert_eq!(pipeline, PassPipeline::empty());
+
+        let mut pipeline = PassPipeline::default();
+        pipeline.push_mml_pass(Transpose(-12));
+        let json = serde_json::to_string(&pipeline).unwrap();
+        let pipeline: PassPipeline = serde_json::from_str(&json).unwrap();
+        assert_eq!(pipeline.unrestored_passes(), ["transpose"]);
+        assert_eq!(
+            pipeline.mml_pass_names(),
+            PassPipeline::default().mml_pass_names()
+        );
+        assert!(!pipeline.is_default());
+    }
+}
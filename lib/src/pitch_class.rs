@@ -1,6 +1,10 @@
 use std::fmt::Display;
 
-#[derive(Debug, Clone, Eq, PartialEq)]
+use serde::{Deserialize, Serialize};
+
+use crate::dialect::{MmlDialect, RevelationDialect};
+
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
 pub enum PitchClass {
     C,
     Db,
@@ -19,22 +23,6 @@ pub enum PitchClass {
 
 impl Display for PitchClass {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        let display_str = match self {
-            Self::C => "c",
-            Self::Db => "c+",
-            Self::D => "d",
-            Self::Eb => "d+",
-            Self::E => "e",
-            Self::F => "f",
-            Self::Gb => "f+",
-            Self::G => "g",
-            Self::Ab => "g+",
-            Self::A => "a",
-            Self::Bb => "a+",
-            Self::B => "b",
-            Self::Rest => "r",
-        };
-
-        write!(f, "{}", display_str)
+        write!(f, "{}", RevelationDialect.pitch(self))
     }
 }
@@ -0,0 +1,223 @@
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    MmlTrack,
+    mml_event::{BridgeEvent, MmlEvent},
+    syntax::{TokenKind, tokenize},
+    utils,
+};
+
+/// Layout of `MmlTrack::to_pretty_mml`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PrettyMmlOptions {
+    /// Bars written on each line, a time signature change always starts a new line
+    pub bars_per_line: usize,
+
+    /// Labels the track, the time signatures and the first bar of each line with comments
+    pub comments: bool,
+}
+
+impl Default for PrettyMmlOptions {
+    fn default() -> Self {
+        Self {
+            bars_per_line: 4,
+            comments: true,
+        }
+    }
+}
+
+/// The MML of the track with bars separated by spaces and lines.
+/// Removing the whitespace and the comments with `compact_pretty_mml` gives `MmlTrack::to_mml` back.
+pub fn pretty_track(track: &MmlTrack, options: &PrettyMmlOptions) -> String {
+    let smallest_unit = track.song_options.smallest_unit;
+    let bars_per_line = options.bars_per_line.max(1);
+    let bar_lengths = utils::get_bar_lengths_in_tick(&track.bridge_meta_events, track.ppq);
+    let time_signatures = get_time_signatures(track, &bar_lengths);
+    let get_bar = |position: usize| {
+        let tick = utils::smallest_unit_to_tick(position, track.ppq, smallest_unit);
+        utils::tick_to_bar(&bar_lengths, tick)
+    };
+
+    let mut mml = String::new();
+    if options.comments {
+        mml.push_str(&format!("/* {} */\n", escape_comment(&track.name)));
+    }
+    push_line_start(&mut mml, None, 0, &time_signatures, options.comments);
+
+    let mut position = 0usize;
+    let mut bar = 0usize;
+    let mut line_bar = 0usize;
+
+    // Between a `:` and its note, the chord must stay in one piece
+    let mut is_in_chord = false;
+
+    for event in track.events.iter() {
+        let is_chord_event = match event {
+            MmlEvent::ConnectChord => true,
+            MmlEvent::Note(note) => note.is_part_of_chord,
+            _ => false,
+        };
+
+        if !is_in_chord && !is_chord_event {
+            let event_bar = get_bar(position);
+
+            if event_bar > bar {
+                let has_time_signature = time_signatures
+                    .iter()
+                    .any(|(start, _)| *start > bar && *start <= event_bar);
+
+                if has_time_signature || event_bar - line_bar >= bars_per_line {
+                    mml.push('\n');
+                    push_line_start(
+                        &mut mml,
+                        Some(bar),
+                        event_bar,
+                        &time_signatures,
+                        options.comments,
+                    );
+                    line_bar = event_bar;
+                } else {
+                    mml.push(' ');
+                }
+                bar = event_bar;
+            }
+        }
+
+        mml.push_str(&event.to_mml(smallest_unit));
+
+        match event {
+            MmlEvent::ConnectChord => is_in_chord = true,
+            MmlEvent::Note(note) if note.is_part_of_chord => is_in_chord = false,
+            _ => (),
+        }
+        if !event.is_part_of_chord()
+            && let Some(duration) = event.get_duration()
+        {
+            position += duration;
+        }
+    }
+
+    mml.push('\n');
+    mml
+}
+
+/// `(first bar, "numerator/denominator")` of every time signature.
+fn get_time_signatures(track: &MmlTrack, bar_lengths: &[(usize, usize)]) -> Vec<(usize, String)> {
+    let mut time_signatures: Vec<(usize, String)> = Vec::new();
+
+    for event in track.bridge_meta_events.iter() {
+        if let BridgeEvent::TimeSignature(numerator, denominator, state) = event {
+            let bar = utils::tick_to_bar(bar_lengths, state.position_in_tick);
+            time_signatures.retain(|(start, _)| *start != bar);
+            time_signatures.push((bar, format!("{numerator}/{denominator}")));
+        }
+    }
+    time_signatures.sort_by_key(|(start, _)| *start);
+
+    time_signatures
+}
+
+/// Labels the line with its first bar, after the time signature that starts since `previous_bar`.
+fn push_line_start(
+    mml: &mut String,
+    previous_bar: Option<usize>,
+    bar: usize,
+    time_signatures: &[(usize, String)],
+    comments: bool,
+) {
+    if !comments {
+        return;
+    }
+
+    if let Some((_, time_signature)) = time_signatures.iter().rev().find(|(start, _)| {
+        *start <= bar && previous_bar.is_none_or(|previous_bar| *start > previous_bar)
+    }) {
+        mml.push_str(&format!("/* {time_signature} */\n"));
+    }
+    mml.push_str(&format!("/* {} */ ", bar + 1));
+}
+
+/// The MML without whitespace and comments, as written by `MmlTrack::to_mml`.
+pub fn compact_pretty_mml(mml: &str) -> String {
+    tokenize(mml)
+        .into_iter()
+        .filter(|token| !matches!(token.kind, TokenKind::Whitespace | TokenKind::Comment))
+        .map(|token| &mml[token.span])
+        .collect()
+}
+
+/// A comment cannot hold its own end.
+fn escape_comment(text: &str) -> String {
+    text.replace("*/", "* /")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        MmlSongOptions,
+        test_utils::{create_track, note, state},
+    };
+
+    #[test]
+    fn test_pretty_bars() {
+        let notes = (0..10).map(|index| note(60, index * 960, 960)).collect();
+        let track = create_track(vec![], notes, &MmlSongOptions::default());
+        let options = PrettyMmlOptions {
+            bars_per_line: 2,
+            comments: false,
+        };
+
+        assert_eq!(track.to_mml(), "v11o4c2c2c2c2c2c2c2c2c2c2");
+        assert_eq!(
+            track.to_pretty_mml(&options),
+            "v11o4c2c2 c2c2\nc2c2 c2c2\nc2c2\n"
+        );
+    }
+
+    #[test]
+    fn test_pretty_comments() {
+        let meta_events = vec![BridgeEvent::TimeSignature(3, 4, state(1920, 0))];
+        let notes = vec![note(60, 0, 1920), note(64, 0, 1920), note(67, 1920, 1440)];
+        let track = create_track(meta_events, notes, &MmlSongOptions::default());
+
+        assert_eq!(
+            track.to_pretty_mml(&PrettyMmlOptions::default()),
+            "/* Lead */\n/* 1 */ v11o4c1:e1\n/* 3/4 */\n/* 2 */ g2.\n"
+        );
+    }
+
+    #[test]
+    fn test_pretty_keeps_chords_and_ties() {
+        // A chord crossing the bar line and a note tied over it
+        let notes = vec![
+            note(60, 1440, 960),
+            note(64, 1440, 960),
+            note(67, 3360, 1440),
+        ];
+        let track = create_track(vec![], notes, &MmlSongOptions::default());
+        let pretty = track.to_pretty_mml(&PrettyMmlOptions::default());
+
+        assert_eq!(compact_pretty_mml(&pretty), track.to_mml());
+        assert!(!pretty.contains(": ") && !pretty.contains(" :"));
+    }
+
+    #[test]
+    fn test_compact_pretty_mml() {
+        assert_eq!(
+            compact_pretty_mml("/* Lead */\nt120 v12o4\n/* 2 */ c4:e4  d8&d16\n"),
+            "t120v12o4c4:e4d8&d16"
+        );
+    }
+
+    #[test]
+    fn test_escape_comment() {
+        let mut track = create_track(vec![], vec![note(60, 0, 480)], &MmlSongOptions::default());
+        track.name = String::from("a */ b");
+        let pretty = track.to_pretty_mml(&PrettyMmlOptions::default());
+
+        assert!(pretty.starts_with("/* a * / b */"));
+        assert_eq!(compact_pretty_mml(&pretty), track.to_mml());
+    }
+}
@@ -0,0 +1,158 @@
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    MmlSongOptions,
+    error::{ConstraintError, Result},
+    keymap::KeymapPreset,
+    mml_song::TrackOptionsOverride,
+    tempo::TempoTransform,
+    utils::BalanceMetric,
+};
+
+/// Version written to new project files, older versions can still be opened.
+pub const PROJECT_VERSION: u32 = 1;
+
+/// An edit applied to a song, replayed in order when a project is reopened.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum SongOperation {
+    SplitTrack {
+        index: usize,
+    },
+    MergeTracks {
+        index_a: usize,
+        index_b: usize,
+    },
+    EqualizeTracks {
+        index_a: usize,
+        index_b: usize,
+    },
+    BalanceTracks {
+        indexes: Vec<usize>,
+        metric: BalanceMetric,
+    },
+    RenameTrack {
+        index: usize,
+        name: String,
+    },
+    ApplyKeymap {
+        index: usize,
+
+        /// `(from, to)` pairs sorted by key
+        keymap: Vec<(u8, u8)>,
+    },
+    /// `index` is `None` when the preset was applied to the whole song
+    ApplyKeymapPreset {
+        index: Option<usize>,
+        preset: KeymapPreset,
+    },
+    SetTrackOptions {
+        index: usize,
+        options: TrackOptionsOverride,
+    },
+    SetSongOptions {
+        options: MmlSongOptions,
+    },
+    TransformTempo {
+        transform: TempoTransform,
+    },
+}
+
+/// The source MIDI and every edit made to the song converted from it.
+/// The MIDI itself is not stored, only its hash to make sure the same file is reopened.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MmlProject {
+    pub version: u32,
+
+    /// FNV-1a hash of the MIDI bytes, as 16 hex digits
+    pub source_hash: String,
+
+    /// Options used for the first conversion, later changes are operations
+    pub options: MmlSongOptions,
+    pub operations: Vec<SongOperation>,
+}
+
+impl MmlProject {
+    pub fn new(source: &[u8], options: MmlSongOptions) -> Self {
+        Self {
+            version: PROJECT_VERSION,
+            source_hash: hash_source(source),
+            options,
+            operations: Vec::new(),
+        }
+    }
+
+    pub fn to_json(&self) -> Result<String> {
+        Ok(serde_json::to_string_pretty(self)?)
+    }
+
+    pub fn from_json(json: &str) -> Result<Self> {
+        let project: Self = serde_json::from_str(json)?;
+        if project.version > PROJECT_VERSION {
+            return Err(ConstraintError::UnsupportedVersion {
+                version: project.version,
+                supported: PROJECT_VERSION,
+            }
+            .into());
+        }
+
+        Ok(project)
+    }
+
+    /// Whether the project was created from these MIDI bytes.
+    pub fn matches_source(&self, source: &[u8]) -> bool {
+        self.source_hash == hash_source(source)
+    }
+}
+
+fn hash_source(bytes: &[u8]) -> String {
+    let hash = bytes.iter().fold(0xcbf29ce484222325u64, |hash, byte| {
+        (hash ^ *byte as u64).wrapping_mul(0x100000001b3)
+    });
+
+    format!("{hash:016x}")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hash_source() {
+        assert_eq!(hash_source(b""), "cbf29ce484222325");
+        assert_eq!(hash_source(b"a"), "af63dc4c8601ec8c");
+    }
+
+    #[test]
+    fn test_project_json() {
+        let mut project = MmlProject::new(b"midi", MmlSongOptions::default());
+        project.operations = vec![
+            SongOperation::SplitTrack { index: 0 },
+            SongOperation::RenameTrack {
+                index: 1,
+                name: String::from("Bass"),
+            },
+            SongOperation::ApplyKeymap {
+                index: 0,
+                keymap: vec![(60, 62)],
+            },
+        ];
+
+        let json = project.to_json().unwrap();
+        let reopened = MmlProject::from_json(&json).unwrap();
+
+        assert_eq!(reopened.version, PROJECT_VERSION);
+        assert_eq!(reopened.operations, project.operations);
+        assert!(reopened.matches_source(b"midi"));
+        assert!(!reopened.matches_source(b"other"));
+    }
+
+    #[test]
+    fn test_project_newer_version() {
+        let mut project = MmlProject::new(b"midi", MmlSongOptions::default());
+        project.version = PROJECT_VERSION + 1;
+        let json = project.to_json().unwrap();
+
+        assert!(MmlProject::from_json(&json).is_err());
+    }
+}
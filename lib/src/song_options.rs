@@ -0,0 +1,369 @@
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    MmlSongOptions,
+    error::{ConstraintError, MmlError, Result},
+    pass::PassPipeline,
+    syntax::MAX_SMALLEST_UNIT,
+    tempo::TempoPlacement,
+    velocity_curve::VelocityCurve,
+};
+
+/// Highest velocity of the MML `v` command.
+pub const MAX_MML_VELOCITY: u8 = 15;
+
+/// Named starting points for the song options.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OptionsPreset {
+    /// Fine timing, notes rolled within a 1/32 note are kept together as a chord.
+    PianoSolo,
+
+    /// Louder tracks and the tempo commands in one track only,
+    /// only notes starting together form chords.
+    Band,
+
+    /// 1/32 notes and notes within a 1/16 note grouped as chords, for the shortest MML.
+    Compact,
+}
+
+impl OptionsPreset {
+    pub const ALL: [Self; 3] = [Self::PianoSolo, Self::Band, Self::Compact];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::PianoSolo => "piano solo",
+            Self::Band => "band",
+            Self::Compact => "compact",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|preset| preset.name() == name)
+    }
+
+    pub fn to_options(&self) -> MmlSongOptions {
+        match self {
+            Self::PianoSolo => MmlSongOptions {
+                min_gap_for_chord: 2,
+                ..MmlSongOptions::default()
+            },
+            Self::Band => MmlSongOptions {
+                auto_boot_velocity: true,
+                tempo_placement: TempoPlacement::FirstNonEmpty,
+                ..MmlSongOptions::default()
+            },
+            Self::Compact => MmlSongOptions {
+                smallest_unit: 32,
+                min_gap_for_chord: 2,
+                tempo_placement: TempoPlacement::FirstNonEmpty,
+                ..MmlSongOptions::default()
+            },
+        }
+    }
+}
+
+impl MmlSongOptions {
+    pub fn builder() -> MmlSongOptionsBuilder {
+        MmlSongOptionsBuilder::default()
+    }
+
+    /// Fails with the first option MML cannot be written with.
+    pub fn validate(&self) -> Result<()> {
+        if !self.smallest_unit.is_power_of_two() || self.smallest_unit > MAX_SMALLEST_UNIT {
+            return Err(invalid(
+                "smallest_unit",
+                self.smallest_unit,
+                "must be a power of two up to 256",
+            ));
+        }
+        if self.velocity_max > MAX_MML_VELOCITY {
+            return Err(invalid(
+                "velocity_max",
+                self.velocity_max,
+                "must be 15 or lower",
+            ));
+        }
+        if self.velocity_min > self.velocity_max {
+            return Err(invalid(
+                "velocity_min",
+                self.velocity_min,
+                "must not be above velocity_max",
+            ));
+        }
+        if self.min_gap_for_chord as usize >= self.smallest_unit {
+            return Err(invalid(
+                "min_gap_for_chord",
+                self.min_gap_for_chord,
+                "must be shorter than a whole note in smallest units",
+            ));
+        }
+
+        if !self.pipeline.mml_pass_names().contains(&"update_note_mml") {
+            return Err(invalid(
+                "pipeline",
+                self.pipeline.mml_pass_names().join(", "),
+                "must write the notes with update_note_mml",
+            ));
+        }
+        if !self.pipeline.unrestored_passes().is_empty() {
+            return Err(ConstraintError::UnrestoredPasses {
+                names: self.pipeline.unrestored_passes().to_vec(),
+            }
+            .into());
+        }
+
+        match &self.velocity_curve {
+            VelocityCurve::Exponential { strength } | VelocityCurve::Logarithmic { strength }
+                if !strength.is_finite() || *strength <= 0. =>
+            {
+                Err(invalid(
+                    "velocity_curve",
+                    strength,
+                    "the strength must be above zero",
+                ))
+            }
+            VelocityCurve::Percentile { low, high, .. } if low >= high || *high > 100 => {
+                Err(invalid(
+                    "velocity_curve",
+                    format!("{low}-{high}"),
+                    "the percentiles must rise up to 100",
+                ))
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+fn invalid(name: &'static str, value: impl ToString, reason: &'static str) -> MmlError {
+    MmlError::Options {
+        name,
+        value: value.to_string(),
+        reason,
+    }
+}
+
+/// Song options from a preset, or the defaults, with some of them replaced.
+/// `build` checks the result with `MmlSongOptions::validate`.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MmlSongOptionsBuilder {
+    preset: Option<OptionsPreset>,
+    auto_boot_velocity: Option<bool>,
+    auto_equalize_note_length: Option<bool>,
+    velocity_min: Option<u8>,
+    velocity_max: Option<u8>,
+    min_gap_for_chord: Option<u8>,
+    smallest_unit: Option<usize>,
+    velocity_curve: Option<VelocityCurve>,
+    tempo_placement: Option<TempoPlacement>,
+    pipeline: Option<PassPipeline>,
+}
+
+impl MmlSongOptionsBuilder {
+    pub fn preset(mut self, preset: OptionsPreset) -> Self {
+        self.preset = Some(preset);
+        self
+    }
+
+    pub fn auto_boot_velocity(mut self, value: bool) -> Self {
+        self.auto_boot_velocity = Some(value);
+        self
+    }
+
+    pub fn auto_equalize_note_length(mut self, value: bool) -> Self {
+        self.auto_equalize_note_length = Some(value);
+        self
+    }
+
+    /// 0-15, not above the max velocity
+    pub fn velocity_min(mut self, value: u8) -> Self {
+        self.velocity_min = Some(value);
+        self
+    }
+
+    /// 0-15
+    pub fn velocity_max(mut self, value: u8) -> Self {
+        self.velocity_max = Some(value);
+        self
+    }
+
+    /// In smallest units, a gap of 2 with a smallest unit of 64 is a 1/32 note.
+    pub fn min_gap_for_chord(mut self, value: u8) -> Self {
+        self.min_gap_for_chord = Some(value);
+        self
+    }
+
+    /// A power of two up to 256, 64 means a 1/64 note.
+    pub fn smallest_unit(mut self, value: usize) -> Self {
+        self.smallest_unit = Some(value);
+        self
+    }
+
+    pub fn velocity_curve(mut self, value: VelocityCurve) -> Self {
+        self.velocity_curve = Some(value);
+        self
+    }
+
+    pub fn tempo_placement(mut self, value: TempoPlacement) -> Self {
+        self.tempo_placement = Some(value);
+        self
+    }
+
+    pub fn pipeline(mut self, value: PassPipeline) -> Self {
+        self.pipeline = Some(value);
+        self
+    }
+
+    pub fn build(&self) -> Result<MmlSongOptions> {
+        let base = self
+            .preset
+            .map(|preset| preset.to_options())
+            .unwrap_or_default();
+        let options = MmlSongOptions {
+            auto_boot_velocity: self.auto_boot_velocity.unwrap_or(base.auto_boot_velocity),
+            auto_equalize_note_length: self
+                .auto_equalize_note_length
+                .unwrap_or(base.auto_equalize_note_length),
+            velocity_min: self.velocity_min.unwrap_or(base.velocity_min),
+            velocity_max: self.velocity_max.unwrap_or(base.velocity_max),
+            min_gap_for_chord: self.min_gap_for_chord.unwrap_or(base.min_gap_for_chord),
+            smallest_unit: self.smallest_unit.unwrap_or(base.smallest_unit),
+            velocity_curve: self.velocity_curve.clone().unwrap_or(base.velocity_curve),
+            tempo_placement: self.tempo_placement.clone().unwrap_or(base.tempo_placement),
+            pipeline: self.pipeline.clone().unwrap_or(base.pipeline),
+        };
+        options.validate()?;
+
+        Ok(options)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{create_track, note};
+
+    fn get_invalid_name(builder: MmlSongOptionsBuilder) -> &'static str {
+        match builder.build() {
+            Err(MmlError::Options { name, .. }) => name,
+            other => panic!("expected an options error, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn test_builder_validation() {
+        let builder = MmlSongOptions::builder;
+
+        assert_eq!(builder().build().unwrap(), MmlSongOptions::default());
+        assert_eq!(
+            get_invalid_name(builder().smallest_unit(48)),
+            "smallest_unit"
+        );
+        assert_eq!(
+            get_invalid_name(builder().smallest_unit(512)),
+            "smallest_unit"
+        );
+        assert_eq!(
+            get_invalid_name(builder().smallest_unit(0)),
+            "smallest_unit"
+        );
+        assert_eq!(get_invalid_name(builder().velocity_max(16)), "velocity_max");
+        assert_eq!(
+            get_invalid_name(builder().velocity_min(10).velocity_max(5)),
+            "velocity_min"
+        );
+        assert_eq!(
+            get_invalid_name(builder().smallest_unit(16).min_gap_for_chord(16)),
+            "min_gap_for_chord"
+        );
+        assert_eq!(
+            get_invalid_name(builder().velocity_curve(VelocityCurve::Exponential { strength: 0. })),
+            "velocity_curve"
+        );
+        assert_eq!(
+            get_invalid_name(builder().velocity_curve(VelocityCurve::Percentile {
+                low: 90,
+                high: 10,
+                fitted: None
+            })),
+            "velocity_curve"
+        );
+        assert_eq!(
+            get_invalid_name(builder().pipeline(PassPipeline::empty())),
+            "pipeline"
+        );
+
+        let options = builder()
+            .smallest_unit(128)
+            .min_gap_for_chord(4)
+            .velocity_min(5)
+            .velocity_max(5)
+            .build()
+            .unwrap();
+        assert_eq!(options.smallest_unit, 128);
+        assert_eq!(options.min_gap_for_chord, 4);
+        assert_eq!((options.velocity_min, options.velocity_max), (5, 5));
+    }
+
+    #[test]
+    fn test_presets() {
+        for preset in OptionsPreset::ALL {
+            assert_eq!(OptionsPreset::from_name(preset.name()), Some(preset));
+            preset.to_options().validate().unwrap();
+        }
+        assert_eq!(OptionsPreset::from_name("orchestra"), None);
+
+        let options = MmlSongOptions::builder()
+            .preset(OptionsPreset::Compact)
+            .smallest_unit(16)
+            .build()
+            .unwrap();
+        assert_eq!(options.smallest_unit, 16);
+        assert_eq!(options.min_gap_for_chord, 2);
+        assert_eq!(options.tempo_placement, TempoPlacement::FirstNonEmpty);
+    }
+
+    #[test]
+    fn test_preset_chord_gaps() {
+        // Widest gap between the notes of a chord, in whole notes
+        let gaps: Vec<f64> = OptionsPreset::ALL
+            .iter()
+            .map(|preset| {
+                let options = preset.to_options();
+                options.min_gap_for_chord as f64 / options.smallest_unit as f64
+            })
+            .collect();
+        assert_eq!(gaps, vec![1. / 32., 0., 1. / 16.]);
+
+        // Notes rolled by a 1/48 note, at 480 PPQ
+        let notes = vec![note(60, 0, 960), note(64, 40, 920), note(67, 80, 880)];
+        let chord_counts: Vec<usize> = OptionsPreset::ALL
+            .iter()
+            .map(|preset| {
+                let track = create_track(vec![], notes.to_owned(), &preset.to_options());
+                track.to_mml().matches(':').count()
+            })
+            .collect();
+        assert_eq!(chord_counts, vec![1, 0, 2]);
+    }
+
+    #[test]
+    fn test_builder_serde() {
+        let builder: MmlSongOptionsBuilder =
+            serde_json::from_str(r#"{"preset": "band", "velocity_max": 12}"#).unwrap();
+        let options = builder.build().unwrap();
+        assert!(options.auto_boot_velocity);
+        assert_eq!(options.velocity_max, 12);
+
+        let json = serde_json::to_string(&builder).unwrap();
+        assert_eq!(
+            serde_json::from_str::<MmlSongOptionsBuilder>(&json).unwrap(),
+            builder
+        );
+
+        let builder: MmlSongOptionsBuilder =
+            serde_json::from_str(r#"{"smallest_unit": 100}"#).unwrap();
+        assert!(builder.build().is_err());
+    }
+}
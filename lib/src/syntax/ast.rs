@@ -0,0 +1,592 @@
+use std::fmt::Display;
+
+use super::{
+    Span,
+    tokenizer::{Token, TokenKind, tokenize},
+};
+use crate::{pitch_class::PitchClass, utils};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MmlNode {
+    pub kind: MmlNodeKind,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MmlNodeKind {
+    /// A note or a rest, with all its tied parts
+    Note(NoteNode),
+    Tempo(u32),
+    Octave(u8),
+    IncreOctave,
+    DecreOctave,
+    ConnectChord,
+    Velocity(u8),
+    NoteLength(NoteLength),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NoteNode {
+    /// `c`, `d`, `e`, `f`, `g`, `a`, `b` or `r` for a rest
+    pub name: char,
+
+    /// Semitones added by `+`, `#` and `-`
+    pub accidental: i8,
+
+    /// `c4&c16` has two parts
+    pub lengths: Vec<NoteLength>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NoteLength {
+    /// `None` when the length is omitted and the `l` command applies
+    pub value: Option<u32>,
+    pub dots: u8,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseError {
+    pub kind: ParseErrorKind,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ParseErrorKind {
+    UnknownCommand(char),
+
+    /// `t`, `o`, `v` or `l` without a number
+    MissingValue(char),
+
+    /// `&` that is not followed by the same note
+    DanglingTie,
+
+    /// `:` that is not between two notes, rests in between are allowed
+    DanglingChord,
+
+    /// `/* */` when `ParseOptions::comments` is off, the game does not read comments
+    Comment,
+
+    /// `/*` without its `*/`
+    UnclosedComment,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct ParseOptions {
+    /// Skips `/* */` comments, e.g. in the output of `MmlTrack::to_pretty_mml`
+    pub comments: bool,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct ParsedMml {
+    pub nodes: Vec<MmlNode>,
+    pub errors: Vec<ParseError>,
+}
+
+impl NoteNode {
+    pub fn is_rest(&self) -> bool {
+        self.name == 'r'
+    }
+
+    /// Semitones from the C of the current octave, `None` for a rest
+    pub fn semitone(&self) -> Option<i8> {
+        let natural = match self.name {
+            'c' => 0,
+            'd' => 2,
+            'e' => 4,
+            'f' => 5,
+            'g' => 7,
+            'a' => 9,
+            'b' => 11,
+            _ => return None,
+        };
+
+        Some(natural + self.accidental)
+    }
+
+    /// `o4c` => 60
+    pub fn midi_key(&self, octave: u8) -> Option<u8> {
+        let key = (octave as i16 + 1) * 12 + self.semitone()? as i16;
+        u8::try_from(key).ok().filter(|key| *key < 128)
+    }
+
+    pub fn pitch_class(&self, octave: u8) -> PitchClass {
+        match self.midi_key(octave) {
+            Some(key) => utils::midi_key_to_pitch_class(key),
+            None => PitchClass::Rest,
+        }
+    }
+
+    pub fn duration_in_smallest_unit(
+        &self,
+        default_length: &NoteLength,
+        smallest_unit: usize,
+    ) -> usize {
+        self.lengths
+            .iter()
+            .map(|length| length.duration_in_smallest_unit(default_length, smallest_unit))
+            .sum()
+    }
+}
+
+impl NoteLength {
+    pub fn new(value: u32, dots: u8) -> Self {
+        Self {
+            value: Some(value),
+            dots,
+            span: 0..0,
+        }
+    }
+
+    /// `4.` => 24 when the smallest unit is 64.
+    /// An omitted value takes both the value and the dots of `default_length`.
+    pub fn duration_in_smallest_unit(
+        &self,
+        default_length: &NoteLength,
+        smallest_unit: usize,
+    ) -> usize {
+        let (value, dots) = match self.value {
+            Some(value) => (value, self.dots),
+            None => (
+                default_length.value.unwrap_or(super::DEFAULT_NOTE_LENGTH),
+                default_length.dots.saturating_add(self.dots),
+            ),
+        };
+
+        let Some(mut part) = smallest_unit.checked_div(value as usize) else {
+            return 0;
+        };
+
+        let mut duration = part;
+        for _ in 0..dots {
+            part /= 2;
+            duration += part;
+        }
+
+        duration
+    }
+}
+
+impl Default for NoteLength {
+    fn default() -> Self {
+        Self::new(super::DEFAULT_NOTE_LENGTH, 0)
+    }
+}
+
+impl Display for ParseErrorKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::UnknownCommand(c) => write!(f, "Unknown command `{c}`"),
+            Self::MissingValue(c) => write!(f, "Missing value for `{c}`"),
+            Self::DanglingTie => write!(f, "`&` is not followed by the same note"),
+            Self::DanglingChord => write!(f, "`:` is not between two notes"),
+            Self::Comment => write!(f, "Comments are not allowed"),
+            Self::UnclosedComment => write!(f, "`/*` is not closed"),
+        }
+    }
+}
+
+impl Display for ParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} at {}..{}", self.kind, self.span.start, self.span.end)
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+/// Parses MML text into nodes.
+/// Parsing never stops at an error, every problem is collected in `ParsedMml::errors`.
+pub fn parse(mml: &str) -> ParsedMml {
+    parse_with_options(mml, &ParseOptions::default())
+}
+
+/// Same as `parse`, with comments skipped when `options` allows them.
+pub fn parse_with_options(mml: &str, options: &ParseOptions) -> ParsedMml {
+    let mut result = ParsedMml::default();
+    let tokens: Vec<Token> = tokenize(mml)
+        .into_iter()
+        .filter(|token| {
+            if token.kind == TokenKind::Comment {
+                let is_closed = token.span.len() >= 4 && mml[token.span.to_owned()].ends_with("*/");
+                let kind = match (options.comments, is_closed) {
+                    (false, _) => Some(ParseErrorKind::Comment),
+                    (true, false) => Some(ParseErrorKind::UnclosedComment),
+                    (true, true) => None,
+                };
+
+                if let Some(kind) = kind {
+                    result.errors.push(ParseError {
+                        kind,
+                        span: token.span.to_owned(),
+                    });
+                }
+            }
+
+            !matches!(token.kind, TokenKind::Whitespace | TokenKind::Comment)
+        })
+        .collect();
+
+    let mut parser = Parser {
+        mml,
+        tokens: &tokens,
+        index: 0,
+        result,
+    };
+    parser.parse_nodes();
+    parser.result
+}
+
+struct Parser<'a> {
+    mml: &'a str,
+    tokens: &'a [Token],
+    index: usize,
+    result: ParsedMml,
+}
+
+impl Parser<'_> {
+    fn parse_nodes(&mut self) {
+        // Index of the `:` node waiting for its note
+        let mut pending_chord: Option<usize> = None;
+        let mut has_note = false;
+
+        while let Some(token) = self.tokens.get(self.index) {
+            let start = token.span.start;
+            self.index += 1;
+
+            let kind = match &token.kind {
+                TokenKind::Note(name) => Some(MmlNodeKind::Note(self.parse_note(*name))),
+                TokenKind::Rest => Some(MmlNodeKind::Note(self.parse_note('r'))),
+                TokenKind::Tempo => self.parse_value('t').map(MmlNodeKind::Tempo),
+                TokenKind::Octave => self
+                    .parse_value('o')
+                    .map(|value| MmlNodeKind::Octave(value.min(u8::MAX as u32) as u8)),
+                TokenKind::Velocity => self
+                    .parse_value('v')
+                    .map(|value| MmlNodeKind::Velocity(value.min(u8::MAX as u32) as u8)),
+                TokenKind::NoteLength => self.parse_default_length(),
+                TokenKind::IncreOctave => Some(MmlNodeKind::IncreOctave),
+                TokenKind::DecreOctave => Some(MmlNodeKind::DecreOctave),
+                TokenKind::ChordConnector => Some(MmlNodeKind::ConnectChord),
+                TokenKind::Tie => {
+                    self.push_error(ParseErrorKind::DanglingTie, token.span.to_owned());
+                    None
+                }
+                TokenKind::Sharp | TokenKind::Flat | TokenKind::Dot | TokenKind::Number(_) => {
+                    let c = self.mml[start..].chars().next().unwrap_or_default();
+                    self.push_error(ParseErrorKind::UnknownCommand(c), token.span.to_owned());
+                    None
+                }
+                TokenKind::Unknown(c) => {
+                    self.push_error(ParseErrorKind::UnknownCommand(*c), token.span.to_owned());
+                    None
+                }
+                TokenKind::Whitespace | TokenKind::Comment => None,
+            };
+
+            let Some(kind) = kind else {
+                continue;
+            };
+
+            let end = self.tokens[self.index - 1].span.end;
+
+            match &kind {
+                MmlNodeKind::ConnectChord => {
+                    if !has_note || pending_chord.is_some() {
+                        self.push_error(ParseErrorKind::DanglingChord, start..end);
+                    }
+                    pending_chord = Some(self.result.nodes.len());
+                }
+                // The converter may place a rest between `:` and the chord note,
+                // the rest keeps its own duration and the chord stays open.
+                MmlNodeKind::Note(note) if !note.is_rest() => {
+                    pending_chord = None;
+                    has_note = true;
+                }
+                _ => (),
+            }
+
+            self.result.nodes.push(MmlNode {
+                kind,
+                span: start..end,
+            });
+        }
+
+        if let Some(index) = pending_chord {
+            let span = self.result.nodes[index].span.to_owned();
+            self.push_error(ParseErrorKind::DanglingChord, span);
+        }
+    }
+
+    fn parse_note(&mut self, name: char) -> NoteNode {
+        let accidental = self.parse_accidental();
+        let mut lengths = vec![self.parse_length()];
+
+        while let Some(Token {
+            kind: TokenKind::Tie,
+            span,
+        }) = self.tokens.get(self.index)
+        {
+            let tie_span = span.to_owned();
+            let next_name = match self.tokens.get(self.index + 1).map(|token| &token.kind) {
+                Some(TokenKind::Note(c)) => Some(*c),
+                Some(TokenKind::Rest) => Some('r'),
+                _ => None,
+            };
+
+            if next_name != Some(name) {
+                self.push_error(ParseErrorKind::DanglingTie, tie_span);
+                self.index += 1;
+                break;
+            }
+
+            let index_before_tie = self.index;
+            self.index += 2;
+
+            if self.parse_accidental() != accidental {
+                self.push_error(ParseErrorKind::DanglingTie, tie_span);
+                self.index = index_before_tie + 1;
+                break;
+            }
+
+            lengths.push(self.parse_length());
+        }
+
+        NoteNode {
+            name,
+            accidental,
+            lengths,
+        }
+    }
+
+    fn parse_accidental(&mut self) -> i8 {
+        let mut accidental = 0i8;
+
+        while let Some(token) = self.tokens.get(self.index) {
+            match token.kind {
+                TokenKind::Sharp => accidental += 1,
+                TokenKind::Flat => accidental -= 1,
+                _ => break,
+            }
+            self.index += 1;
+        }
+
+        accidental
+    }
+
+    fn parse_length(&mut self) -> NoteLength {
+        let start = self
+            .tokens
+            .get(self.index)
+            .map(|token| token.span.start)
+            .unwrap_or_else(|| self.tokens.last().map(|t| t.span.end).unwrap_or_default());
+        let mut end = start;
+
+        let value = match self.tokens.get(self.index) {
+            Some(Token {
+                kind: TokenKind::Number(value),
+                span,
+            }) => {
+                end = span.end;
+                self.index += 1;
+                Some(*value)
+            }
+            _ => None,
+        };
+
+        let mut dots = 0u8;
+        while let Some(Token {
+            kind: TokenKind::Dot,
+            span,
+        }) = self.tokens.get(self.index)
+        {
+            end = span.end;
+            dots = dots.saturating_add(1);
+            self.index += 1;
+        }
+
+        NoteLength {
+            value,
+            dots,
+            span: start..end,
+        }
+    }
+
+    fn parse_value(&mut self, command: char) -> Option<u32> {
+        match self.tokens.get(self.index) {
+            Some(Token {
+                kind: TokenKind::Number(value),
+                ..
+            }) => {
+                self.index += 1;
+                Some(*value)
+            }
+            _ => {
+                let span = self.tokens[self.index - 1].span.to_owned();
+                self.push_error(ParseErrorKind::MissingValue(command), span);
+                None
+            }
+        }
+    }
+
+    fn parse_default_length(&mut self) -> Option<MmlNodeKind> {
+        let length = self.parse_length();
+
+        if length.value.is_none() {
+            let span = self.tokens[self.index - 1].span.to_owned();
+            self.push_error(ParseErrorKind::MissingValue('l'), span);
+            return None;
+        }
+
+        Some(MmlNodeKind::NoteLength(length))
+    }
+
+    fn push_error(&mut self, kind: ParseErrorKind, span: Span) {
+        self.result.errors.push(ParseError { kind, span });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn kinds(mml: &str) -> Vec<MmlNodeKind> {
+        let parsed = parse(mml);
+        assert!(parsed.errors.is_empty(), "{:?}", parsed.errors);
+        parsed.nodes.into_iter().map(|node| node.kind).collect()
+    }
+
+    #[test]
+    fn test_parse_commands() {
+        assert_eq!(
+            kinds("t120v12o4><l8"),
+            vec![
+                MmlNodeKind::Tempo(120),
+                MmlNodeKind::Velocity(12),
+                MmlNodeKind::Octave(4),
+                MmlNodeKind::IncreOctave,
+                MmlNodeKind::DecreOctave,
+                MmlNodeKind::NoteLength(NoteLength {
+                    value: Some(8),
+                    dots: 0,
+                    span: 12..13,
+                }),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_tied_note() {
+        let parsed = parse("c+4.&c+16 r");
+        assert!(parsed.errors.is_empty());
+
+        let node = &parsed.nodes[0];
+        assert_eq!(node.span, 0..9);
+
+        let MmlNodeKind::Note(note) = &node.kind else {
+            panic!("Expected a note");
+        };
+        assert_eq!(note.name, 'c');
+        assert_eq!(note.accidental, 1);
+        assert_eq!(note.lengths.len(), 2);
+        assert_eq!(note.midi_key(4), Some(61));
+        assert_eq!(
+            note.duration_in_smallest_unit(&NoteLength::default(), 64),
+            28
+        );
+
+        let MmlNodeKind::Note(rest) = &parsed.nodes[1].kind else {
+            panic!("Expected a rest");
+        };
+        assert!(rest.is_rest());
+        assert_eq!(rest.midi_key(4), None);
+        assert_eq!(
+            rest.duration_in_smallest_unit(&NoteLength::new(8, 1), 64),
+            12
+        );
+    }
+
+    #[test]
+    fn test_parse_chord() {
+        let parsed = parse("c4:>e4");
+        assert!(parsed.errors.is_empty());
+        assert_eq!(parsed.nodes.len(), 4);
+        assert_eq!(parsed.nodes[1].kind, MmlNodeKind::ConnectChord);
+
+        assert!(parse("c4:r8e4").errors.is_empty());
+        assert_eq!(parse("c4:r8").errors[0].kind, ParseErrorKind::DanglingChord);
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        let parsed = parse("c4&d4 x t :c4 e4:");
+        let errors: Vec<(ParseErrorKind, Span)> = parsed
+            .errors
+            .into_iter()
+            .map(|error| (error.kind, error.span))
+            .collect();
+
+        assert_eq!(
+            errors,
+            vec![
+                (ParseErrorKind::DanglingTie, 2..3),
+                (ParseErrorKind::UnknownCommand('x'), 6..7),
+                (ParseErrorKind::MissingValue('t'), 8..9),
+                (ParseErrorKind::DanglingChord, 16..17),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_comments() {
+        let options = ParseOptions { comments: true };
+        let parsed = parse_with_options("/* intro */ c4 /* c4 */ d4", &options);
+        assert!(parsed.errors.is_empty());
+        assert_eq!(parsed.nodes.len(), 2);
+
+        let parsed = parse("/* intro */ c4 /* c4 */ d4");
+        let errors: Vec<(ParseErrorKind, Span)> = parsed
+            .errors
+            .into_iter()
+            .map(|error| (error.kind, error.span))
+            .collect();
+        assert_eq!(
+            errors,
+            vec![
+                (ParseErrorKind::Comment, 0..11),
+                (ParseErrorKind::Comment, 15..23),
+            ]
+        );
+        assert_eq!(parsed.nodes.len(), 2);
+
+        let errors: Vec<(ParseErrorKind, Span)> = ["c4 /* open", "c4/*/"]
+            .into_iter()
+            .flat_map(|mml| parse_with_options(mml, &options).errors)
+            .map(|error| (error.kind, error.span))
+            .collect();
+        assert_eq!(
+            errors,
+            vec![
+                (ParseErrorKind::UnclosedComment, 3..10),
+                (ParseErrorKind::UnclosedComment, 2..5),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_note_length_duration() {
+        let default_length = NoteLength::default();
+
+        assert_eq!(
+            NoteLength::new(1, 0).duration_in_smallest_unit(&default_length, 64),
+            64
+        );
+        assert_eq!(
+            NoteLength::new(4, 2).duration_in_smallest_unit(&default_length, 64),
+            28
+        );
+        assert_eq!(
+            NoteLength::new(0, 0).duration_in_smallest_unit(&default_length, 64),
+            0
+        );
+    }
+}
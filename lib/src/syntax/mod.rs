@@ -0,0 +1,27 @@
+//! MML syntax shared by the converter and the player,
+//! so that writing and reading MML follow one grammar.
+
+mod ast;
+mod timeline;
+mod tokenizer;
+mod validator;
+
+pub use self::ast::{
+    MmlNode, MmlNodeKind, NoteLength, NoteNode, ParseError, ParseErrorKind, ParseOptions,
+    ParsedMml, parse, parse_with_options,
+};
+pub use self::timeline::{MmlState, TimedNote, timed_notes, timed_tempos};
+pub use self::tokenizer::{Token, TokenKind, tokenize};
+pub use self::validator::{Diagnostic, DiagnosticCode, Severity, ValidatorOptions, validate};
+
+/// Byte range in the MML text
+pub type Span = std::ops::Range<usize>;
+
+pub const DEFAULT_OCTAVE: u8 = 4;
+pub const DEFAULT_VELOCITY: u8 = 12;
+pub const DEFAULT_TEMPO: u32 = 120;
+pub const DEFAULT_NOTE_LENGTH: u32 = 4;
+
+/// Resolution used when a parsed length must be measured without knowing the song options,
+/// every length down to a 1/256 note is a whole number of this unit.
+pub const MAX_SMALLEST_UNIT: usize = 256;
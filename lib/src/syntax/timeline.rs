@@ -0,0 +1,258 @@
+use super::{
+    DEFAULT_OCTAVE, DEFAULT_TEMPO, DEFAULT_VELOCITY, Span,
+    ast::{MmlNode, MmlNodeKind, NoteLength},
+};
+
+/// Octave, velocity, tempo and default length while reading a track from left to right.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MmlState {
+    pub octave: u8,
+    pub velocity: u8,
+    pub tempo: u32,
+    pub note_length: NoteLength,
+}
+
+/// A note or rest placed on the timeline of its track.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TimedNote {
+    /// `None` for a rest
+    pub midi_key: Option<u8>,
+
+    /// MML velocity
+    pub velocity: u8,
+    pub tempo: u32,
+    pub position_in_smallest_unit: usize,
+    pub duration_in_smallest_unit: usize,
+    pub is_part_of_chord: bool,
+    pub span: Span,
+}
+
+impl Default for MmlState {
+    fn default() -> Self {
+        Self {
+            octave: DEFAULT_OCTAVE,
+            velocity: DEFAULT_VELOCITY,
+            tempo: DEFAULT_TEMPO,
+            note_length: NoteLength::default(),
+        }
+    }
+}
+
+impl MmlState {
+    pub fn apply(&mut self, kind: &MmlNodeKind) {
+        match kind {
+            MmlNodeKind::Tempo(tempo) => self.tempo = *tempo,
+            MmlNodeKind::Octave(octave) => self.octave = *octave,
+            MmlNodeKind::IncreOctave => self.octave = self.octave.saturating_add(1),
+            MmlNodeKind::DecreOctave => self.octave = self.octave.saturating_sub(1),
+            MmlNodeKind::Velocity(velocity) => self.velocity = *velocity,
+            MmlNodeKind::NoteLength(length) => self.note_length = length.to_owned(),
+            MmlNodeKind::Note(_) | MmlNodeKind::ConnectChord => (),
+        }
+    }
+}
+
+/// Places every note and rest of a parsed track on the timeline.
+/// Like in the MIDI and JSON exports, notes of a chord start together
+/// and only the first note of the chord moves the position forward.
+/// A rest never belongs to a chord, even between `:` and the chord note,
+/// and the chord note after it still starts with the chord.
+pub fn timed_notes(nodes: &[MmlNode], smallest_unit: usize) -> Vec<TimedNote> {
+    let mut notes: Vec<TimedNote> = Vec::with_capacity(nodes.len());
+    let mut state = MmlState::default();
+    let mut position = 0usize;
+    let mut chord_position = 0usize;
+    let mut is_connect_chord = false;
+
+    for node in nodes.iter() {
+        match &node.kind {
+            MmlNodeKind::Note(note) => {
+                let duration = note.duration_in_smallest_unit(&state.note_length, smallest_unit);
+                let is_part_of_chord = is_connect_chord && !note.is_rest();
+                if !note.is_rest() {
+                    is_connect_chord = false;
+                }
+
+                let position_in_smallest_unit = if is_part_of_chord {
+                    chord_position
+                } else if note.is_rest() {
+                    position += duration;
+                    position - duration
+                } else {
+                    chord_position = position;
+                    position += duration;
+                    chord_position
+                };
+
+                notes.push(TimedNote {
+                    midi_key: note.midi_key(state.octave),
+                    velocity: state.velocity,
+                    tempo: state.tempo,
+                    position_in_smallest_unit,
+                    duration_in_smallest_unit: duration,
+                    is_part_of_chord,
+                    span: node.span.to_owned(),
+                });
+            }
+            MmlNodeKind::ConnectChord => is_connect_chord = true,
+            kind => state.apply(kind),
+        }
+    }
+
+    notes
+}
+
+/// Position and value of every tempo command of a parsed track.
+pub fn timed_tempos(nodes: &[MmlNode], smallest_unit: usize) -> Vec<(usize, u32)> {
+    let mut tempos: Vec<(usize, u32)> = Vec::new();
+    let mut state = MmlState::default();
+    let mut position = 0usize;
+    let mut is_connect_chord = false;
+
+    for node in nodes.iter() {
+        match &node.kind {
+            MmlNodeKind::Note(note) => {
+                if !is_connect_chord || note.is_rest() {
+                    position += note.duration_in_smallest_unit(&state.note_length, smallest_unit);
+                }
+                if !note.is_rest() {
+                    is_connect_chord = false;
+                }
+            }
+            MmlNodeKind::ConnectChord => is_connect_chord = true,
+            MmlNodeKind::Tempo(tempo) => tempos.push((position, *tempo)),
+            kind => state.apply(kind),
+        }
+    }
+
+    tempos
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{MmlEvent, MmlSong, MmlSongOptions, syntax::parse, test_utils::MIDI_PATHS};
+
+    #[test]
+    fn test_timed_notes() {
+        let parsed = parse("t150v10o4c4:>e8r8<b16.&b32");
+        let notes = timed_notes(&parsed.nodes, 64);
+
+        let summary: Vec<(Option<u8>, usize, usize, bool)> = notes
+            .iter()
+            .map(|note| {
+                (
+                    note.midi_key,
+                    note.position_in_smallest_unit,
+                    note.duration_in_smallest_unit,
+                    note.is_part_of_chord,
+                )
+            })
+            .collect();
+
+        assert_eq!(
+            summary,
+            vec![
+                (Some(60), 0, 16, false),
+                (Some(76), 0, 8, true),
+                (None, 16, 8, false),
+                (Some(71), 24, 8, false),
+            ]
+        );
+        assert!(
+            notes
+                .iter()
+                .all(|note| note.tempo == 150 && note.velocity == 10)
+        );
+        assert_eq!(notes[3].span, 18..26);
+    }
+
+    #[test]
+    fn test_timed_notes_rest_in_chord() {
+        let parsed = parse("c4:r16e4d4");
+        let positions: Vec<(Option<u8>, usize, bool)> = timed_notes(&parsed.nodes, 64)
+            .iter()
+            .map(|note| {
+                (
+                    note.midi_key,
+                    note.position_in_smallest_unit,
+                    note.is_part_of_chord,
+                )
+            })
+            .collect();
+
+        assert_eq!(
+            positions,
+            vec![
+                (Some(60), 0, false),
+                (None, 16, false),
+                (Some(64), 0, true),
+                (Some(62), 20, false),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_timed_tempos() {
+        let parsed = parse("t120c4:e4t90l8r d16:<g16t150c");
+        assert_eq!(
+            timed_tempos(&parsed.nodes, 64),
+            vec![(0, 120), (16, 90), (28, 150)]
+        );
+    }
+
+    #[test]
+    fn test_timed_notes_default_length() {
+        let parsed = parse("l8c c4 l16. d");
+        let notes = timed_notes(&parsed.nodes, 64);
+
+        let durations: Vec<usize> = notes
+            .iter()
+            .map(|note| note.duration_in_smallest_unit)
+            .collect();
+
+        assert_eq!(durations, vec![8, 16, 6]);
+    }
+
+    #[test]
+    fn test_timed_notes_match_converted_song() {
+        for path in MIDI_PATHS {
+            let song = MmlSong::from_path(path, MmlSongOptions::default()).unwrap();
+
+            for track in song.tracks.iter() {
+                let parsed = parse(&track.to_mml());
+                assert!(parsed.errors.is_empty(), "{:?}", parsed.errors);
+
+                let notes = timed_notes(&parsed.nodes, song.options.smallest_unit);
+                let expected: Vec<(Option<u8>, usize, bool)> = track
+                    .events
+                    .iter()
+                    .filter_map(|event| match event {
+                        MmlEvent::Note(note) => Some((
+                            Some(note.midi_state.key),
+                            note.position_in_smallest_unit,
+                            note.is_part_of_chord,
+                        )),
+                        _ => None,
+                    })
+                    .collect();
+                let actual: Vec<(Option<u8>, usize, bool)> = notes
+                    .iter()
+                    .filter(|note| note.midi_key.is_some())
+                    .map(|note| {
+                        (
+                            note.midi_key,
+                            note.position_in_smallest_unit,
+                            note.is_part_of_chord,
+                        )
+                    })
+                    .collect();
+
+                assert_eq!(actual.len(), expected.len());
+                for (actual, expected) in actual.iter().zip(expected.iter()) {
+                    assert_eq!(actual, expected);
+                }
+            }
+        }
+    }
+}
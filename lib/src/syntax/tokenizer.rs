@@ -0,0 +1,174 @@
+use super::Span;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TokenKind {
+    /// `c`, `d`, `e`, `f`, `g`, `a` or `b`, always lowercase
+    Note(char),
+    Rest,
+    /// `+` or `#`
+    Sharp,
+    /// `-`
+    Flat,
+    Number(u32),
+    Dot,
+    Tie,
+    ChordConnector,
+    Tempo,
+    Octave,
+    Velocity,
+    NoteLength,
+    IncreOctave,
+    DecreOctave,
+    Whitespace,
+
+    /// `/* ... */`, runs to the end of the text when it is not closed
+    Comment,
+    Unknown(char),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Token {
+    pub kind: TokenKind,
+    pub span: Span,
+}
+
+pub fn tokenize(mml: &str) -> Vec<Token> {
+    let mut tokens: Vec<Token> = Vec::with_capacity(mml.len());
+    let mut chars = mml.char_indices().peekable();
+
+    while let Some((start, char)) = chars.next() {
+        let mut end = start + char.len_utf8();
+
+        let kind = match char.to_ascii_lowercase() {
+            c @ ('c' | 'd' | 'e' | 'f' | 'g' | 'a' | 'b') => TokenKind::Note(c),
+            'r' => TokenKind::Rest,
+            '+' | '#' => TokenKind::Sharp,
+            '-' => TokenKind::Flat,
+            '.' => TokenKind::Dot,
+            '&' => TokenKind::Tie,
+            ':' => TokenKind::ChordConnector,
+            't' => TokenKind::Tempo,
+            'o' => TokenKind::Octave,
+            'v' => TokenKind::Velocity,
+            'l' => TokenKind::NoteLength,
+            '>' => TokenKind::IncreOctave,
+            '<' => TokenKind::DecreOctave,
+            c if c.is_ascii_digit() => {
+                let mut value = c.to_digit(10).unwrap_or_default();
+
+                while let Some(&(i, next)) = chars.peek()
+                    && let Some(digit) = next.to_digit(10)
+                {
+                    value = value.saturating_mul(10).saturating_add(digit);
+                    end = i + 1;
+                    chars.next();
+                }
+
+                TokenKind::Number(value)
+            }
+            c if c.is_whitespace() => {
+                while let Some(&(i, next)) = chars.peek()
+                    && next.is_whitespace()
+                {
+                    end = i + next.len_utf8();
+                    chars.next();
+                }
+
+                TokenKind::Whitespace
+            }
+            '/' if chars.peek().is_some_and(|(_, next)| *next == '*') => {
+                chars.next();
+                end = mml.len();
+
+                while let Some((i, next)) = chars.next() {
+                    if next == '*' && chars.peek().is_some_and(|(_, next)| *next == '/') {
+                        chars.next();
+                        end = i + 2;
+                        break;
+                    }
+                }
+
+                TokenKind::Comment
+            }
+            _ => TokenKind::Unknown(char),
+        };
+
+        tokens.push(Token {
+            kind,
+            span: start..end,
+        });
+    }
+
+    tokens
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn kinds(mml: &str) -> Vec<TokenKind> {
+        tokenize(mml).into_iter().map(|token| token.kind).collect()
+    }
+
+    #[test]
+    fn test_tokenize_note() {
+        assert_eq!(
+            kinds("C+4.&c+16"),
+            vec![
+                TokenKind::Note('c'),
+                TokenKind::Sharp,
+                TokenKind::Number(4),
+                TokenKind::Dot,
+                TokenKind::Tie,
+                TokenKind::Note('c'),
+                TokenKind::Sharp,
+                TokenKind::Number(16),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_tokenize_commands() {
+        assert_eq!(
+            kinds("t120 o4>v12"),
+            vec![
+                TokenKind::Tempo,
+                TokenKind::Number(120),
+                TokenKind::Whitespace,
+                TokenKind::Octave,
+                TokenKind::Number(4),
+                TokenKind::IncreOctave,
+                TokenKind::Velocity,
+                TokenKind::Number(12),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_tokenize_spans() {
+        let tokens = tokenize("t120 \n c");
+
+        assert_eq!(tokens[1].span, 1..4);
+        assert_eq!(tokens[2].span, 4..7);
+        assert_eq!(tokens[3].span, 7..8);
+    }
+
+    #[test]
+    fn test_tokenize_comment() {
+        let tokens = tokenize("c4/* bar 2 */d4/ /* open");
+
+        assert_eq!(tokens[2].kind, TokenKind::Comment);
+        assert_eq!(tokens[2].span, 2..13);
+        assert_eq!(tokens[5].kind, TokenKind::Unknown('/'));
+        assert_eq!(tokens[7].kind, TokenKind::Comment);
+        assert_eq!(tokens[7].span, 17..24);
+    }
+
+    #[test]
+    fn test_tokenize_unknown() {
+        let tokens = tokenize("cé");
+
+        assert_eq!(tokens[1].kind, TokenKind::Unknown('é'));
+        assert_eq!(tokens[1].span, 1..3);
+    }
+}
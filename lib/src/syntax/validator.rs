@@ -0,0 +1,432 @@
+use std::{fmt::Display, ops::RangeInclusive};
+
+use super::{
+    MAX_SMALLEST_UNIT, Span,
+    ast::{MmlNode, MmlNodeKind, NoteLength, ParseErrorKind, parse},
+    timeline::{MmlState, timed_notes},
+};
+use crate::dialect::MmlDialect;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    Warning,
+    Error,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DiagnosticCode {
+    UnknownCommand,
+    MissingValue,
+    InvalidLength,
+    OctaveOutOfRange,
+    TempoOutOfRange,
+    DanglingTie,
+    DanglingChord,
+
+    /// `/* */` is kept in the text the game reads
+    Comment,
+
+    /// The target has no chords
+    UnsupportedChord,
+
+    /// Notes of a chord do not have the same length
+    ChordDurationMismatch,
+    TooManyCharacters,
+}
+
+/// A problem found in MML text, `span` is the byte range to underline.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostic {
+    pub span: Span,
+    pub severity: Severity,
+    pub code: DiagnosticCode,
+    pub message: String,
+}
+
+/// Limits of the target the MML is written for.
+#[derive(Debug, Clone)]
+pub struct ValidatorOptions {
+    /// Longest length value, e.g. `64` allows `c64` but not `c128`
+    pub max_length: u32,
+
+    /// `None` accepts every length up to `max_length`
+    pub allowed_lengths: Option<Vec<u32>>,
+    pub octave_range: RangeInclusive<u8>,
+    pub tempo_range: RangeInclusive<u32>,
+    pub allow_chords: bool,
+
+    /// `None` means no limit
+    pub max_chars: Option<usize>,
+}
+
+impl Default for ValidatorOptions {
+    fn default() -> Self {
+        Self {
+            max_length: MAX_SMALLEST_UNIT as u32,
+            allowed_lengths: None,
+            octave_range: 0..=8,
+            tempo_range: 32..=255,
+            allow_chords: true,
+            max_chars: None,
+        }
+    }
+}
+
+impl ValidatorOptions {
+    /// Lengths and chords follow the dialect, other limits are the defaults.
+    pub fn from_dialect(dialect: &dyn MmlDialect) -> Self {
+        let allowed_lengths: Option<Vec<u32>> = dialect
+            .allowed_lengths()
+            .map(|lengths| lengths.iter().map(|length| *length as u32).collect());
+
+        Self {
+            max_length: allowed_lengths
+                .as_ref()
+                .and_then(|lengths| lengths.iter().max().copied())
+                .unwrap_or(MAX_SMALLEST_UNIT as u32),
+            allowed_lengths,
+            allow_chords: dialect.chord_connector().is_some(),
+            ..Default::default()
+        }
+    }
+
+    fn is_length_valid(&self, value: u32) -> bool {
+        if value == 0 || value > self.max_length {
+            return false;
+        }
+
+        match &self.allowed_lengths {
+            Some(lengths) => lengths.contains(&value),
+            None => true,
+        }
+    }
+}
+
+impl Display for DiagnosticCode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let code = match self {
+            Self::UnknownCommand => "unknown-command",
+            Self::MissingValue => "missing-value",
+            Self::InvalidLength => "invalid-length",
+            Self::OctaveOutOfRange => "octave-out-of-range",
+            Self::TempoOutOfRange => "tempo-out-of-range",
+            Self::DanglingTie => "dangling-tie",
+            Self::DanglingChord => "dangling-chord",
+            Self::Comment => "comment",
+            Self::UnsupportedChord => "unsupported-chord",
+            Self::ChordDurationMismatch => "chord-duration-mismatch",
+            Self::TooManyCharacters => "too-many-characters",
+        };
+
+        write!(f, "{code}")
+    }
+}
+
+impl Display for Diagnostic {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{:?}[{}] {} at {}..{}",
+            self.severity, self.code, self.message, self.span.start, self.span.end
+        )
+    }
+}
+
+impl Diagnostic {
+    fn new(span: Span, severity: Severity, code: DiagnosticCode, message: String) -> Self {
+        Self {
+            span,
+            severity,
+            code,
+            message,
+        }
+    }
+}
+
+/// Checks MML text against the limits of the target.
+/// Diagnostics are sorted by position.
+pub fn validate(mml: &str, options: &ValidatorOptions) -> Vec<Diagnostic> {
+    let parsed = parse(mml);
+    let mut diagnostics: Vec<Diagnostic> = parsed
+        .errors
+        .iter()
+        .map(|error| {
+            let code = match error.kind {
+                ParseErrorKind::UnknownCommand(_) => DiagnosticCode::UnknownCommand,
+                ParseErrorKind::MissingValue(_) => DiagnosticCode::MissingValue,
+                ParseErrorKind::DanglingTie => DiagnosticCode::DanglingTie,
+                ParseErrorKind::DanglingChord => DiagnosticCode::DanglingChord,
+                ParseErrorKind::Comment | ParseErrorKind::UnclosedComment => {
+                    DiagnosticCode::Comment
+                }
+            };
+
+            Diagnostic::new(
+                error.span.to_owned(),
+                Severity::Error,
+                code,
+                error.kind.to_string(),
+            )
+        })
+        .collect();
+
+    validate_nodes(&parsed.nodes, options, &mut diagnostics);
+    validate_chords(&parsed.nodes, options, &mut diagnostics);
+
+    if let Some(max_chars) = options.max_chars
+        && let Some((start, _)) = mml.char_indices().nth(max_chars)
+    {
+        diagnostics.push(Diagnostic::new(
+            start..mml.len(),
+            Severity::Error,
+            DiagnosticCode::TooManyCharacters,
+            format!(
+                "{} characters, the limit is {max_chars}",
+                mml.chars().count()
+            ),
+        ));
+    }
+
+    diagnostics.sort_by_key(|diagnostic| diagnostic.span.start);
+    diagnostics
+}
+
+fn validate_nodes(
+    nodes: &[MmlNode],
+    options: &ValidatorOptions,
+    diagnostics: &mut Vec<Diagnostic>,
+) {
+    let mut state = MmlState::default();
+    let check_length = |length: &NoteLength, diagnostics: &mut Vec<Diagnostic>| {
+        if let Some(value) = length.value
+            && !options.is_length_valid(value)
+        {
+            diagnostics.push(Diagnostic::new(
+                length.span.to_owned(),
+                Severity::Error,
+                DiagnosticCode::InvalidLength,
+                format!("Length {value} is not supported"),
+            ));
+        }
+    };
+
+    for node in nodes.iter() {
+        match &node.kind {
+            MmlNodeKind::Note(note) => {
+                for length in note.lengths.iter() {
+                    check_length(length, diagnostics);
+                }
+
+                if !note.is_rest() && note.midi_key(state.octave).is_none() {
+                    diagnostics.push(Diagnostic::new(
+                        node.span.to_owned(),
+                        Severity::Error,
+                        DiagnosticCode::OctaveOutOfRange,
+                        String::from("Note is out of the MIDI range"),
+                    ));
+                }
+            }
+            MmlNodeKind::NoteLength(length) => check_length(length, diagnostics),
+            MmlNodeKind::Tempo(tempo) if !options.tempo_range.contains(tempo) => {
+                diagnostics.push(Diagnostic::new(
+                    node.span.to_owned(),
+                    Severity::Error,
+                    DiagnosticCode::TempoOutOfRange,
+                    format!(
+                        "Tempo {tempo} is out of range {}-{}",
+                        options.tempo_range.start(),
+                        options.tempo_range.end()
+                    ),
+                ));
+            }
+            MmlNodeKind::ConnectChord if !options.allow_chords => {
+                diagnostics.push(Diagnostic::new(
+                    node.span.to_owned(),
+                    Severity::Error,
+                    DiagnosticCode::UnsupportedChord,
+                    String::from("Chords are not supported"),
+                ));
+            }
+            _ => (),
+        }
+
+        let octave_before = state.octave;
+        state.apply(&node.kind);
+
+        let is_octave_command = matches!(
+            node.kind,
+            MmlNodeKind::Octave(_) | MmlNodeKind::IncreOctave | MmlNodeKind::DecreOctave
+        );
+        let is_underflow = matches!(node.kind, MmlNodeKind::DecreOctave) && octave_before == 0;
+
+        if is_octave_command && (is_underflow || !options.octave_range.contains(&state.octave)) {
+            diagnostics.push(Diagnostic::new(
+                node.span.to_owned(),
+                Severity::Error,
+                DiagnosticCode::OctaveOutOfRange,
+                format!(
+                    "Octave is out of range {}-{}",
+                    options.octave_range.start(),
+                    options.octave_range.end()
+                ),
+            ));
+        }
+    }
+}
+
+fn validate_chords(
+    nodes: &[MmlNode],
+    options: &ValidatorOptions,
+    diagnostics: &mut Vec<Diagnostic>,
+) {
+    if !options.allow_chords {
+        return;
+    }
+
+    let notes = timed_notes(nodes, MAX_SMALLEST_UNIT);
+    let mut chord: Vec<(Span, usize)> = Vec::new();
+
+    let mut check_chord = |chord: &Vec<(Span, usize)>| {
+        if let (Some(first), Some(last)) = (chord.first(), chord.last())
+            && chord.iter().any(|(_, duration)| *duration != first.1)
+        {
+            diagnostics.push(Diagnostic::new(
+                first.0.start..last.0.end,
+                Severity::Warning,
+                DiagnosticCode::ChordDurationMismatch,
+                String::from("Notes of the chord have different lengths"),
+            ));
+        }
+    };
+
+    for note in notes.iter().filter(|note| note.midi_key.is_some()) {
+        if !note.is_part_of_chord {
+            check_chord(&chord);
+            chord.clear();
+        }
+
+        chord.push((note.span.to_owned(), note.duration_in_smallest_unit));
+    }
+
+    check_chord(&chord);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{MabinogiDialect, MmlSong, MmlSongOptions, test_utils::MIDI_PATHS};
+
+    fn codes(mml: &str, options: &ValidatorOptions) -> Vec<(DiagnosticCode, Span)> {
+        validate(mml, options)
+            .into_iter()
+            .map(|diagnostic| (diagnostic.code, diagnostic.span))
+            .collect()
+    }
+
+    #[test]
+    fn test_validate_valid_mml() {
+        assert!(validate("t120v12o4c4:e4:g4r8>c8.&c32", &ValidatorOptions::default()).is_empty());
+    }
+
+    #[test]
+    fn test_validate_lengths() {
+        let options = ValidatorOptions::from_dialect(&MabinogiDialect);
+
+        assert_eq!(
+            codes("c0d3l128e", &options),
+            vec![
+                (DiagnosticCode::InvalidLength, 1..2),
+                (DiagnosticCode::InvalidLength, 3..4),
+                (DiagnosticCode::InvalidLength, 5..8),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_validate_ranges() {
+        assert_eq!(
+            codes("t20o8>c<<<<<<<<<<c t300", &ValidatorOptions::default()),
+            vec![
+                (DiagnosticCode::TempoOutOfRange, 0..3),
+                (DiagnosticCode::OctaveOutOfRange, 5..6),
+                (DiagnosticCode::OctaveOutOfRange, 16..17),
+                (DiagnosticCode::TempoOutOfRange, 19..23),
+            ]
+        );
+        assert_eq!(
+            codes("o10c", &ValidatorOptions::default()),
+            vec![
+                (DiagnosticCode::OctaveOutOfRange, 0..3),
+                (DiagnosticCode::OctaveOutOfRange, 3..4),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_validate_dangling() {
+        assert_eq!(
+            codes("c4&d4:", &ValidatorOptions::default()),
+            vec![
+                (DiagnosticCode::DanglingTie, 2..3),
+                (DiagnosticCode::DanglingChord, 5..6),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_validate_comments() {
+        assert_eq!(
+            codes("/* 1 */ c4 /* open", &ValidatorOptions::default()),
+            vec![
+                (DiagnosticCode::Comment, 0..7),
+                (DiagnosticCode::Comment, 11..18)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_validate_chords() {
+        let diagnostics = validate("c4:e8:g4 c4:e4", &ValidatorOptions::default());
+
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].code, DiagnosticCode::ChordDurationMismatch);
+        assert_eq!(diagnostics[0].severity, Severity::Warning);
+        assert_eq!(diagnostics[0].span, 0..8);
+
+        assert_eq!(
+            codes("c4:e4", &ValidatorOptions::from_dialect(&MabinogiDialect)),
+            vec![(DiagnosticCode::UnsupportedChord, 2..3)]
+        );
+    }
+
+    #[test]
+    fn test_validate_max_chars() {
+        let options = ValidatorOptions {
+            max_chars: Some(4),
+            ..Default::default()
+        };
+
+        assert!(validate("c4d4", &options).is_empty());
+        assert_eq!(
+            codes("c4d4e4", &options),
+            vec![(DiagnosticCode::TooManyCharacters, 4..6)]
+        );
+    }
+
+    #[test]
+    fn test_validate_converted_song() {
+        for path in MIDI_PATHS {
+            let song = MmlSong::from_path(path, MmlSongOptions::default()).unwrap();
+
+            for track in song.tracks.iter() {
+                let errors: Vec<Diagnostic> =
+                    validate(&track.to_mml(), &ValidatorOptions::default())
+                        .into_iter()
+                        .filter(|diagnostic| diagnostic.severity == Severity::Error)
+                        .collect();
+
+                assert!(errors.is_empty(), "{:?}", errors);
+            }
+        }
+    }
+}
@@ -0,0 +1,554 @@
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    MmlTrack,
+    mml_event::{BridgeEvent, MidiState},
+    syntax::DEFAULT_TEMPO,
+    utils,
+};
+
+/// A change of the tempo events of every track, see `MmlSong::transform_tempo`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum TempoTransform {
+    /// Multiplies every tempo, the song plays `factor` times faster.
+    Scale { factor: f64 },
+
+    /// Keeps a single tempo and moves the notes so they play at the same time.
+    /// `None` uses the mean tempo, so the song keeps its length.
+    Flatten { tempo: Option<u32> },
+
+    /// Joins tempo changes of less than `threshold` BPM from the start of their run into
+    /// their mean tempo, and moves the notes so they play at the same time.
+    Merge { threshold: u32 },
+}
+
+/// Tracks that get the tempo commands, the game applies a tempo command to every track.
+/// Positions do not depend on the tempo, so the tracks stay aligned whichever track has them.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum TempoPlacement {
+    /// Every track repeats the tempo commands
+    #[default]
+    AllTracks,
+
+    /// Only the track at `index`, the first track with notes when there is no such track
+    Conductor { index: usize },
+
+    /// Only the first track with notes
+    FirstNonEmpty,
+}
+
+impl TempoPlacement {
+    /// Index of the only track with tempo commands, `None` when every track has them.
+    pub fn get_conductor_index(&self, tracks: &[MmlTrack]) -> Option<usize> {
+        let first_non_empty = || {
+            tracks
+                .iter()
+                .position(|track| {
+                    track
+                        .bridge_note_events
+                        .iter()
+                        .any(|event| matches!(event, BridgeEvent::Note(_)))
+                })
+                .unwrap_or(0)
+        };
+
+        match self {
+            Self::AllTracks => None,
+            Self::Conductor { index } if *index < tracks.len() => Some(*index),
+            Self::Conductor { .. } | Self::FirstNonEmpty => Some(first_non_empty()),
+        }
+    }
+}
+
+/// Result of a tempo transform, errors are measured on note starts rounded to the smallest unit.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TempoReport {
+    /// Tempo events of the song, shared by every track
+    pub tempo_count_before: usize,
+    pub tempo_count_after: usize,
+    pub note_count: usize,
+
+    /// Distance to the time the note should play at, the original time divided by the scale factor
+    pub mean_error_ms: f64,
+    pub max_error_ms: f64,
+}
+
+impl TempoReport {
+    pub(crate) fn new(tempo_counts: &[(usize, usize)], errors: &[f64]) -> Self {
+        let max_error_ms = errors.iter().copied().fold(0., f64::max);
+        let mean_error_ms = if errors.is_empty() {
+            0.
+        } else {
+            errors.iter().sum::<f64>() / errors.len() as f64
+        };
+
+        Self {
+            tempo_count_before: tempo_counts
+                .iter()
+                .map(|(before, _)| *before)
+                .max()
+                .unwrap_or(0),
+            tempo_count_after: tempo_counts
+                .iter()
+                .map(|(_, after)| *after)
+                .max()
+                .unwrap_or(0),
+            note_count: errors.len(),
+            mean_error_ms,
+            max_error_ms,
+        }
+    }
+}
+
+/// Converts positions to milliseconds with the tempo changes of a track.
+#[derive(Debug, Clone)]
+pub(crate) struct TempoMap {
+    /// (position, milliseconds at position, tempo from position)
+    points: Vec<(usize, f64, u32)>,
+    units_per_quarter: f64,
+}
+
+impl TempoMap {
+    pub fn new(mut tempos: Vec<(usize, u32)>, units_per_quarter: f64) -> Self {
+        tempos.sort_by_key(|(position, _)| *position);
+
+        let mut tempo_map = Self {
+            points: vec![(0, 0., DEFAULT_TEMPO)],
+            units_per_quarter,
+        };
+        for (position, tempo) in tempos {
+            tempo_map.push(position, tempo);
+        }
+
+        tempo_map
+    }
+
+    /// Adds a tempo after every other one, a zero tempo is ignored.
+    pub fn push(&mut self, position: usize, tempo: u32) {
+        if tempo > 0 {
+            let ms = self.ms_from_point(self.points.last().unwrap(), position as f64);
+            self.points.push((position, ms, tempo));
+        }
+    }
+
+    pub fn to_ms(&self, position: usize) -> f64 {
+        let index = self
+            .points
+            .partition_point(|(point_position, _, _)| *point_position <= position);
+        let point = &self.points[index.saturating_sub(1)];
+        self.ms_from_point(point, position as f64)
+    }
+
+    /// Position playing at `ms`, between two units when it does not fall on one.
+    pub fn to_position(&self, ms: f64) -> f64 {
+        let index = self
+            .points
+            .partition_point(|(_, point_ms, _)| *point_ms <= ms);
+        let (point_position, point_ms, tempo) = self.points[index.saturating_sub(1)];
+        point_position as f64 + (ms - point_ms) * tempo as f64 * self.units_per_quarter / 60_000.
+    }
+
+    fn ms_from_point(&self, point: &(usize, f64, u32), position: f64) -> f64 {
+        let (point_position, point_ms, tempo) = *point;
+        let ms_per_unit = 60_000. / (tempo as f64 * self.units_per_quarter);
+        point_ms + (position - point_position as f64) * ms_per_unit
+    }
+}
+
+/// `(position, tempo)` of every tempo event, the last one wins at a position.
+/// The default tempo is added at 0 when the events start later.
+pub(crate) fn get_tempos(meta_events: &[BridgeEvent]) -> Vec<(usize, u32)> {
+    let mut tempos: Vec<(usize, u32)> = meta_events
+        .iter()
+        .filter_map(|event| match event {
+            BridgeEvent::Tempo(tempo, state) if *tempo > 0 => {
+                Some((state.position_in_tick, *tempo))
+            }
+            _ => None,
+        })
+        .collect();
+    tempos.sort_by_key(|(position, _)| *position);
+
+    let mut deduplicated: Vec<(usize, u32)> = Vec::with_capacity(tempos.len() + 1);
+    for (position, tempo) in tempos {
+        match deduplicated.last_mut() {
+            Some(last) if last.0 == position => last.1 = tempo,
+            _ => deduplicated.push((position, tempo)),
+        }
+    }
+    if deduplicated
+        .first()
+        .is_none_or(|(position, _)| *position > 0)
+    {
+        deduplicated.insert(0, (0, DEFAULT_TEMPO));
+    }
+
+    deduplicated
+}
+
+/// The tempos of a song after a transform, shared by every track so they stay in sync.
+#[derive(Debug, Clone)]
+pub(crate) struct TempoPlan {
+    old_tempo_map: TempoMap,
+    new_tempo_map: TempoMap,
+    new_tempos: Vec<(usize, u32)>,
+
+    /// Scaled songs keep their positions, the other transforms move them
+    is_moved: bool,
+
+    /// Time the notes should play at divided by their original time
+    ratio: f64,
+    ppq: u16,
+}
+
+impl TempoTransform {
+    /// Computes the new tempos from the meta events shared by the tracks,
+    /// `end` is the position of the latest note end of the song.
+    pub(crate) fn plan(&self, meta_events: &[BridgeEvent], end: usize, ppq: u16) -> TempoPlan {
+        let tempos = get_tempos(meta_events);
+        let old_tempo_map = TempoMap::new(tempos.to_owned(), ppq as f64);
+        let end = end.max(get_end_position(meta_events, &[]));
+
+        let (new_tempos, ratio) = match self {
+            Self::Scale { factor } => {
+                let new_tempos: Vec<(usize, u32)> = tempos
+                    .iter()
+                    .map(|(position, tempo)| {
+                        (*position, (*tempo as f64 * factor).round().max(1.) as u32)
+                    })
+                    .collect();
+                (new_tempos, 1. / factor)
+            }
+            Self::Flatten { tempo } => {
+                let groups = vec![(0, tempos.len())];
+                let new_tempos =
+                    merge_tempo_groups(&tempos, &groups, &old_tempo_map, *tempo, end, ppq);
+                (new_tempos, 1.)
+            }
+            Self::Merge { threshold } => {
+                let groups = group_tempos(&tempos, *threshold);
+                let new_tempos =
+                    merge_tempo_groups(&tempos, &groups, &old_tempo_map, None, end, ppq);
+                (new_tempos, 1.)
+            }
+        };
+
+        TempoPlan {
+            old_tempo_map,
+            new_tempo_map: TempoMap::new(new_tempos.to_owned(), ppq as f64),
+            new_tempos,
+            is_moved: !matches!(self, Self::Scale { .. }),
+            ratio,
+            ppq,
+        }
+    }
+}
+
+impl TempoPlan {
+    fn move_position(&self, position: usize) -> usize {
+        if !self.is_moved {
+            return position;
+        }
+
+        let ms = self.old_tempo_map.to_ms(position);
+        self.new_tempo_map.to_position(ms).round().max(0.) as usize
+    }
+
+    /// Moves the notes of a track to the new tempos.
+    /// Returns the error of every note in milliseconds.
+    pub fn move_notes(&self, note_events: &mut [BridgeEvent], smallest_unit: usize) -> Vec<f64> {
+        let unit_in_tick = utils::get_smallest_unit_in_tick(self.ppq, smallest_unit) as f64;
+        let quantize = |position: usize| {
+            ((position as f64 / unit_in_tick).round() * unit_in_tick).round() as usize
+        };
+
+        let mut errors: Vec<f64> = Vec::new();
+        for event in note_events.iter_mut() {
+            let state = get_state_mut(event);
+            let start = state.position_in_tick;
+            let end = start + state.duration_in_tick;
+            let new_start = self.move_position(start);
+            let new_end = self.move_position(end).max(new_start);
+
+            if let BridgeEvent::Note(_) = event {
+                let expected_ms = self.old_tempo_map.to_ms(quantize(start)) * self.ratio;
+                let actual_ms = self.new_tempo_map.to_ms(quantize(new_start));
+                errors.push((actual_ms - expected_ms).abs());
+            }
+
+            let state = get_state_mut(event);
+            state.position_in_tick = new_start;
+            if state.duration_in_tick > 0 {
+                state.duration_in_tick = (new_end - new_start).max(1);
+            }
+        }
+
+        errors
+    }
+
+    /// The meta events with the new tempos and the other events moved.
+    pub fn move_meta_events(&self, meta_events: &[BridgeEvent]) -> Vec<BridgeEvent> {
+        let tempo_state = meta_events
+            .iter()
+            .find_map(|event| match event {
+                BridgeEvent::Tempo(_, state) => Some(state.to_owned()),
+                _ => None,
+            })
+            .unwrap_or(MidiState {
+                position_in_tick: 0,
+                duration_in_tick: 0,
+                channel: 0,
+            });
+
+        let mut new_meta_events: Vec<BridgeEvent> = meta_events
+            .iter()
+            .filter(|event| !matches!(event, BridgeEvent::Tempo(_, _)))
+            .cloned()
+            .collect();
+        for event in new_meta_events.iter_mut() {
+            let state = get_state_mut(event);
+            state.position_in_tick = self.move_position(state.position_in_tick);
+        }
+        new_meta_events.extend(self.new_tempos.iter().map(|(position, tempo)| {
+            BridgeEvent::Tempo(
+                *tempo,
+                MidiState {
+                    position_in_tick: *position,
+                    ..tempo_state.to_owned()
+                },
+            )
+        }));
+        new_meta_events.sort();
+
+        new_meta_events
+    }
+}
+
+/// `(first, end)` index ranges of the tempos that stay within `threshold` of the first of the range.
+fn group_tempos(tempos: &[(usize, u32)], threshold: u32) -> Vec<(usize, usize)> {
+    let mut groups: Vec<(usize, usize)> = Vec::new();
+
+    for (index, (_, tempo)) in tempos.iter().enumerate() {
+        match groups.last_mut() {
+            Some((first, end)) if tempos[*first].1.abs_diff(*tempo) < threshold => *end = index + 1,
+            _ => groups.push((index, index + 1)),
+        }
+    }
+
+    groups
+}
+
+/// One tempo per group at the position its first tempo plays at with the new tempos.
+/// Without a fixed tempo, a group gets its mean tempo so it keeps its length.
+fn merge_tempo_groups(
+    tempos: &[(usize, u32)],
+    groups: &[(usize, usize)],
+    old_tempo_map: &TempoMap,
+    fixed_tempo: Option<u32>,
+    end: usize,
+    ppq: u16,
+) -> Vec<(usize, u32)> {
+    let mut new_tempo_map = TempoMap::new(Vec::new(), ppq as f64);
+    let mut new_tempos: Vec<(usize, u32)> = Vec::with_capacity(groups.len());
+
+    for (first, group_end) in groups.iter() {
+        let start = tempos[*first].0;
+        let stop = tempos
+            .get(*group_end)
+            .map(|(position, _)| *position)
+            .unwrap_or(end);
+
+        let tempo = fixed_tempo.filter(|tempo| *tempo > 0).unwrap_or_else(|| {
+            let duration_ms = old_tempo_map.to_ms(stop) - old_tempo_map.to_ms(start);
+            if stop > start && duration_ms > 0. {
+                let quarters = (stop - start) as f64 / ppq as f64;
+                (quarters * 60_000. / duration_ms).round().max(1.) as u32
+            } else {
+                tempos[*first].1
+            }
+        });
+
+        let position = if new_tempos.is_empty() {
+            0
+        } else {
+            let ms = old_tempo_map.to_ms(start);
+            new_tempo_map.to_position(ms).round().max(0.) as usize
+        };
+        new_tempo_map.push(position, tempo);
+        new_tempos.push((position, tempo));
+    }
+
+    new_tempos
+}
+
+/// Latest note end or meta event position.
+pub(crate) fn get_end_position(meta_events: &[BridgeEvent], note_events: &[BridgeEvent]) -> usize {
+    meta_events
+        .iter()
+        .chain(note_events.iter())
+        .map(|event| match event {
+            BridgeEvent::Note(note) => {
+                note.midi_state.position_in_tick + note.midi_state.duration_in_tick
+            }
+            BridgeEvent::Tempo(_, state)
+            | BridgeEvent::ProgramChange(_, state)
+            | BridgeEvent::TimeSignature(_, _, state) => state.position_in_tick,
+        })
+        .max()
+        .unwrap_or(0)
+}
+
+fn get_state_mut(event: &mut BridgeEvent) -> &mut MidiState {
+    match event {
+        BridgeEvent::Note(note) => &mut note.midi_state,
+        BridgeEvent::Tempo(_, state)
+        | BridgeEvent::ProgramChange(_, state)
+        | BridgeEvent::TimeSignature(_, _, state) => state,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{note, tempo};
+
+    /// Transforms the tempos of a single track at 480 PPQ, returns the error of every note.
+    fn apply(
+        transform: &TempoTransform,
+        meta_events: &mut Vec<BridgeEvent>,
+        note_events: &mut [BridgeEvent],
+    ) -> Vec<f64> {
+        let plan = transform.plan(meta_events, get_end_position(&[], note_events), 480);
+        *meta_events = plan.move_meta_events(meta_events);
+        plan.move_notes(note_events, 64)
+    }
+
+    fn positions(events: &[BridgeEvent]) -> Vec<(usize, usize)> {
+        events
+            .iter()
+            .map(|event| match event {
+                BridgeEvent::Note(note) => (
+                    note.midi_state.position_in_tick,
+                    note.midi_state.duration_in_tick,
+                ),
+                _ => panic!("Expected note event"),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_tempo_map_to_position() {
+        let tempo_map = TempoMap::new(vec![(0, 120), (960, 60)], 480.);
+
+        assert_eq!(tempo_map.to_ms(960).round(), 1000.);
+        assert_eq!(tempo_map.to_ms(1440).round(), 2000.);
+        assert_eq!(tempo_map.to_position(500.).round(), 480.);
+        assert_eq!(tempo_map.to_position(2000.).round(), 1440.);
+    }
+
+    #[test]
+    fn test_get_tempos() {
+        let events = vec![tempo(100, 480), tempo(90, 960), tempo(80, 960)];
+
+        assert_eq!(get_tempos(&events), vec![(0, 120), (480, 100), (960, 80)]);
+    }
+
+    #[test]
+    fn test_scale() {
+        let mut meta_events = vec![tempo(120, 0), tempo(90, 960)];
+        let mut note_events = vec![note(60, 0, 480), note(60, 960, 480)];
+
+        let errors = apply(
+            &TempoTransform::Scale { factor: 1.5 },
+            &mut meta_events,
+            &mut note_events,
+        );
+
+        assert_eq!(get_tempos(&meta_events), vec![(0, 180), (960, 135)]);
+        assert_eq!(positions(&note_events), vec![(0, 480), (960, 480)]);
+        assert!(errors.iter().all(|error| *error < 1e-6));
+    }
+
+    #[test]
+    fn test_flatten_keeps_time() {
+        // 2 quarters at 120 then 2 quarters at 60: 3 seconds for 4 quarters, 80 BPM
+        let mut meta_events = vec![tempo(120, 0), tempo(60, 960)];
+        let mut note_events = vec![note(60, 0, 960), note(60, 960, 480), note(60, 1440, 480)];
+
+        let errors = apply(
+            &TempoTransform::Flatten { tempo: None },
+            &mut meta_events,
+            &mut note_events,
+        );
+
+        assert_eq!(get_tempos(&meta_events), vec![(0, 80)]);
+        assert_eq!(
+            positions(&note_events),
+            vec![(0, 640), (640, 640), (1280, 640)]
+        );
+        assert!(errors.iter().all(|error| *error < 25.));
+    }
+
+    #[test]
+    fn test_flatten_with_tempo() {
+        let mut meta_events = vec![tempo(120, 0), tempo(60, 960)];
+        let mut note_events = vec![note(60, 960, 480)];
+
+        apply(
+            &TempoTransform::Flatten { tempo: Some(120) },
+            &mut meta_events,
+            &mut note_events,
+        );
+
+        assert_eq!(get_tempos(&meta_events), vec![(0, 120)]);
+        assert_eq!(positions(&note_events), vec![(960, 960)]);
+    }
+
+    #[test]
+    fn test_merge() {
+        let mut meta_events = vec![
+            tempo(120, 0),
+            tempo(121, 480),
+            tempo(119, 960),
+            tempo(90, 1440),
+            tempo(91, 1920),
+        ];
+        let mut note_events: Vec<BridgeEvent> =
+            (0..6).map(|index| note(60, index * 480, 480)).collect();
+
+        let errors = apply(
+            &TempoTransform::Merge { threshold: 3 },
+            &mut meta_events,
+            &mut note_events,
+        );
+
+        let tempos = get_tempos(&meta_events);
+        assert_eq!(tempos.len(), 2);
+        assert_eq!(tempos[0], (0, 120));
+        assert_eq!(tempos[1].1, 91);
+        assert!(tempos[1].0.abs_diff(1440) <= 1);
+        assert!(errors.iter().all(|error| *error < 10.));
+    }
+
+    #[test]
+    fn test_group_tempos() {
+        let tempos = vec![(0, 100), (1, 102), (2, 104), (3, 105), (4, 80)];
+
+        assert_eq!(group_tempos(&tempos, 5), vec![(0, 3), (3, 4), (4, 5)]);
+        assert_eq!(group_tempos(&tempos, 0).len(), 5);
+    }
+
+    #[test]
+    fn test_transform_json() {
+        let transform = TempoTransform::Merge { threshold: 4 };
+        let json = serde_json::to_string(&transform).unwrap();
+
+        assert_eq!(json, r#"{"type":"merge","threshold":4}"#);
+        assert_eq!(
+            serde_json::from_str::<TempoTransform>(&json).unwrap(),
+            transform
+        );
+    }
+}
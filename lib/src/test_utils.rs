@@ -3,7 +3,7 @@ use std::fs;
 use midly::{Smf, Timing};
 
 use crate::{
-    BridgeEvent, MmlSongOptions,
+    BridgeEvent, MidiNoteState, MidiState, MmlSongOptions, MmlTrack,
     parser::{bridge_meta_from_midi_track, bridge_notes_from_midi_track},
 };
 
@@ -32,3 +32,38 @@ pub fn setup_bridge_events(midi_path: &str) -> (Vec<BridgeEvent>, MmlSongOptions
 
     (bridge_events, options, ppq)
 }
+
+pub fn state(position_in_tick: usize, duration_in_tick: usize) -> MidiState {
+    MidiState {
+        position_in_tick,
+        duration_in_tick,
+        channel: 0,
+    }
+}
+
+pub fn note(key: u8, position: usize, duration: usize) -> BridgeEvent {
+    BridgeEvent::Note(MidiNoteState {
+        key,
+        velocity: 100,
+        midi_state: state(position, duration),
+    })
+}
+
+pub fn tempo(tempo: u32, position: usize) -> BridgeEvent {
+    BridgeEvent::Tempo(tempo, state(position, 0))
+}
+
+/// A track named `Lead` at 480 PPQ.
+pub fn create_track(
+    meta_events: Vec<BridgeEvent>,
+    note_events: Vec<BridgeEvent>,
+    options: &MmlSongOptions,
+) -> MmlTrack {
+    MmlTrack::from_bridge_events(
+        String::from("Lead"),
+        meta_events,
+        note_events,
+        options.to_owned(),
+        480,
+    )
+}
@@ -1,6 +1,25 @@
-use crate::{MmlSongOptions, mml_event::MmlEvent, mml_track::MmlTrack, pitch_class::PitchClass};
+use crate::{
+    MmlSongOptions,
+    dialect::{MmlDialect, RevelationDialect},
+    mml_event::{BridgeEvent, MmlEvent},
+    mml_track::MmlTrack,
+    pitch_class::PitchClass,
+};
 use rayon::prelude::*;
-use std::convert::TryInto;
+use serde::{Deserialize, Serialize};
+use std::{collections::BTreeMap, convert::TryInto};
+
+/// What `balance_tracks` tries to make equal between tracks.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BalanceMetric {
+    /// Sum of `mml_note_length`, the number of notes including ties
+    #[default]
+    NoteLength,
+
+    /// Number of MML characters
+    Characters,
+}
 
 pub fn compute_position_in_smallest_unit(events: &[MmlEvent], current_index: usize) -> usize {
     let mut duration = 0usize;
@@ -18,6 +37,22 @@ pub fn compute_position_in_smallest_unit(events: &[MmlEvent], current_index: usi
     duration
 }
 
+/// Merges two sorted sequences into one sorted iterator, `a` comes first on ties.
+/// The result is the one of a stable sort of `a` followed by `b`.
+pub fn merge_sorted<T: Ord>(
+    a: impl IntoIterator<Item = T>,
+    b: impl IntoIterator<Item = T>,
+) -> impl Iterator<Item = T> {
+    let mut a = a.into_iter().peekable();
+    let mut b = b.into_iter().peekable();
+
+    std::iter::from_fn(move || match (a.peek(), b.peek()) {
+        (Some(item_a), Some(item_b)) if item_b < item_a => b.next(),
+        (Some(_), _) => a.next(),
+        (None, _) => b.next(),
+    })
+}
+
 pub fn count_mml_notes(mml_string: &str) -> usize {
     mml_string.split("&").count()
 }
@@ -44,13 +79,14 @@ pub fn equalize_tracks(track_a: &mut MmlTrack, track_b: &mut MmlTrack) {
         let (left, right) = a
             .bridge_note_events
             .split_at(bridge_event_center_index.floor() as usize);
-        let mut left = left.to_vec();
+        let left = left.to_vec();
         let right = right.to_vec();
 
         a.bridge_note_events = right;
         a.generate_mml_events();
 
-        b.bridge_note_events.append(&mut left);
+        b.bridge_note_events =
+            merge_sorted(std::mem::take(&mut b.bridge_note_events), left).collect();
         b.generate_mml_events();
     };
 
@@ -65,14 +101,160 @@ pub fn equalize_tracks(track_a: &mut MmlTrack, track_b: &mut MmlTrack) {
     }
 }
 
+/// Balances the tracks by moving whole bars from the heaviest track to the lightest one,
+/// until no move makes the two closer.
+/// Notes starting within `min_gap_for_chord` of each other move together,
+/// so a chord crossing a bar line is never split.
+/// Bars where the lightest track is silent are moved first to avoid creating chords.
+pub fn balance_tracks(tracks: &mut [&mut MmlTrack], metric: BalanceMetric) {
+    let Some(first) = tracks.first() else {
+        return;
+    };
+    let bar_lengths = get_bar_lengths_in_tick(&first.bridge_meta_events, first.ppq);
+
+    let mut costs: Vec<BTreeMap<usize, usize>> = tracks
+        .iter()
+        .map(|track| get_bar_costs(track, &bar_lengths, metric))
+        .collect();
+    let mut notes: Vec<BTreeMap<usize, Vec<BridgeEvent>>> = tracks
+        .iter()
+        .map(|track| group_notes_by_bar(track, &bar_lengths))
+        .collect();
+    let mut is_changed = vec![false; tracks.len()];
+
+    loop {
+        let totals: Vec<usize> = costs.iter().map(|cost| cost.values().sum()).collect();
+        let (Some(heaviest), Some(lightest)) = (
+            (0..totals.len()).max_by_key(|index| totals[*index]),
+            (0..totals.len()).min_by_key(|index| totals[*index]),
+        ) else {
+            break;
+        };
+
+        // Moving less than the difference always makes the pair closer
+        let diff = totals[heaviest] - totals[lightest];
+        let candidate = costs[heaviest]
+            .iter()
+            .filter(|(_, cost)| **cost > 0 && **cost < diff)
+            .min_by_key(|(bar, cost)| {
+                (
+                    costs[lightest].contains_key(bar),
+                    cost.abs_diff(diff / 2),
+                    **bar,
+                )
+            })
+            .map(|(bar, cost)| (*bar, *cost));
+
+        let Some((bar, cost)) = candidate else {
+            break;
+        };
+
+        costs[heaviest].remove(&bar);
+        *costs[lightest].entry(bar).or_default() += cost;
+
+        let moved = notes[heaviest].remove(&bar).unwrap_or_default();
+        notes[lightest].entry(bar).or_default().extend(moved);
+
+        is_changed[heaviest] = true;
+        is_changed[lightest] = true;
+    }
+
+    for ((track, notes), is_changed) in tracks.iter_mut().zip(notes).zip(is_changed) {
+        if !is_changed {
+            continue;
+        }
+
+        let mut events: Vec<BridgeEvent> = track
+            .bridge_note_events
+            .iter()
+            .filter(|event| !matches!(event, BridgeEvent::Note(_)))
+            .cloned()
+            .collect();
+        events.extend(notes.into_values().flatten());
+        events.sort();
+
+        track.bridge_note_events = events;
+        track.generate_mml_events();
+    }
+}
+
+/// Cost of every bar with notes, the position of each event is the one of the last note.
+fn get_bar_costs(
+    track: &MmlTrack,
+    bar_lengths: &[(usize, usize)],
+    metric: BalanceMetric,
+) -> BTreeMap<usize, usize> {
+    let smallest_unit = track.song_options.smallest_unit;
+    let mut costs: BTreeMap<usize, usize> = BTreeMap::new();
+    let mut bar = 0usize;
+
+    for event in track.events.iter() {
+        if let MmlEvent::Note(note) = event {
+            let tick =
+                smallest_unit_to_tick(note.position_in_smallest_unit, track.ppq, smallest_unit);
+            bar = tick_to_bar(bar_lengths, tick);
+        }
+
+        let cost = match (metric, event) {
+            (BalanceMetric::NoteLength, MmlEvent::Note(note)) => note.mml_note_length,
+            (BalanceMetric::NoteLength, _) => continue,
+            (BalanceMetric::Characters, event) => event.to_mml(smallest_unit).len(),
+        };
+        *costs.entry(bar).or_default() += cost;
+    }
+
+    costs
+}
+
+/// Note events by the bar where their chord starts.
+fn group_notes_by_bar(
+    track: &MmlTrack,
+    bar_lengths: &[(usize, usize)],
+) -> BTreeMap<usize, Vec<BridgeEvent>> {
+    let chord_gap = smallest_unit_to_tick(
+        track.song_options.min_gap_for_chord as usize,
+        track.ppq,
+        track.song_options.smallest_unit,
+    );
+
+    let sorted_notes = track
+        .bridge_note_events
+        .iter()
+        .filter(|event| matches!(event, BridgeEvent::Note(_)));
+
+    let mut groups: BTreeMap<usize, Vec<BridgeEvent>> = BTreeMap::new();
+    let mut chord: Option<(usize, usize)> = None;
+
+    for event in sorted_notes {
+        let BridgeEvent::Note(note) = event else {
+            continue;
+        };
+        let position = note.midi_state.position_in_tick;
+
+        let bar = match chord {
+            Some((start, bar)) if position <= start + chord_gap => bar,
+            _ => {
+                let bar = tick_to_bar(bar_lengths, position);
+                chord = Some((position, bar));
+                bar
+            }
+        };
+        groups.entry(bar).or_default().push(event.to_owned());
+    }
+
+    groups
+}
+
+/// The largest boost keeping every track under its own `velocity_max`.
 pub fn get_song_velocity_diff(song_options: &MmlSongOptions, tracks: &[MmlTrack]) -> u8 {
-    let velocity_max: u8 = tracks
+    tracks
         .par_iter()
-        .map(|track| get_highest_velocity(&track.events))
-        .max()
-        .unwrap_or(0);
-
-    song_options.velocity_max - velocity_max
+        .map(|track| {
+            let velocity_max = track.song_options.velocity_max;
+            velocity_max.saturating_sub(get_highest_velocity(&track.events))
+        })
+        .min()
+        .unwrap_or(song_options.velocity_max)
 }
 
 pub fn auto_boot_song_velocity(tracks: &mut [MmlTrack], velocity_diff: u8) {
@@ -98,6 +280,63 @@ pub fn midi_velocity_to_mml_velocity(midi_velocity: u8, velocity_min: u8, veloci
         .unwrap()
 }
 
+/// MML velocity (0-15) to MIDI velocity (0-127).
+/// Rounded up, so that `midi_velocity_to_mml_velocity(x, 0, 15)` gives the MML velocity back.
+pub fn mml_velocity_to_midi_velocity(mml_velocity: u8) -> u8 {
+    let midi_velocity = (mml_velocity as u32 * 127).div_ceil(15);
+    midi_velocity.min(127) as u8
+}
+
+pub fn smallest_unit_to_tick(smallest_unit_count: usize, ppq: u16, smallest_unit: usize) -> usize {
+    let note = get_smallest_unit_in_tick(ppq, smallest_unit);
+    (smallest_unit_count as f32 * note).round() as usize
+}
+
+/// (start tick, bar length in tick) of every time signature, 4/4 is used before the first one.
+pub fn get_bar_lengths_in_tick(meta_events: &[BridgeEvent], ppq: u16) -> Vec<(usize, usize)> {
+    let mut time_signatures: Vec<(usize, usize)> = meta_events
+        .iter()
+        .filter_map(|event| match event {
+            BridgeEvent::TimeSignature(numerator, denominator, state) => {
+                let length =
+                    ppq as usize * 4 * *numerator as usize / (*denominator).max(1) as usize;
+                Some((state.position_in_tick, length.max(1)))
+            }
+            _ => None,
+        })
+        .collect();
+
+    time_signatures.sort_by_key(|(start, _)| *start);
+    time_signatures.dedup_by_key(|(start, _)| *start);
+
+    if time_signatures.first().is_none_or(|(start, _)| *start > 0) {
+        time_signatures.insert(0, (0, ppq as usize * 4));
+    }
+
+    time_signatures
+}
+
+/// Index of the bar containing the tick, from 0.
+/// `bar_lengths` comes from `get_bar_lengths_in_tick`.
+pub fn tick_to_bar(bar_lengths: &[(usize, usize)], tick: usize) -> usize {
+    let mut bar = 0usize;
+
+    for (index, (start, length)) in bar_lengths.iter().enumerate() {
+        let end = bar_lengths
+            .get(index + 1)
+            .map(|(start, _)| *start)
+            .unwrap_or(usize::MAX);
+
+        if tick < end {
+            return bar + tick.saturating_sub(*start) / length;
+        }
+
+        bar += (end - start).div_ceil(*length);
+    }
+
+    bar
+}
+
 pub fn get_highest_velocity(events: &[MmlEvent]) -> u8 {
     let mut max = 0u8;
 
@@ -180,46 +419,167 @@ fn get_list_of_mml_notes(smallest_unit: usize) -> Vec<CustomMmlNote> {
 }
 
 pub fn get_display_mml(
-    mut duration_in_smallest_unit: usize,
+    duration_in_smallest_unit: usize,
+    note_class: &PitchClass,
+    smallest_unit: usize,
+) -> String {
+    get_display_mml_with_dialect(
+        duration_in_smallest_unit,
+        note_class,
+        smallest_unit,
+        &RevelationDialect,
+    )
+}
+
+/// Duration of the shortest length allowed by the dialect, 1 when every length is allowed.
+pub fn get_shortest_length_in_smallest_unit(
+    smallest_unit: usize,
+    dialect: &dyn MmlDialect,
+) -> usize {
+    get_list_of_mml_notes(smallest_unit)
+        .into_iter()
+        .filter(|note| dialect.is_length_allowed(note.mml_value))
+        .map(|note| note.duration_in_smallest_unit)
+        .min()
+        .unwrap_or(1)
+}
+
+/// Same as `get_display_mml` but spelled in the given dialect.
+/// The duration is rounded to the nearest multiple of the shortest length allowed by the dialect,
+/// `MmlTrack::to_mml_with_dialect` carries the rounding into the next note or rest.
+pub fn get_display_mml_with_dialect(
+    duration_in_smallest_unit: usize,
     note_class: &PitchClass,
     smallest_unit: usize,
+    dialect: &dyn MmlDialect,
 ) -> String {
     let mut result: Vec<String> = Vec::new();
-    let notes = get_list_of_mml_notes(smallest_unit);
+    let pitch = dialect.pitch(note_class);
+    let notes: Vec<CustomMmlNote> = get_list_of_mml_notes(smallest_unit)
+        .into_iter()
+        .filter(|note| dialect.is_length_allowed(note.mml_value))
+        .collect();
+    let shortest = get_shortest_length_in_smallest_unit(smallest_unit, dialect);
+    let mut duration_in_smallest_unit = round_to_multiple(duration_in_smallest_unit, shortest);
 
     while duration_in_smallest_unit > 0 {
-        let mut current_note: usize = 0;
+        let Some(mml_note) = notes
+            .iter()
+            .find(|note| duration_in_smallest_unit >= note.duration_in_smallest_unit)
+        else {
+            break;
+        };
+        duration_in_smallest_unit -= mml_note.duration_in_smallest_unit;
+        let current_note = mml_note.mml_value;
 
-        for mml_note in notes.iter() {
-            if duration_in_smallest_unit >= mml_note.duration_in_smallest_unit {
-                duration_in_smallest_unit -= mml_note.duration_in_smallest_unit;
-                current_note = mml_note.mml_value;
-                break;
-            }
+        if !result.is_empty() {
+            result.push(dialect.tie().to_string());
         }
-
-        result.push(format!("{}{}", note_class, current_note));
+        result.push(format!("{}{}", pitch, current_note));
 
         let half_of_current_note = smallest_unit / (current_note * 2);
-        if duration_in_smallest_unit > 0 && duration_in_smallest_unit >= half_of_current_note {
-            result.push(".".to_string());
+        if let Some(dot) = dialect.dot()
+            && duration_in_smallest_unit > 0
+            && duration_in_smallest_unit >= half_of_current_note
+        {
+            result.push(dot.to_string());
             duration_in_smallest_unit -= half_of_current_note;
         }
-
-        if duration_in_smallest_unit == 0 {
-            break;
-        } else {
-            result.push("&".to_string());
-        }
     }
 
     result.join("")
 }
 
+/// Rounds half up to the nearest multiple of `unit`.
+pub fn round_to_multiple(value: usize, unit: usize) -> usize {
+    (value + unit / 2) / unit * unit
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::{MmlEvent, PitchClass};
+    use crate::{
+        MmlEvent, PitchClass,
+        test_utils::{create_track, note},
+    };
+
+    fn create_notes_track(notes: &[(u8, usize, usize)], options: &MmlSongOptions) -> MmlTrack {
+        let notes = notes
+            .iter()
+            .map(|(key, position, duration)| note(*key, *position, *duration))
+            .collect();
+        create_track(vec![], notes, options)
+    }
+
+    #[test]
+    fn test_balance_tracks() {
+        let options = MmlSongOptions::default();
+        let quarter_notes: Vec<(u8, usize, usize)> =
+            (0..16).map(|index| (60, index * 480, 480)).collect();
+        let mut track_a = create_notes_track(&quarter_notes, &options);
+        let mut track_b = create_notes_track(&[(64, 0, 480)], &options);
+        let mut track_c = create_notes_track(&[], &options);
+
+        balance_tracks(
+            &mut [&mut track_a, &mut track_b, &mut track_c],
+            BalanceMetric::NoteLength,
+        );
+
+        assert_eq!(
+            vec![
+                track_a.mml_note_length,
+                track_b.mml_note_length,
+                track_c.mml_note_length
+            ],
+            vec![8, 5, 4]
+        );
+        // Bars are moved whole, to the bars where the other track is silent
+        let get_starts = |track: &MmlTrack| -> Vec<usize> {
+            track
+                .bridge_note_events
+                .iter()
+                .filter_map(|event| match event {
+                    BridgeEvent::Note(note) => Some(note.midi_state.position_in_tick),
+                    _ => None,
+                })
+                .collect()
+        };
+        assert_eq!(get_starts(&track_c), vec![0, 480, 960, 1440]);
+        assert_eq!(get_starts(&track_b), vec![0, 1920, 2400, 2880, 3360]);
+    }
+
+    #[test]
+    fn test_group_notes_by_bar_keeps_chords() {
+        let options = MmlSongOptions {
+            min_gap_for_chord: 2,
+            ..MmlSongOptions::default()
+        };
+        let track = create_notes_track(
+            &[(60, 1900, 480), (64, 1940, 480), (67, 2400, 480)],
+            &options,
+        );
+        let bar_lengths = get_bar_lengths_in_tick(&[], 480);
+
+        let groups: Vec<(usize, usize)> = group_notes_by_bar(&track, &bar_lengths)
+            .iter()
+            .map(|(bar, notes)| (*bar, notes.len()))
+            .collect();
+
+        assert_eq!(groups, vec![(0, 2), (1, 1)]);
+    }
+
+    #[test]
+    fn test_bar_costs_characters() {
+        let options = MmlSongOptions::default();
+        let track = create_notes_track(&[(60, 0, 480), (72, 1920, 960)], &options);
+        let bar_lengths = get_bar_lengths_in_tick(&[], 480);
+
+        let costs = get_bar_costs(&track, &bar_lengths, BalanceMetric::Characters);
+        let total: usize = costs.values().sum();
+
+        assert_eq!(total, track.to_mml().len());
+        assert_eq!(costs.keys().copied().collect::<Vec<usize>>(), vec![0, 1]);
+    }
 
     #[test]
     fn test_count_mml_notes() {
@@ -245,6 +605,21 @@ mod tests {
         assert_eq!(midi_velocity_to_mml_velocity(126, 0, 15), 14);
     }
 
+    #[test]
+    fn test_mml_velocity_to_midi_velocity() {
+        assert_eq!(mml_velocity_to_midi_velocity(0), 0);
+        assert_eq!(mml_velocity_to_midi_velocity(15), 127);
+        assert_eq!(mml_velocity_to_midi_velocity(20), 127);
+
+        for mml_velocity in 0..=15 {
+            let midi_velocity = mml_velocity_to_midi_velocity(mml_velocity);
+            assert_eq!(
+                midi_velocity_to_mml_velocity(midi_velocity, 0, 15),
+                mml_velocity
+            );
+        }
+    }
+
     #[test]
     fn test_midi_key_to_pitch_class() {
         // Test C notes
@@ -309,6 +684,47 @@ mod tests {
         assert_eq!(tick_to_smallest_unit(45, ppq, smallest_unit), 2); // Rounds up
     }
 
+    #[test]
+    fn test_smallest_unit_to_tick() {
+        assert_eq!(smallest_unit_to_tick(16, 480, 64), 480);
+        assert_eq!(smallest_unit_to_tick(3, 96, 64), 18);
+        assert_eq!(
+            tick_to_smallest_unit(smallest_unit_to_tick(37, 480, 64), 480, 64),
+            37
+        );
+    }
+
+    #[test]
+    fn test_tick_to_bar() {
+        use crate::MidiState;
+
+        let time_signature = |numerator: u8, denominator: u8, position: usize| {
+            BridgeEvent::TimeSignature(
+                numerator,
+                denominator,
+                MidiState {
+                    position_in_tick: position,
+                    duration_in_tick: 0,
+                    channel: 0,
+                },
+            )
+        };
+
+        assert_eq!(get_bar_lengths_in_tick(&[], 480), vec![(0, 1920)]);
+
+        // 4/4 for 2 bars, then 3/4, then 6/8
+        let meta_events = vec![time_signature(6, 8, 6720), time_signature(3, 4, 3840)];
+        let bar_lengths = get_bar_lengths_in_tick(&meta_events, 480);
+        assert_eq!(bar_lengths, vec![(0, 1920), (3840, 1440), (6720, 1440)]);
+
+        assert_eq!(tick_to_bar(&bar_lengths, 0), 0);
+        assert_eq!(tick_to_bar(&bar_lengths, 3839), 1);
+        assert_eq!(tick_to_bar(&bar_lengths, 3840), 2);
+        assert_eq!(tick_to_bar(&bar_lengths, 5280), 3);
+        assert_eq!(tick_to_bar(&bar_lengths, 6720), 4);
+        assert_eq!(tick_to_bar(&bar_lengths, 8160), 5);
+    }
+
     #[test]
     fn test_get_display_mml_basic_notes() {
         let smallest_unit = 64;
@@ -358,6 +774,42 @@ mod tests {
         ); // Dotted whole + half (96+32=128)
     }
 
+    #[test]
+    fn test_get_display_mml_with_dialect() {
+        use crate::{GenericDialect, MabinogiDialect};
+
+        assert_eq!(
+            get_display_mml_with_dialect(20, &PitchClass::Db, 64, &RevelationDialect),
+            "c+4&c+16"
+        );
+        assert_eq!(
+            get_display_mml_with_dialect(24, &PitchClass::Eb, 64, &GenericDialect),
+            "d#4."
+        );
+
+        // 1/128 is not allowed in Mabinogi, the duration is rounded to a multiple of 1/64
+        assert_eq!(
+            get_display_mml_with_dialect(3, &PitchClass::C, 128, &MabinogiDialect),
+            "c32"
+        );
+        assert_eq!(
+            get_display_mml_with_dialect(5, &PitchClass::C, 128, &MabinogiDialect),
+            "c32."
+        );
+        assert_eq!(
+            get_display_mml_with_dialect(1, &PitchClass::C, 128, &MabinogiDialect),
+            "c64"
+        );
+        assert_eq!(
+            get_shortest_length_in_smallest_unit(128, &MabinogiDialect),
+            2
+        );
+        assert_eq!(
+            get_shortest_length_in_smallest_unit(128, &RevelationDialect),
+            1
+        );
+    }
+
     #[test]
     fn test_get_highest_velocity() {
         let events = vec![
@@ -436,4 +888,28 @@ mod tests {
         // Test minimum MIDI velocity
         assert_eq!(midi_velocity_to_mml_velocity(0, 0, 15), 0);
     }
+
+    #[test]
+    fn test_merge_sorted() {
+        // Notes at the same position are equal, only the key tells them apart
+        let note = |key: u8, position_in_tick: usize| note(key, position_in_tick, 0);
+        let get_keys = |events: Vec<BridgeEvent>| {
+            events
+                .iter()
+                .map(|event| match event {
+                    BridgeEvent::Note(note) => note.key,
+                    _ => 0,
+                })
+                .collect::<Vec<u8>>()
+        };
+
+        let a = vec![note(1, 0), note(2, 480), note(3, 960)];
+        let b = vec![note(4, 480), note(5, 480), note(6, 1440)];
+        let merged: Vec<BridgeEvent> = merge_sorted(a.to_owned(), b.to_owned()).collect();
+
+        let mut sorted = [a, b].concat();
+        sorted.sort();
+        assert_eq!(get_keys(merged), vec![1, 2, 4, 5, 3, 6]);
+        assert_eq!(get_keys(sorted), vec![1, 2, 4, 5, 3, 6]);
+    }
 }
@@ -0,0 +1,260 @@
+use serde::{Deserialize, Serialize};
+
+use crate::{mml_event::BridgeEvent, utils};
+
+/// How MIDI velocities (0-127) are spread over the MML velocity range.
+/// Strengths are compared bit for bit, so curves are `Eq` like the rest of the options.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum VelocityCurve {
+    /// Same as `utils::midi_velocity_to_mml_velocity`
+    #[default]
+    Linear,
+
+    /// Keeps soft notes soft and spreads the loud ones, a higher strength bends more.
+    Exponential { strength: f32 },
+
+    /// Lifts soft notes and spreads them, a higher strength bends more.
+    Logarithmic { strength: f32 },
+
+    /// `(midi velocity, mml velocity)` points joined by straight lines.
+    /// Velocities before the first point or after the last one take the value of that point.
+    Piecewise { points: Vec<(u8, u8)> },
+
+    /// Maps the `low` percentile of the song velocities to the lowest MML velocity
+    /// and the `high` percentile to the highest, so most notes use the whole range.
+    Percentile {
+        low: u8,
+        high: u8,
+
+        /// MIDI velocities at both percentiles, set by `fit` when the song is read
+        #[serde(default)]
+        fitted: Option<(u8, u8)>,
+    },
+}
+
+impl PartialEq for VelocityCurve {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Self::Linear, Self::Linear) => true,
+            (Self::Exponential { strength: a }, Self::Exponential { strength: b })
+            | (Self::Logarithmic { strength: a }, Self::Logarithmic { strength: b }) => {
+                a.to_bits() == b.to_bits()
+            }
+            (Self::Piecewise { points: a }, Self::Piecewise { points: b }) => a == b,
+            (
+                Self::Percentile { low, high, fitted },
+                Self::Percentile {
+                    low: other_low,
+                    high: other_high,
+                    fitted: other_fitted,
+                },
+            ) => low == other_low && high == other_high && fitted == other_fitted,
+            _ => false,
+        }
+    }
+}
+
+impl Eq for VelocityCurve {}
+
+impl VelocityCurve {
+    /// The MML velocity between `velocity_min` and `velocity_max` (swapped when reversed).
+    pub fn to_mml_velocity(&self, midi_velocity: u8, velocity_min: u8, velocity_max: u8) -> u8 {
+        let (min, max) = (
+            velocity_min.min(velocity_max),
+            velocity_max.max(velocity_min),
+        );
+        let x = midi_velocity.min(127) as f32 / 127.;
+
+        let y = match self {
+            Self::Linear => {
+                return utils::midi_velocity_to_mml_velocity(midi_velocity, min, max);
+            }
+            Self::Exponential { strength } if *strength > 0. => {
+                (strength * x).exp_m1() / strength.exp_m1()
+            }
+            Self::Logarithmic { strength } if *strength > 0. => {
+                (strength * x).ln_1p() / strength.ln_1p()
+            }
+            Self::Exponential { .. } | Self::Logarithmic { .. } => x,
+            Self::Piecewise { points } => {
+                return interpolate(points, midi_velocity).clamp(min, max);
+            }
+            Self::Percentile { fitted, .. } => match fitted {
+                Some((low, high)) if high > low => {
+                    (midi_velocity as f32 - *low as f32) / (*high - *low) as f32
+                }
+                _ => return utils::midi_velocity_to_mml_velocity(midi_velocity, min, max),
+            },
+        };
+
+        min + (y.clamp(0., 1.) * (max - min) as f32).round() as u8
+    }
+
+    /// Finds the velocities of a `Percentile` curve from the note velocities of the song.
+    /// Other curves do not depend on the song and are left as they are.
+    pub fn fit<'a, I>(&mut self, events: I)
+    where
+        I: IntoIterator<Item = &'a BridgeEvent>,
+    {
+        let Self::Percentile { low, high, fitted } = self else {
+            return;
+        };
+
+        let mut velocities: Vec<u8> = events
+            .into_iter()
+            .filter_map(|event| match event {
+                BridgeEvent::Note(note) => Some(note.velocity),
+                _ => None,
+            })
+            .collect();
+        if velocities.is_empty() {
+            *fitted = None;
+            return;
+        }
+        velocities.sort_unstable();
+
+        let get_percentile = |percentile: u8| {
+            let index = (velocities.len() - 1) * percentile.min(100) as usize / 100;
+            velocities[index]
+        };
+        *fitted = Some((get_percentile(*low), get_percentile(*high)));
+    }
+}
+
+fn interpolate(points: &[(u8, u8)], midi_velocity: u8) -> u8 {
+    let mut points = points.to_owned();
+    points.sort_unstable_by_key(|(midi, _)| *midi);
+
+    let Some((first, last)) = points.first().zip(points.last()) else {
+        return utils::midi_velocity_to_mml_velocity(midi_velocity, 0, 15);
+    };
+    if midi_velocity <= first.0 {
+        return first.1;
+    }
+    if midi_velocity >= last.0 {
+        return last.1;
+    }
+
+    points
+        .windows(2)
+        .find(|pair| midi_velocity <= pair[1].0)
+        .map(|pair| {
+            let ((x0, y0), (x1, y1)) = (pair[0], pair[1]);
+            let ratio = (midi_velocity - x0) as f32 / (x1 - x0).max(1) as f32;
+            (y0 as f32 + (y1 as f32 - y0 as f32) * ratio).round() as u8
+        })
+        .unwrap_or(last.1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mml_event::{MidiNoteState, MidiState};
+
+    fn note(velocity: u8) -> BridgeEvent {
+        BridgeEvent::Note(MidiNoteState {
+            key: 60,
+            velocity,
+            midi_state: MidiState {
+                position_in_tick: 0,
+                duration_in_tick: 480,
+                channel: 0,
+            },
+        })
+    }
+
+    #[test]
+    fn test_linear_is_unchanged() {
+        for midi_velocity in 0..=127 {
+            assert_eq!(
+                VelocityCurve::Linear.to_mml_velocity(midi_velocity, 2, 13),
+                utils::midi_velocity_to_mml_velocity(midi_velocity, 2, 13)
+            );
+        }
+    }
+
+    #[test]
+    fn test_exponential_and_logarithmic() {
+        let exponential = VelocityCurve::Exponential { strength: 3. };
+        let logarithmic = VelocityCurve::Logarithmic { strength: 9. };
+
+        // A soft note at 40
+        assert_eq!(exponential.to_mml_velocity(40, 0, 15), 1);
+        assert_eq!(VelocityCurve::Linear.to_mml_velocity(40, 0, 15), 4);
+        assert_eq!(logarithmic.to_mml_velocity(40, 0, 15), 9);
+
+        for curve in [exponential, logarithmic] {
+            assert_eq!(curve.to_mml_velocity(0, 3, 15), 3);
+            assert_eq!(curve.to_mml_velocity(127, 3, 15), 15);
+        }
+        assert_eq!(
+            VelocityCurve::Exponential { strength: 0. }.to_mml_velocity(64, 0, 15),
+            8
+        );
+    }
+
+    #[test]
+    fn test_piecewise() {
+        let curve = VelocityCurve::Piecewise {
+            points: vec![(100, 15), (20, 4), (60, 12)],
+        };
+
+        assert_eq!(curve.to_mml_velocity(0, 0, 15), 4);
+        assert_eq!(curve.to_mml_velocity(40, 0, 15), 8);
+        assert_eq!(curve.to_mml_velocity(80, 0, 15), 14);
+        assert_eq!(curve.to_mml_velocity(120, 0, 15), 15);
+        assert_eq!(curve.to_mml_velocity(120, 0, 10), 10);
+    }
+
+    #[test]
+    fn test_percentile_fit() {
+        let mut curve = VelocityCurve::Percentile {
+            low: 10,
+            high: 90,
+            fitted: None,
+        };
+        let events: Vec<BridgeEvent> = (0..=100).map(|index| note(20 + index / 4)).collect();
+
+        // Linear until fitted
+        assert_eq!(curve.to_mml_velocity(40, 0, 15), 4);
+
+        curve.fit(&events);
+        assert_eq!(
+            curve,
+            VelocityCurve::Percentile {
+                low: 10,
+                high: 90,
+                fitted: Some((22, 42)),
+            }
+        );
+        assert_eq!(curve.to_mml_velocity(20, 0, 15), 0);
+        assert_eq!(curve.to_mml_velocity(32, 0, 15), 8);
+        assert_eq!(curve.to_mml_velocity(45, 0, 15), 15);
+    }
+
+    #[test]
+    fn test_curve_json() {
+        let curve = VelocityCurve::Piecewise {
+            points: vec![(0, 2), (127, 15)],
+        };
+        let json = serde_json::to_string(&curve).unwrap();
+
+        assert_eq!(serde_json::from_str::<VelocityCurve>(&json).unwrap(), curve);
+    }
+
+    #[test]
+    fn test_curve_eq() {
+        fn assert_eq_type<T: Eq>() {}
+        assert_eq_type::<crate::MmlSongOptions>();
+
+        let curve = VelocityCurve::Exponential { strength: 2. };
+        assert_eq!(curve, VelocityCurve::Exponential { strength: 2. });
+        assert_ne!(curve, VelocityCurve::Exponential { strength: 2.5 });
+        assert_ne!(curve, VelocityCurve::Logarithmic { strength: 2. });
+        assert_ne!(
+            VelocityCurve::Linear,
+            VelocityCurve::Piecewise { points: vec![] }
+        );
+    }
+}
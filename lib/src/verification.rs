@@ -0,0 +1,368 @@
+use crate::{
+    MmlTrack,
+    mml_event::BridgeEvent,
+    syntax::{Span, parse, timed_notes, timed_tempos},
+    tempo::{TempoMap, get_tempos},
+    utils,
+};
+
+#[derive(Debug, Clone)]
+pub struct VerificationOptions {
+    /// An MML note further than this from a MIDI note is never matched with it
+    pub tolerance_ms: f64,
+
+    /// Number of notes kept in `TrackVerification::worst_offsets`
+    pub worst_offsets_count: usize,
+}
+
+impl Default for VerificationOptions {
+    fn default() -> Self {
+        Self {
+            tolerance_ms: 250.,
+            worst_offsets_count: 10,
+        }
+    }
+}
+
+/// A MIDI note and the MML note it was matched with.
+#[derive(Debug, Clone, PartialEq)]
+pub struct NoteOffset {
+    /// Bar of the MIDI note, from 0
+    pub bar: usize,
+    pub position_in_tick: usize,
+    pub key: u8,
+
+    /// `None` when the note was dropped
+    pub mml_key: Option<u8>,
+
+    /// Start in the MML minus start in the MIDI
+    pub drift_ms: f64,
+
+    /// Byte range of the MML note
+    pub span: Option<Span>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct BarVerification {
+    pub bar: usize,
+    pub note_count: usize,
+    pub dropped_count: usize,
+    pub pitch_error_count: usize,
+    pub max_drift_ms: f64,
+}
+
+/// How far the MML of a track is from its MIDI notes in real time.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TrackVerification {
+    pub name: String,
+    pub note_count: usize,
+
+    /// Notes found in the MML with the same key
+    pub matched_count: usize,
+
+    /// Mean of the absolute drift of matched notes and pitch errors
+    pub mean_drift_ms: f64,
+    pub max_drift_ms: f64,
+    pub dropped_notes: Vec<NoteOffset>,
+
+    /// Notes found in the MML with another key
+    pub pitch_errors: Vec<NoteOffset>,
+
+    /// Matched notes with the largest absolute drift, largest first
+    pub worst_offsets: Vec<NoteOffset>,
+
+    /// Bars that contain at least one MIDI note
+    pub bars: Vec<BarVerification>,
+}
+
+impl TrackVerification {
+    pub fn has_errors(&self) -> bool {
+        !self.dropped_notes.is_empty() || !self.pitch_errors.is_empty()
+    }
+}
+
+struct MmlNoteTime {
+    start_ms: f64,
+    key: u8,
+    span: Span,
+    is_used: bool,
+}
+
+/// Parses the MML of the track back and matches its notes with the bridge notes.
+pub fn verify_track(track: &MmlTrack, options: &VerificationOptions) -> TrackVerification {
+    verify_track_with_tempos(track, options, None)
+}
+
+/// Same as `verify_track`, `conductor_tempos` are the `(position in smallest unit, tempo)`
+/// the track plays with when its tempo commands are left to another track.
+/// Without them, the MIDI tempos rounded to the smallest unit are used.
+pub(crate) fn verify_track_with_tempos(
+    track: &MmlTrack,
+    options: &VerificationOptions,
+    conductor_tempos: Option<Vec<(usize, u32)>>,
+) -> TrackVerification {
+    let smallest_unit = track.song_options.smallest_unit;
+    let parsed = parse(&track.to_mml());
+
+    let mml_tempos: Vec<(usize, u32)> = if !track.omit_tempo {
+        timed_tempos(&parsed.nodes, smallest_unit)
+    } else if let Some(conductor_tempos) = conductor_tempos {
+        conductor_tempos
+    } else {
+        get_tempos(&track.bridge_meta_events)
+            .into_iter()
+            .map(|(position, tempo)| {
+                let position = utils::tick_to_smallest_unit(position, track.ppq, smallest_unit);
+                (position, tempo)
+            })
+            .collect()
+    };
+    let mml_tempo_map = TempoMap::new(mml_tempos, smallest_unit as f64 / 4.);
+    let mut mml_notes: Vec<MmlNoteTime> = timed_notes(&parsed.nodes, smallest_unit)
+        .into_iter()
+        .filter_map(|note| {
+            Some(MmlNoteTime {
+                start_ms: mml_tempo_map.to_ms(note.position_in_smallest_unit),
+                key: note.midi_key?,
+                span: note.span,
+                is_used: false,
+            })
+        })
+        .collect();
+    mml_notes.sort_by(|a, b| a.start_ms.total_cmp(&b.start_ms));
+
+    let midi_tempos: Vec<(usize, u32)> = track
+        .bridge_meta_events
+        .iter()
+        .filter_map(|event| match event {
+            BridgeEvent::Tempo(tempo, state) => Some((state.position_in_tick, *tempo)),
+            _ => None,
+        })
+        .collect();
+    let midi_tempo_map = TempoMap::new(midi_tempos, track.ppq as f64);
+    let bar_lengths = utils::get_bar_lengths_in_tick(&track.bridge_meta_events, track.ppq);
+
+    let mut midi_notes: Vec<(usize, u8)> = track
+        .bridge_note_events
+        .iter()
+        .filter_map(|event| match event {
+            BridgeEvent::Note(note) => Some((note.midi_state.position_in_tick, note.key)),
+            _ => None,
+        })
+        .collect();
+    midi_notes.sort();
+
+    let mut offsets: Vec<NoteOffset> = midi_notes
+        .iter()
+        .map(|(position_in_tick, key)| NoteOffset {
+            bar: utils::tick_to_bar(&bar_lengths, *position_in_tick),
+            position_in_tick: *position_in_tick,
+            key: *key,
+            mml_key: None,
+            drift_ms: 0.,
+            span: None,
+        })
+        .collect();
+
+    // Notes with the same key are matched first, so that a missing note
+    // does not take the MML note of another note of its chord
+    for is_same_key in [true, false] {
+        for offset in offsets.iter_mut().filter(|offset| offset.mml_key.is_none()) {
+            let start_ms = midi_tempo_map.to_ms(offset.position_in_tick);
+            let first =
+                mml_notes.partition_point(|note| note.start_ms < start_ms - options.tolerance_ms);
+
+            let best = mml_notes[first..]
+                .iter_mut()
+                .take_while(|note| note.start_ms <= start_ms + options.tolerance_ms)
+                .filter(|note| !note.is_used && (!is_same_key || note.key == offset.key))
+                .min_by(|a, b| {
+                    let drift_a = (a.start_ms - start_ms).abs();
+                    let drift_b = (b.start_ms - start_ms).abs();
+                    drift_a.total_cmp(&drift_b)
+                });
+
+            if let Some(note) = best {
+                note.is_used = true;
+                offset.mml_key = Some(note.key);
+                // Rounded to microseconds, hiding float errors of the tempo maps
+                offset.drift_ms = ((note.start_ms - start_ms) * 1000.).round() / 1000.;
+                offset.span = Some(note.span.to_owned());
+            }
+        }
+    }
+
+    summarize(track.name.to_owned(), offsets, options)
+}
+
+fn summarize(
+    name: String,
+    offsets: Vec<NoteOffset>,
+    options: &VerificationOptions,
+) -> TrackVerification {
+    let mut bars: Vec<BarVerification> = Vec::new();
+    let mut dropped_notes: Vec<NoteOffset> = Vec::new();
+    let mut pitch_errors: Vec<NoteOffset> = Vec::new();
+    let mut found: Vec<NoteOffset> = Vec::with_capacity(offsets.len());
+    let mut matched_count = 0usize;
+
+    for offset in offsets.iter() {
+        if bars.last().is_none_or(|bar| bar.bar != offset.bar) {
+            bars.push(BarVerification {
+                bar: offset.bar,
+                note_count: 0,
+                dropped_count: 0,
+                pitch_error_count: 0,
+                max_drift_ms: 0.,
+            });
+        }
+        let bar = bars.last_mut().unwrap();
+        bar.note_count += 1;
+
+        match offset.mml_key {
+            None => {
+                bar.dropped_count += 1;
+                dropped_notes.push(offset.to_owned());
+                continue;
+            }
+            Some(key) if key != offset.key => {
+                bar.pitch_error_count += 1;
+                pitch_errors.push(offset.to_owned());
+            }
+            Some(_) => matched_count += 1,
+        }
+
+        bar.max_drift_ms = bar.max_drift_ms.max(offset.drift_ms.abs());
+        found.push(offset.to_owned());
+    }
+
+    let mean_drift_ms = match found.len() {
+        0 => 0.,
+        count => {
+            found
+                .iter()
+                .map(|offset| offset.drift_ms.abs())
+                .sum::<f64>()
+                / count as f64
+        }
+    };
+
+    found.sort_by(|a, b| b.drift_ms.abs().total_cmp(&a.drift_ms.abs()));
+    let max_drift_ms = found
+        .first()
+        .map(|offset| offset.drift_ms.abs())
+        .unwrap_or(0.);
+    found.truncate(options.worst_offsets_count);
+
+    TrackVerification {
+        name,
+        note_count: offsets.len(),
+        matched_count,
+        mean_drift_ms,
+        max_drift_ms,
+        dropped_notes,
+        pitch_errors,
+        worst_offsets: found,
+        bars,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        MmlEvent, MmlSong, MmlSongOptions,
+        test_utils::{MIDI_PATHS, create_track, note, tempo},
+    };
+
+    #[test]
+    fn test_verify_exact_track() {
+        let track = create_track(
+            vec![tempo(120, 0)],
+            vec![note(60, 0, 480), note(64, 0, 480), note(67, 1920, 480)],
+            &MmlSongOptions::default(),
+        );
+        let verification = verify_track(&track, &VerificationOptions::default());
+
+        assert_eq!(verification.note_count, 3);
+        assert_eq!(verification.matched_count, 3);
+        assert_eq!(verification.max_drift_ms, 0.);
+        assert!(!verification.has_errors());
+
+        let bars: Vec<(usize, usize)> = verification
+            .bars
+            .iter()
+            .map(|bar| (bar.bar, bar.note_count))
+            .collect();
+        assert_eq!(bars, vec![(0, 2), (1, 1)]);
+    }
+
+    #[test]
+    fn test_verify_drift() {
+        // 10 ticks after the beat is quantized to the beat, 480 ticks is 500ms at 120 bpm
+        let track = create_track(
+            vec![tempo(120, 0)],
+            vec![note(60, 0, 480), note(62, 490, 470)],
+            &MmlSongOptions::default(),
+        );
+        let verification = verify_track(&track, &VerificationOptions::default());
+
+        assert_eq!(verification.matched_count, 2);
+        assert_eq!(verification.max_drift_ms, 10.417);
+        assert_eq!(verification.worst_offsets[0].key, 62);
+        assert_eq!(verification.worst_offsets[0].span, Some(11..13));
+    }
+
+    #[test]
+    fn test_verify_pitch_errors_and_dropped_notes() {
+        let mut track = create_track(
+            vec![tempo(120, 0)],
+            vec![note(60, 0, 480), note(62, 1920, 480), note(64, 3840, 480)],
+            &MmlSongOptions::default(),
+        );
+        // Change the pitch of the MML only
+        let smallest_unit = track.song_options.smallest_unit;
+        for event in track.events.iter_mut() {
+            if let MmlEvent::Note(note) = event
+                && note.midi_state.key == 62
+            {
+                note.apply_keymap(65, smallest_unit);
+            }
+        }
+
+        // Replace the last note with a rest
+        if let Some(MmlEvent::Note(note)) = track.events.pop() {
+            track
+                .events
+                .push(MmlEvent::Rest(note.duration_in_smallest_unit));
+        }
+
+        let verification = verify_track(&track, &VerificationOptions::default());
+
+        assert_eq!(verification.matched_count, 1);
+        assert_eq!(verification.pitch_errors.len(), 1);
+        assert_eq!(verification.pitch_errors[0].bar, 1);
+        assert_eq!(verification.pitch_errors[0].mml_key, Some(65));
+        assert_eq!(verification.dropped_notes.len(), 1);
+        assert_eq!(verification.dropped_notes[0].bar, 2);
+        assert_eq!(verification.bars[2].dropped_count, 1);
+    }
+
+    #[test]
+    fn test_verify_converted_song() {
+        for path in MIDI_PATHS {
+            let song = MmlSong::from_path(path, MmlSongOptions::default()).unwrap();
+
+            for verification in song.verify(&VerificationOptions::default()) {
+                assert_eq!(
+                    verification.matched_count
+                        + verification.pitch_errors.len()
+                        + verification.dropped_notes.len(),
+                    verification.note_count
+                );
+                assert!(verification.worst_offsets.len() <= 10);
+                assert!(verification.bars.is_sorted_by_key(|bar| bar.bar));
+            }
+        }
+    }
+}
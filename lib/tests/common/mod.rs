@@ -0,0 +1,32 @@
+use midi_to_mml::{
+    MmlTrack,
+    syntax::{parse, timed_notes},
+};
+
+/// (midi key, velocity, position, duration) of every note.
+/// Converting the same notes again can move octave events around rests,
+/// so round trips are compared on the notes instead of the MML.
+pub fn get_notes(track: &MmlTrack) -> Vec<(Option<u8>, u8, usize, usize)> {
+    let parsed = parse(&track.to_mml());
+    assert!(parsed.errors.is_empty());
+
+    let mut notes: Vec<(Option<u8>, u8, usize, usize)> =
+        timed_notes(&parsed.nodes, track.song_options.smallest_unit)
+            .into_iter()
+            .filter(|note| note.midi_key.is_some())
+            .map(|note| {
+                (
+                    note.midi_key,
+                    note.velocity,
+                    note.position_in_smallest_unit,
+                    note.duration_in_smallest_unit,
+                )
+            })
+            .collect();
+
+    notes.sort_by_key(|note| (note.2, note.0));
+
+    // A MIDI channel cannot play the same key twice at once
+    notes.dedup();
+    notes
+}
@@ -0,0 +1,67 @@
+use midi_to_mml::{MmlSong, MmlSongOptions, SongAnalysis};
+
+const MIDI_PATH: &str = "../assets/Stay_With_Me_-_Miki_Matsubara.mid";
+
+#[test]
+fn test_analyze_song() {
+    let song = MmlSong::from_path(MIDI_PATH, MmlSongOptions::default()).unwrap();
+    let analysis = song.analyze();
+
+    assert_eq!(analysis.tracks.len(), song.tracks.len());
+    assert_eq!(
+        analysis.note_count,
+        analysis
+            .tracks
+            .iter()
+            .map(|track| track.note_count)
+            .sum::<usize>()
+    );
+    assert_eq!(
+        analysis.char_count,
+        song.tracks
+            .iter()
+            .map(|track| track.to_mml().len())
+            .sum::<usize>()
+    );
+    assert_eq!(analysis.tempo.count, 8);
+    assert!(analysis.tempo.min <= analysis.tempo.max);
+    assert!(analysis.duration_seconds > 60.);
+
+    for (track, track_analysis) in song.tracks.iter().zip(analysis.tracks.iter()) {
+        let (lowest, highest) = track_analysis.pitch_range.unwrap();
+        assert!(lowest <= highest);
+        assert!(track_analysis.max_polyphony >= 1);
+        assert!(track_analysis.average_polyphony >= 1.);
+        assert!(analysis.max_polyphony >= track_analysis.max_polyphony);
+        assert_eq!(
+            track_analysis.notes_per_bar.iter().sum::<usize>(),
+            track_analysis.note_count
+        );
+        assert_eq!(track_analysis.velocity_distribution.len(), 16);
+        assert_eq!(
+            track_analysis.quantization.max_error_ms,
+            track.verify(&Default::default()).max_drift_ms
+        );
+        assert_eq!(track.analyze(), *track_analysis);
+    }
+}
+
+#[test]
+fn test_analysis_json() {
+    let song = MmlSong::from_path(MIDI_PATH, MmlSongOptions::default()).unwrap();
+    let analysis = song.analyze();
+
+    let json = serde_json::to_string(&analysis).unwrap();
+    let deserialized: SongAnalysis = serde_json::from_str(&json).unwrap();
+
+    // Floats may differ in the last digit
+    assert!((deserialized.duration_seconds - analysis.duration_seconds).abs() < 1e-9);
+    for (deserialized, track) in deserialized.tracks.iter().zip(analysis.tracks.iter()) {
+        assert_eq!(deserialized.note_count, track.note_count);
+        assert_eq!(deserialized.notes_per_bar, track.notes_per_bar);
+        assert_eq!(
+            deserialized.velocity_distribution,
+            track.velocity_distribution
+        );
+    }
+}
@@ -0,0 +1,78 @@
+use midi_to_mml::{MmlSong, MmlSongOptions, MmlTrack, utils::BalanceMetric};
+
+const INDEXES: [usize; 4] = [0, 1, 2, 3];
+
+fn count_notes(tracks: &[&MmlTrack]) -> usize {
+    tracks
+        .iter()
+        .map(|track| {
+            track
+                .bridge_note_events
+                .iter()
+                .filter(|event| matches!(event, midi_to_mml::BridgeEvent::Note(_)))
+                .count()
+        })
+        .sum()
+}
+
+fn get_spread(song: &MmlSong, metric: BalanceMetric) -> usize {
+    let lengths: Vec<usize> = INDEXES
+        .iter()
+        .map(|index| {
+            let track = &song.tracks[*index];
+            match metric {
+                BalanceMetric::NoteLength => track.mml_note_length,
+                BalanceMetric::Characters => track.to_mml().len(),
+            }
+        })
+        .collect();
+
+    lengths.iter().max().unwrap() - lengths.iter().min().unwrap()
+}
+
+#[test]
+fn test_balance_four_tracks() {
+    for metric in [BalanceMetric::NoteLength, BalanceMetric::Characters] {
+        let mut song =
+            MmlSong::from_path("../assets/heart_beat-band.mid", MmlSongOptions::default()).unwrap();
+        let before_spread = get_spread(&song, metric);
+        let before_notes = count_notes(&INDEXES.map(|index| &song.tracks[index]));
+        let other_tracks: Vec<String> = song.tracks[4..]
+            .iter()
+            .map(|track| track.to_mml())
+            .collect();
+
+        song.balance_tracks(&INDEXES, metric).unwrap();
+
+        assert!(get_spread(&song, metric) < before_spread);
+        assert_eq!(
+            count_notes(&INDEXES.map(|index| &song.tracks[index])),
+            before_notes
+        );
+        let after_other_tracks: Vec<String> = song.tracks[4..]
+            .iter()
+            .map(|track| track.to_mml())
+            .collect();
+        assert_eq!(after_other_tracks, other_tracks);
+    }
+}
+
+#[test]
+fn test_balance_invalid_indexes() {
+    let mut song =
+        MmlSong::from_path("../assets/heart_beat-band.mid", MmlSongOptions::default()).unwrap();
+
+    assert!(
+        song.balance_tracks(&[0], BalanceMetric::NoteLength)
+            .is_err()
+    );
+    assert!(
+        song.balance_tracks(&[0, 0], BalanceMetric::NoteLength)
+            .is_err()
+    );
+    assert!(
+        song.balance_tracks(&[0, 100], BalanceMetric::NoteLength)
+            .is_err()
+    );
+    assert!(!song.can_undo());
+}
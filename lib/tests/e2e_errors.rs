@@ -0,0 +1,101 @@
+use midi_to_mml::{
+    ConstraintError, Instrument, MmlError, MmlSong, MmlSongOptions, TempoTransform,
+    TrackOptionsOverride, syntax::ParseErrorKind, utils::BalanceMetric,
+};
+
+const MIDI_PATH: &str = "../assets/heart_beat-band.mid";
+
+fn from_mml(mml: &[&str]) -> Result<MmlSong, MmlError> {
+    let tracks = mml
+        .iter()
+        .map(|mml| (mml.to_string(), Instrument::default()))
+        .collect();
+    MmlSong::from_mml(tracks, MmlSongOptions::default())
+}
+
+#[test]
+fn test_read_errors() {
+    let error = MmlSong::from_path("../assets/missing.mid", MmlSongOptions::default());
+    assert!(matches!(error, Err(MmlError::Io(_))));
+
+    let error = MmlSong::from_bytes(b"not a midi file".to_vec(), MmlSongOptions::default());
+    assert!(matches!(error, Err(MmlError::Midi(_))));
+
+    let Err(MmlError::Parse(errors)) = from_mml(&["c4d4", "c4 x"]) else {
+        panic!("Expected a parse error");
+    };
+    assert_eq!(errors.len(), 1);
+    assert_eq!(errors[0].index, 1);
+    assert_eq!(errors[0].error.kind, ParseErrorKind::UnknownCommand('x'));
+}
+
+#[test]
+fn test_timing_errors() {
+    // The tempo starts after a whole note, 4 quarters of 960 ticks
+    let error = from_mml(&["c1t0c4"]);
+    assert!(matches!(
+        error,
+        Err(MmlError::Timing {
+            tick: 3840,
+            tempo: 0
+        })
+    ));
+}
+
+#[test]
+fn test_song_errors() {
+    let mut song = MmlSong::from_path(MIDI_PATH, MmlSongOptions::default()).unwrap();
+    let track_count = song.tracks.len();
+
+    assert!(matches!(
+        song.split_track(track_count),
+        Err(MmlError::Index { index, track_count: count }) if index == track_count && count == track_count
+    ));
+    assert!(matches!(
+        song.equalize_tracks(0, 0),
+        Err(MmlError::Constraint(ConstraintError::SameTrack {
+            index: 0
+        }))
+    ));
+    assert!(matches!(
+        song.balance_tracks(&[0], BalanceMetric::NoteLength),
+        Err(MmlError::Constraint(ConstraintError::NotEnoughTracks {
+            count: 1
+        }))
+    ));
+    assert!(matches!(
+        song.transform_tempo(&TempoTransform::Scale { factor: -1. }),
+        Err(MmlError::Options { name: "factor", .. })
+    ));
+    assert!(matches!(
+        song.set_song_options(MmlSongOptions {
+            velocity_min: 12,
+            velocity_max: 4,
+            ..MmlSongOptions::default()
+        }),
+        Err(MmlError::Options {
+            name: "velocity_min",
+            ..
+        })
+    ));
+    assert!(matches!(
+        song.set_track_options(
+            0,
+            TrackOptionsOverride {
+                smallest_unit: Some(48),
+                ..TrackOptionsOverride::default()
+            }
+        ),
+        Err(MmlError::Options {
+            name: "smallest_unit",
+            ..
+        })
+    ));
+    assert!(!song.can_undo());
+
+    let project = song.project().unwrap();
+    assert!(matches!(
+        MmlSong::from_project(project, b"other".to_vec()),
+        Err(MmlError::Constraint(ConstraintError::SourceMismatch))
+    ));
+}
@@ -0,0 +1,108 @@
+mod common;
+
+use common::get_notes;
+use midi_to_mml::{BridgeEvent, ConstraintError, Instrument, MmlError, MmlSong, MmlSongOptions};
+
+#[test]
+fn test_e2e() {
+    for entry in std::fs::read_dir("../assets").unwrap() {
+        let entry = entry.unwrap();
+        let path = entry.path();
+
+        if path.is_file() && path.extension().is_some_and(|ext| ext == "mid") {
+            let song = MmlSong::from_path(&path, MmlSongOptions::default()).unwrap();
+            assert_round_trip(&song);
+        }
+    }
+}
+
+#[test]
+fn test_unknown_commands() {
+    let tracks = vec![
+        (String::from("c4d4"), Instrument::default()),
+        (String::from("c4 x4 e4"), Instrument::default()),
+    ];
+    let error = MmlSong::from_mml(tracks, MmlSongOptions::default()).unwrap_err();
+
+    assert!(
+        error
+            .to_string()
+            .contains("Track 1: Unknown command `x` at 3..4")
+    );
+}
+
+#[test]
+fn test_track_operations() {
+    let tracks = vec![
+        (String::from("t140o4c4:e4:g4 d2"), Instrument::new(0, 0)),
+        (String::from("o3c1"), Instrument::new(32, 1)),
+    ];
+    let mut song = MmlSong::from_mml(tracks, MmlSongOptions::default()).unwrap();
+
+    song.split_track(0).unwrap();
+    assert_eq!(song.tracks.len(), 3);
+
+    song.merge_tracks(0, 1).unwrap();
+    assert_eq!(song.tracks[0].to_mml(), "t140v12o4c4:e4:g4d2");
+    assert_eq!(song.tracks[1].to_mml(), "t140v12o3c1");
+    assert_eq!(song.tracks[1].instrument, Instrument::new(32, 1));
+}
+
+#[test]
+fn test_tempos_of_tracks() {
+    let from_mml = |mml: &[&str]| {
+        let tracks = mml
+            .iter()
+            .map(|mml| (mml.to_string(), Instrument::default()))
+            .collect();
+        MmlSong::from_mml(tracks, MmlSongOptions::default())
+    };
+
+    // Tracks repeating a tempo or adding their own keep every tempo once
+    let song = from_mml(&["t90c4t150c4", "t90c4c4t60c4"]).unwrap();
+    let tempos: Vec<(u32, usize)> = song.tracks[0]
+        .bridge_meta_events
+        .iter()
+        .filter_map(|event| match event {
+            BridgeEvent::Tempo(tempo, state) => Some((*tempo, state.position_in_tick)),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(tempos, vec![(90, 0), (150, 960), (60, 1920)]);
+
+    // The last tempo of a track at a tick wins
+    let song = from_mml(&["t120t80c4"]).unwrap();
+    assert!(song.tracks[0].to_mml().starts_with("t80"));
+
+    let error = from_mml(&["t90c4", "c1", "t100c4"]).unwrap_err();
+    assert!(matches!(
+        error,
+        MmlError::Constraint(ConstraintError::ConflictingMeta {
+            kind: "tempo",
+            tick: 0,
+            index: 2,
+            other_index: 0,
+        })
+    ));
+}
+
+/// Reading the MML of a converted song must give the same notes.
+fn assert_round_trip(song: &MmlSong) {
+    let imported = import_mml(song);
+    assert_eq!(imported.tracks.len(), song.tracks.len());
+
+    for (track, imported_track) in song.tracks.iter().zip(imported.tracks.iter()) {
+        assert_eq!(track.instrument, imported_track.instrument);
+        assert_eq!(get_notes(track), get_notes(imported_track));
+    }
+}
+
+fn import_mml(song: &MmlSong) -> MmlSong {
+    let tracks: Vec<(String, Instrument)> = song
+        .tracks
+        .iter()
+        .map(|track| (track.to_mml(), track.instrument.to_owned()))
+        .collect();
+
+    MmlSong::from_mml(tracks, MmlSongOptions::default()).unwrap()
+}
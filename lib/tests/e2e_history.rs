@@ -0,0 +1,102 @@
+use std::collections::HashMap;
+
+use midi_to_mml::{MmlSong, MmlSongOptions, SongOperation, utils::BalanceMetric};
+
+fn summarize(song: &MmlSong) -> Vec<(String, String)> {
+    song.tracks
+        .iter()
+        .map(|track| (track.name.to_owned(), track.to_mml()))
+        .collect()
+}
+
+fn assert_undo_redo<F>(song: &mut MmlSong, operation: F)
+where
+    F: FnOnce(&mut MmlSong),
+{
+    let before = summarize(song);
+    let before_options = song.options.to_owned();
+    operation(song);
+    let after = summarize(song);
+    let after_options = song.options.to_owned();
+
+    let undone = song.undo().unwrap();
+    assert_eq!(summarize(song), before, "undo {undone:?}");
+    assert_eq!(song.options, before_options);
+
+    let redone = song.redo().unwrap().unwrap();
+    assert_eq!(redone, undone);
+    assert_eq!(summarize(song), after, "redo {redone:?}");
+    assert_eq!(song.options, after_options);
+}
+
+#[test]
+fn test_undo_redo_operations() {
+    let mut song = MmlSong::from_path(
+        "../assets/heart_beat-band.mid",
+        MmlSongOptions {
+            auto_boot_velocity: true,
+            ..MmlSongOptions::default()
+        },
+    )
+    .unwrap();
+
+    assert_undo_redo(&mut song, |song| song.split_track(2).unwrap());
+    assert_undo_redo(&mut song, |song| song.merge_tracks(5, 1).unwrap());
+    assert_undo_redo(&mut song, |song| song.merge_tracks(0, 4).unwrap());
+    assert_undo_redo(&mut song, |song| song.equalize_tracks(2, 3).unwrap());
+    assert_undo_redo(&mut song, |song| {
+        song.balance_tracks(&[0, 3, 4, 6], BalanceMetric::Characters)
+            .unwrap()
+    });
+    assert_undo_redo(&mut song, |song| {
+        song.rename_track(0, String::from("Lead")).unwrap()
+    });
+    assert_undo_redo(&mut song, |song| {
+        song.apply_keymap(1, &HashMap::from([(60, 61), (62, 63)]))
+            .unwrap()
+    });
+    assert_undo_redo(&mut song, |song| {
+        song.set_song_options(MmlSongOptions {
+            smallest_unit: 32,
+            velocity_max: 12,
+            ..song.options.to_owned()
+        })
+        .unwrap()
+    });
+
+    assert_eq!(song.history().count(), 8);
+    assert_eq!(song.project().unwrap().operations.len(), 8);
+}
+
+#[test]
+fn test_undo_all() {
+    let mut song =
+        MmlSong::from_path("../assets/heart_beat-band.mid", MmlSongOptions::default()).unwrap();
+    let original = summarize(&song);
+
+    song.merge_tracks(0, 1).unwrap();
+    song.merge_tracks(0, 1).unwrap();
+    song.split_track(0).unwrap();
+    song.rename_track(1, String::from("Bass")).unwrap();
+
+    while song.undo().is_some() {}
+
+    assert_eq!(summarize(&song), original);
+    assert!(!song.can_undo());
+    assert!(song.can_redo());
+    assert!(song.project().unwrap().operations.is_empty());
+}
+
+#[test]
+fn test_new_operation_clears_redo() {
+    let mut song =
+        MmlSong::from_path("../assets/heart_beat-band.mid", MmlSongOptions::default()).unwrap();
+
+    song.split_track(0).unwrap();
+    assert_eq!(song.undo(), Some(SongOperation::SplitTrack { index: 0 }));
+    assert!(song.can_redo());
+
+    song.rename_track(0, String::from("Lead")).unwrap();
+    assert!(!song.can_redo());
+    assert!(song.redo().unwrap().is_none());
+}
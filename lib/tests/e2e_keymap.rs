@@ -0,0 +1,79 @@
+mod common;
+
+use common::get_notes;
+use midi_to_mml::{DrumKit, KeymapPreset, MmlSong, MmlSongOptions, Scale};
+
+#[test]
+fn test_fold_whole_song() {
+    let mut song =
+        MmlSong::from_path("../assets/heart_beat-band.mid", MmlSongOptions::default()).unwrap();
+    let preset = KeymapPreset::FoldIntoRange {
+        lowest: 48,
+        highest: 72,
+    };
+
+    song.apply_keymap_preset(None, &preset).unwrap();
+
+    for track in song.tracks.iter() {
+        let keys = get_notes(track).into_iter().filter_map(|(key, ..)| key);
+
+        if track.instrument.is_drum() {
+            continue;
+        }
+        for key in keys {
+            assert!((48..=72).contains(&key), "{} has {key}", track.name);
+        }
+    }
+}
+
+#[test]
+fn test_snap_one_track() {
+    let mut song =
+        MmlSong::from_path("../assets/heart_beat-band.mid", MmlSongOptions::default()).unwrap();
+    let other_tracks: Vec<String> = song.tracks[1..]
+        .iter()
+        .map(|track| track.to_mml())
+        .collect();
+    let preset = KeymapPreset::SnapToScale {
+        root: 0,
+        scale: Scale::MajorPentatonic,
+    };
+
+    song.apply_keymap_preset(Some(0), &preset).unwrap();
+
+    for (key, ..) in get_notes(&song.tracks[0]) {
+        if let Some(key) = key {
+            assert!([0, 2, 4, 7, 9].contains(&(key % 12)));
+        }
+    }
+    let after_other_tracks: Vec<String> = song.tracks[1..]
+        .iter()
+        .map(|track| track.to_mml())
+        .collect();
+    assert_eq!(after_other_tracks, other_tracks);
+
+    assert!(song.undo().is_some());
+    assert!(song.apply_keymap_preset(Some(100), &preset).is_err());
+    assert!(song.apply_keymap(100, &preset.to_keymap()).is_err());
+}
+
+#[test]
+fn test_drum_preset_skips_other_tracks() {
+    let mut song =
+        MmlSong::from_path("../assets/heart_beat-band.mid", MmlSongOptions::default()).unwrap();
+    let before: Vec<String> = song.tracks.iter().map(|track| track.to_mml()).collect();
+
+    song.apply_keymap_preset(
+        None,
+        &KeymapPreset::GmDrums {
+            kit: DrumKit::default(),
+        },
+    )
+    .unwrap();
+
+    for (track, before) in song.tracks.iter().zip(before) {
+        if !track.instrument.is_drum() {
+            assert_eq!(track.to_mml(), before);
+        }
+    }
+}
@@ -0,0 +1,203 @@
+use midi_to_mml::{
+    BridgeEvent, BridgePass, ConstraintError, MmlError, MmlEvent, MmlPass, MmlProject, MmlSong,
+    MmlSongOptions, PassContext, PassPipeline,
+};
+
+const MIDI_PATH: &str = "../assets/Stay_With_Me_-_Miki_Matsubara.mid";
+
+/// Removes notes shorter than a 1/32 note.
+struct RemoveGraceNotes;
+
+impl BridgePass for RemoveGraceNotes {
+    fn name(&self) -> &str {
+        "remove_grace_notes"
+    }
+
+    fn run(&self, events: &mut Vec<BridgeEvent>, context: &PassContext) {
+        let min_duration = context.ppq as usize / 8;
+        events.retain(|event| match event {
+            BridgeEvent::Note(note) => note.midi_state.duration_in_tick >= min_duration,
+            _ => true,
+        });
+    }
+}
+
+/// Plays every note at the same velocity.
+struct FlatVelocity(u8);
+
+impl MmlPass for FlatVelocity {
+    fn name(&self) -> &str {
+        "flat_velocity"
+    }
+
+    fn run(&self, events: &mut Vec<MmlEvent>, _context: &PassContext) {
+        for event in events.iter_mut() {
+            if let MmlEvent::Velocity(velocity) = event {
+                *velocity = self.0;
+            }
+        }
+    }
+}
+
+fn get_mml(song: &MmlSong) -> Vec<String> {
+    song.tracks.iter().map(|track| track.to_mml()).collect()
+}
+
+fn get_velocities(song: &MmlSong) -> Vec<u8> {
+    song.tracks
+        .iter()
+        .flat_map(|track| track.events.iter())
+        .filter_map(|event| match event {
+            MmlEvent::Velocity(velocity) => Some(*velocity),
+            _ => None,
+        })
+        .collect()
+}
+
+fn flat_velocity_options(velocity: u8) -> MmlSongOptions {
+    let mut pipeline = PassPipeline::default();
+    pipeline.push_bridge_pass(RemoveGraceNotes);
+    pipeline.push_mml_pass(FlatVelocity(velocity));
+
+    MmlSongOptions {
+        pipeline,
+        ..MmlSongOptions::default()
+    }
+}
+
+fn get_note_count(song: &MmlSong) -> usize {
+    song.tracks
+        .iter()
+        .flat_map(|track| track.events.iter())
+        .filter(|event| matches!(event, MmlEvent::Note(_)))
+        .count()
+}
+
+#[test]
+fn test_custom_passes() {
+    let default_song = MmlSong::from_path(MIDI_PATH, MmlSongOptions::default()).unwrap();
+
+    let options = flat_velocity_options(9);
+    let song = MmlSong::from_path(MIDI_PATH, options.to_owned()).unwrap();
+
+    assert!(get_note_count(&song) < get_note_count(&default_song));
+    assert!(get_velocities(&song).iter().all(|velocity| *velocity == 9));
+
+    // Changing the pipeline of a song matches a new song with it
+    let mut changed_song = MmlSong::from_path(MIDI_PATH, MmlSongOptions::default()).unwrap();
+    changed_song.set_song_options(options).unwrap();
+    assert_eq!(get_mml(&changed_song), get_mml(&song));
+
+    changed_song
+        .set_song_options(MmlSongOptions::default())
+        .unwrap();
+    assert_eq!(get_mml(&changed_song), get_mml(&default_song));
+}
+
+#[test]
+fn test_builtin_passes_in_order() {
+    let default_song = MmlSong::from_path(MIDI_PATH, MmlSongOptions::default()).unwrap();
+
+    // An equal pipeline built by hand converts the same way
+    let mut pipeline = PassPipeline::empty();
+    pipeline.push_mml_pass(midi_to_mml::NormalizeEvents);
+    pipeline.push_mml_pass(midi_to_mml::FixEventsPosition);
+    pipeline.push_mml_pass(midi_to_mml::NormalizeEvents);
+    pipeline.push_mml_pass(midi_to_mml::UpdateChordDuration);
+    pipeline.push_mml_pass(midi_to_mml::UpdateNoteMml);
+    pipeline.push_mml_pass(midi_to_mml::UpdateVelocities);
+    assert!(pipeline.is_default());
+
+    let song = MmlSong::from_path(
+        MIDI_PATH,
+        MmlSongOptions {
+            pipeline,
+            ..MmlSongOptions::default()
+        },
+    )
+    .unwrap();
+    assert_eq!(get_mml(&song), get_mml(&default_song));
+}
+
+/// Changes nothing.
+struct Noop;
+
+impl MmlPass for Noop {
+    fn name(&self) -> &str {
+        "noop"
+    }
+
+    fn run(&self, _events: &mut Vec<MmlEvent>, _context: &PassContext) {}
+}
+
+#[test]
+fn test_noop_pass_after_builtin_passes() {
+    for path in [MIDI_PATH, "../assets/heart_beat-band.mid"] {
+        let default_song = MmlSong::from_path(path, MmlSongOptions::default()).unwrap();
+
+        let mut pipeline = PassPipeline::default();
+        pipeline.push_mml_pass(Noop);
+        let options = MmlSongOptions {
+            pipeline,
+            ..MmlSongOptions::default()
+        };
+        let song = MmlSong::from_path(path, options).unwrap();
+
+        assert_eq!(get_mml(&song), get_mml(&default_song), "{path}");
+    }
+}
+
+#[test]
+fn test_pipeline_without_note_mml() {
+    let json = r#"{"pipeline": {}}"#;
+    let options: MmlSongOptions = serde_json::from_str(json).unwrap();
+    assert!(options.pipeline.is_default());
+
+    let json = r#"{"pipeline": {"mml_passes": ["normalize_events"]}}"#;
+    let options: MmlSongOptions = serde_json::from_str(json).unwrap();
+    assert!(matches!(
+        MmlSong::from_path(MIDI_PATH, options),
+        Err(MmlError::Options {
+            name: "pipeline",
+            ..
+        })
+    ));
+}
+
+#[test]
+fn test_reconfigured_pass() {
+    let mut song = MmlSong::from_path(MIDI_PATH, flat_velocity_options(9)).unwrap();
+    assert!(get_velocities(&song).iter().all(|velocity| *velocity == 9));
+
+    // Same pass names, other settings
+    song.set_song_options(flat_velocity_options(5)).unwrap();
+    assert!(get_velocities(&song).iter().all(|velocity| *velocity == 5));
+}
+
+#[test]
+fn test_custom_passes_are_not_restored() {
+    let bytes = std::fs::read(MIDI_PATH).unwrap();
+    let song = MmlSong::from_bytes(bytes.to_owned(), flat_velocity_options(9)).unwrap();
+
+    let json = song.project().unwrap().to_json().unwrap();
+    let project = MmlProject::from_json(&json).unwrap();
+    let pipeline = &project.options.pipeline;
+    assert_eq!(
+        pipeline.unrestored_passes(),
+        ["remove_grace_notes", "flat_velocity"]
+    );
+
+    let Err(MmlError::Constraint(ConstraintError::UnrestoredPasses { names })) =
+        MmlSong::from_project(&project, bytes.to_owned())
+    else {
+        panic!("a project with custom passes should not be reopened");
+    };
+    assert_eq!(names, ["remove_grace_notes", "flat_velocity"]);
+
+    // The built-in passes are restored
+    let song = MmlSong::from_bytes(bytes.to_owned(), MmlSongOptions::default()).unwrap();
+    let json = song.project().unwrap().to_json().unwrap();
+    let project = MmlProject::from_json(&json).unwrap();
+    assert!(project.options.pipeline.is_default());
+    assert!(MmlSong::from_project(&project, bytes).is_ok());
+}
@@ -0,0 +1,77 @@
+use midi_to_mml::{Instrument, MmlSong, MmlSongOptions, PrettyMmlOptions, compact_pretty_mml};
+
+const MIDI_PATHS: [&str; 3] = [
+    "../assets/Stay_With_Me_-_Miki_Matsubara.mid",
+    "../assets/FIRE_BIRD_(full_ver_)_(BanG_Dream!_Roselia_9th_Single)_(piano_cover).mid",
+    "../assets/heart_beat-band.mid",
+];
+
+#[test]
+fn test_pretty_round_trip() {
+    let layouts = [
+        PrettyMmlOptions::default(),
+        PrettyMmlOptions {
+            bars_per_line: 1,
+            comments: false,
+        },
+    ];
+
+    for path in MIDI_PATHS {
+        let song = MmlSong::from_path(path, MmlSongOptions::default()).unwrap();
+
+        for track in song.tracks.iter() {
+            for options in layouts.iter() {
+                let pretty = track.to_pretty_mml(options);
+                assert_eq!(
+                    compact_pretty_mml(&pretty),
+                    track.to_mml(),
+                    "{path} {}",
+                    track.name
+                );
+            }
+        }
+    }
+}
+
+#[test]
+fn test_read_pretty_mml() {
+    let song = MmlSong::from_path(MIDI_PATHS[0], MmlSongOptions::default()).unwrap();
+    let read = |pretty: bool| {
+        let tracks: Vec<(String, Instrument)> = song
+            .tracks
+            .iter()
+            .map(|track| {
+                let mml = if pretty {
+                    track.to_pretty_mml(&PrettyMmlOptions::default())
+                } else {
+                    track.to_mml()
+                };
+                (mml, track.instrument.to_owned())
+            })
+            .collect();
+
+        let song = MmlSong::from_mml(tracks, MmlSongOptions::default()).unwrap();
+        song.tracks
+            .iter()
+            .map(|track| track.to_mml())
+            .collect::<Vec<String>>()
+    };
+
+    assert_eq!(read(true), read(false));
+}
+
+#[test]
+fn test_pretty_lines() {
+    let song = MmlSong::from_path(MIDI_PATHS[0], MmlSongOptions::default()).unwrap();
+    let track = &song.tracks[0];
+
+    let pretty = track.to_pretty_mml(&PrettyMmlOptions {
+        bars_per_line: 2,
+        comments: true,
+    });
+    let lines: Vec<&str> = pretty.lines().collect();
+
+    assert_eq!(lines[0], format!("/* {} */", track.name));
+    assert!(lines[1..].iter().all(|line| line.starts_with("/* ")));
+    assert!(lines.len() > 10);
+}
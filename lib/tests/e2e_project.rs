@@ -0,0 +1,66 @@
+use std::collections::HashMap;
+
+use midi_to_mml::{MmlProject, MmlSong, MmlSongOptions};
+
+const MIDI_PATH: &str = "../assets/Stay_With_Me_-_Miki_Matsubara.mid";
+
+fn edited_song(bytes: Vec<u8>) -> MmlSong {
+    let mut song = MmlSong::from_bytes(bytes, MmlSongOptions::default()).unwrap();
+    song.split_track(0).unwrap();
+    song.equalize_tracks(0, 1).unwrap();
+    song.rename_track(1, String::from("Bass")).unwrap();
+    song.apply_keymap(0, &HashMap::from([(60, 62), (64, 65)]))
+        .unwrap();
+    song.merge_tracks(1, 2).unwrap();
+    song.set_song_options(MmlSongOptions {
+        velocity_max: 13,
+        auto_boot_velocity: true,
+        ..MmlSongOptions::default()
+    })
+    .unwrap();
+
+    song
+}
+
+fn summarize(song: &MmlSong) -> Vec<(String, String)> {
+    song.tracks
+        .iter()
+        .map(|track| (track.name.to_owned(), track.to_mml()))
+        .collect()
+}
+
+#[test]
+fn test_reopen_project() {
+    let bytes = std::fs::read(MIDI_PATH).unwrap();
+    let song = edited_song(bytes.to_owned());
+
+    let json = song.project().unwrap().to_json().unwrap();
+    let project = MmlProject::from_json(&json).unwrap();
+    assert_eq!(project.operations.len(), 6);
+
+    let reopened = MmlSong::from_project(&project, bytes).unwrap();
+    assert_eq!(summarize(&reopened), summarize(&song));
+    assert_eq!(reopened.project().unwrap().operations, project.operations);
+}
+
+#[test]
+fn test_reopen_project_with_other_midi() {
+    let bytes = std::fs::read(MIDI_PATH).unwrap();
+    let song = edited_song(bytes);
+    let project = song.project().unwrap();
+
+    let other = std::fs::read("../assets/heart_beat-band.mid").unwrap();
+    assert!(MmlSong::from_project(project, other).is_err());
+}
+
+#[test]
+fn test_serialize_song() {
+    let bytes = std::fs::read(MIDI_PATH).unwrap();
+    let song = edited_song(bytes);
+
+    let json = serde_json::to_string(&song).unwrap();
+    let deserialized: MmlSong = serde_json::from_str(&json).unwrap();
+
+    assert_eq!(summarize(&deserialized), summarize(&song));
+    assert_eq!(deserialized.options, song.options);
+}
@@ -0,0 +1,67 @@
+use midi_to_mml::{MmlSong, MmlSongOptions, VelocityCurve};
+
+const MIDI_PATHS: [&str; 3] = [
+    "../assets/Stay_With_Me_-_Miki_Matsubara.mid",
+    "../assets/heart_beat-band.mid",
+    "../assets/cloudless-yorushika.mid",
+];
+
+fn get_events(song: &MmlSong) -> Vec<String> {
+    song.tracks
+        .iter()
+        .map(|track| serde_json::to_string(&track.events).unwrap())
+        .collect()
+}
+
+#[test]
+fn test_set_song_options_matches_new_song() {
+    let changes = [
+        MmlSongOptions {
+            velocity_max: 12,
+            ..MmlSongOptions::default()
+        },
+        MmlSongOptions {
+            velocity_min: 4,
+            velocity_max: 9,
+            ..MmlSongOptions::default()
+        },
+        MmlSongOptions {
+            velocity_min: 3,
+            velocity_curve: VelocityCurve::Exponential { strength: 2. },
+            ..MmlSongOptions::default()
+        },
+        MmlSongOptions {
+            velocity_curve: VelocityCurve::Percentile {
+                low: 5,
+                high: 95,
+                fitted: None,
+            },
+            ..MmlSongOptions::default()
+        },
+        MmlSongOptions {
+            smallest_unit: 32,
+            velocity_max: 10,
+            ..MmlSongOptions::default()
+        },
+        MmlSongOptions {
+            min_gap_for_chord: 2,
+            ..MmlSongOptions::default()
+        },
+    ];
+
+    for path in MIDI_PATHS {
+        let mut song = MmlSong::from_path(path, MmlSongOptions::default()).unwrap();
+
+        // Each change starts from the options of the one before
+        for options in changes.iter() {
+            song.set_song_options(options.to_owned()).unwrap();
+            let expected = MmlSong::from_path(path, options.to_owned()).unwrap();
+
+            assert_eq!(
+                get_events(&song),
+                get_events(&expected),
+                "{path} {options:?}"
+            );
+        }
+    }
+}
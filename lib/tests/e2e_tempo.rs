@@ -0,0 +1,185 @@
+use std::sync::Arc;
+
+use midi_to_mml::{BridgeEvent, Instrument, MmlProject, MmlSong, MmlSongOptions, TempoTransform};
+
+const MIDI_PATH: &str = "../assets/Stay_With_Me_-_Miki_Matsubara.mid";
+
+fn get_tempos(song: &MmlSong, index: usize) -> Vec<(usize, u32)> {
+    song.tracks[index]
+        .bridge_meta_events
+        .iter()
+        .filter_map(|event| match event {
+            BridgeEvent::Tempo(tempo, state) => Some((state.position_in_tick, *tempo)),
+            _ => None,
+        })
+        .collect()
+}
+
+fn summarize(song: &MmlSong) -> Vec<String> {
+    song.tracks.iter().map(|track| track.to_mml()).collect()
+}
+
+#[test]
+fn test_flatten_tempo() {
+    let mut song = MmlSong::from_path(MIDI_PATH, MmlSongOptions::default()).unwrap();
+    let note_count: usize = song
+        .tracks
+        .iter()
+        .map(|track| track.bridge_note_events.len())
+        .sum();
+
+    let report = song
+        .transform_tempo(&TempoTransform::Flatten { tempo: None })
+        .unwrap();
+
+    assert_eq!(report.tempo_count_before, 8);
+    assert_eq!(report.tempo_count_after, 1);
+    assert!(report.note_count > 0 && report.note_count <= note_count);
+    assert!(report.max_error_ms < 30., "{report:?}");
+    for index in 0..song.tracks.len() {
+        assert_eq!(get_tempos(&song, index).len(), 1);
+    }
+}
+
+#[test]
+fn test_merge_tempo() {
+    let mut song = MmlSong::from_path(MIDI_PATH, MmlSongOptions::default()).unwrap();
+    let tempos = get_tempos(&song, 0);
+
+    let report = song
+        .transform_tempo(&TempoTransform::Merge { threshold: 5 })
+        .unwrap();
+
+    assert!(report.tempo_count_after < tempos.len());
+    assert!(report.max_error_ms < 1., "{report:?}");
+}
+
+#[test]
+fn test_scale_tempo() {
+    let mut song =
+        MmlSong::from_path("../assets/hishokunosora.mid", MmlSongOptions::default()).unwrap();
+    let tempos = get_tempos(&song, 0);
+    let mml = summarize(&song);
+
+    let report = song
+        .transform_tempo(&TempoTransform::Scale { factor: 2. })
+        .unwrap();
+
+    let scaled: Vec<(usize, u32)> = tempos
+        .iter()
+        .map(|(position, tempo)| (*position, tempo * 2))
+        .collect();
+    assert_eq!(get_tempos(&song, 0), scaled);
+    assert!(report.max_error_ms < 1., "{report:?}");
+
+    // Only the tempo commands change
+    let remove_tempos = |mml: &str| {
+        mml.split('t')
+            .enumerate()
+            .map(|(index, part)| {
+                if index == 0 {
+                    part
+                } else {
+                    part.trim_start_matches(|c: char| c.is_ascii_digit())
+                }
+            })
+            .collect::<String>()
+    };
+    for (before, after) in mml.iter().zip(summarize(&song).iter()) {
+        assert_eq!(remove_tempos(before), remove_tempos(after));
+    }
+}
+
+#[test]
+fn test_invalid_tempo_transform() {
+    let mut song = MmlSong::from_path(MIDI_PATH, MmlSongOptions::default()).unwrap();
+
+    assert!(
+        song.transform_tempo(&TempoTransform::Scale { factor: 0. })
+            .is_err()
+    );
+    assert!(
+        song.transform_tempo(&TempoTransform::Flatten { tempo: Some(0) })
+            .is_err()
+    );
+    assert!(!song.can_undo());
+}
+
+#[test]
+fn test_undo_and_replay_tempo_transform() {
+    let bytes = std::fs::read(MIDI_PATH).unwrap();
+    let mut song = MmlSong::from_bytes(bytes.to_owned(), MmlSongOptions::default()).unwrap();
+    let original = summarize(&song);
+
+    song.transform_tempo(&TempoTransform::Flatten { tempo: Some(100) })
+        .unwrap();
+    let flattened = summarize(&song);
+    assert_ne!(flattened, original);
+
+    let json = song.project().unwrap().to_json().unwrap();
+    let reopened = MmlSong::from_project(&MmlProject::from_json(&json).unwrap(), bytes).unwrap();
+    assert_eq!(summarize(&reopened), flattened);
+
+    song.undo().unwrap();
+    assert_eq!(summarize(&song), original);
+}
+
+#[test]
+fn test_meta_events_stay_shared() {
+    let is_shared = |song: &MmlSong| {
+        let first = &song.tracks[0].bridge_meta_events;
+        song.tracks
+            .iter()
+            .all(|track| Arc::ptr_eq(first, &track.bridge_meta_events))
+    };
+
+    let mut song = MmlSong::from_path(MIDI_PATH, MmlSongOptions::default()).unwrap();
+    assert!(is_shared(&song));
+
+    song.split_track(0).unwrap();
+    assert!(is_shared(&song));
+
+    song.transform_tempo(&TempoTransform::Flatten { tempo: None })
+        .unwrap();
+    assert!(is_shared(&song));
+    assert_eq!(get_tempos(&song, 0).len(), 1);
+}
+
+#[test]
+fn test_tracks_of_different_lengths_stay_in_sync() {
+    // A whole note at 120 then three at 60, the second track stops after the second note
+    let tracks = vec![
+        (String::from("t120c1t60c1c1c1"), Instrument::default()),
+        (String::from("r1e1"), Instrument::default()),
+    ];
+    let get_starts = |song: &MmlSong, index: usize| -> Vec<usize> {
+        song.tracks[index]
+            .bridge_note_events
+            .iter()
+            .filter_map(|event| match event {
+                BridgeEvent::Note(note) => Some(note.midi_state.position_in_tick),
+                _ => None,
+            })
+            .collect()
+    };
+
+    for transform in [
+        TempoTransform::Flatten { tempo: None },
+        TempoTransform::Merge { threshold: 100 },
+    ] {
+        let mut song = MmlSong::from_mml(tracks.to_owned(), MmlSongOptions::default()).unwrap();
+        song.transform_tempo(&transform).unwrap();
+
+        // 16 quarters in 14 seconds for the whole song, not only for the second track
+        assert_eq!(get_tempos(&song, 0), vec![(0, 69)], "{transform:?}");
+        assert_eq!(get_tempos(&song, 1), get_tempos(&song, 0));
+        assert!(
+            song.tracks
+                .iter()
+                .all(|track| track.to_mml().starts_with("t69"))
+        );
+
+        // The second note of both tracks still starts at the same time
+        assert_eq!(get_starts(&song, 0)[1], get_starts(&song, 1)[0]);
+    }
+}
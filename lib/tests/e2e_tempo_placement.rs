@@ -0,0 +1,78 @@
+use midi_to_mml::{MmlSong, MmlSongOptions, TempoPlacement, VerificationOptions};
+
+const MIDI_PATH: &str = "../assets/Stay_With_Me_-_Miki_Matsubara.mid";
+
+fn song_with_placement(tempo_placement: TempoPlacement) -> MmlSong {
+    MmlSong::from_path(
+        MIDI_PATH,
+        MmlSongOptions {
+            tempo_placement,
+            ..MmlSongOptions::default()
+        },
+    )
+    .unwrap()
+}
+
+fn tempo_counts(song: &MmlSong) -> Vec<usize> {
+    song.tracks
+        .iter()
+        .map(|track| track.to_mml().matches('t').count())
+        .collect()
+}
+
+#[test]
+fn test_tempo_in_every_track_by_default() {
+    let song = song_with_placement(TempoPlacement::AllTracks);
+
+    assert_eq!(tempo_counts(&song), vec![8, 8]);
+}
+
+#[test]
+fn test_tempo_in_conductor_track() {
+    let all_tracks = song_with_placement(TempoPlacement::AllTracks);
+    let song = song_with_placement(TempoPlacement::Conductor { index: 1 });
+
+    assert_eq!(tempo_counts(&song), vec![0, 8]);
+    assert!(song.tracks[0].to_mml().len() < all_tracks.tracks[0].to_mml().len());
+
+    // Out of range, the first track with notes gets them
+    let song = song_with_placement(TempoPlacement::Conductor { index: 5 });
+    assert_eq!(tempo_counts(&song), vec![8, 0]);
+}
+
+#[test]
+fn test_tracks_stay_aligned() {
+    let options = VerificationOptions::default();
+    let all_tracks = song_with_placement(TempoPlacement::AllTracks).verify(&options);
+    let first_non_empty = song_with_placement(TempoPlacement::FirstNonEmpty).verify(&options);
+
+    // Without tempo commands in the way, no note is cut to make room for them
+    for (expected, verification) in all_tracks.iter().zip(first_non_empty.iter()) {
+        assert!(verification.matched_count >= expected.matched_count);
+        assert!(
+            (verification.max_drift_ms - expected.max_drift_ms).abs() < 1.,
+            "{} {}",
+            verification.max_drift_ms,
+            expected.max_drift_ms
+        );
+    }
+}
+
+#[test]
+fn test_tempo_follows_operations() {
+    let mut song = song_with_placement(TempoPlacement::FirstNonEmpty);
+    song.split_track(0).unwrap();
+    assert_eq!(tempo_counts(&song), vec![8, 0, 0]);
+
+    // The conductor is merged into another track
+    song.merge_tracks(1, 0).unwrap();
+    assert_eq!(tempo_counts(&song), vec![8, 0]);
+
+    song.undo().unwrap();
+    assert_eq!(tempo_counts(&song), vec![8, 0, 0]);
+
+    song.set_song_options(MmlSongOptions::default()).unwrap();
+    assert_eq!(tempo_counts(&song), vec![8, 8, 8]);
+    song.undo().unwrap();
+    assert_eq!(tempo_counts(&song), vec![8, 0, 0]);
+}
@@ -0,0 +1,33 @@
+mod common;
+
+use common::get_notes;
+use midi_to_mml::{MmlSong, MmlSongOptions};
+
+#[test]
+fn test_e2e() {
+    for entry in std::fs::read_dir("../assets").unwrap() {
+        let entry = entry.unwrap();
+        let path = entry.path();
+
+        if path.is_file() && path.extension().is_some_and(|ext| ext == "mid") {
+            let song = MmlSong::from_path(&path, MmlSongOptions::default()).unwrap();
+            assert_round_trip(&song);
+        }
+    }
+}
+
+/// Converting the exported file again must give the same notes
+fn assert_round_trip(song: &MmlSong) {
+    let mut bytes: Vec<u8> = Vec::new();
+    song.to_smf().write_std(&mut bytes).unwrap();
+
+    let exported = MmlSong::from_bytes(bytes, MmlSongOptions::default()).unwrap();
+
+    // The first track of the exported file only contains tempo events
+    assert_eq!(exported.tracks.len(), song.tracks.len() + 1);
+
+    for (track, exported_track) in song.tracks.iter().zip(exported.tracks.iter().skip(1)) {
+        assert_eq!(track.instrument, exported_track.instrument);
+        assert_eq!(get_notes(track), get_notes(exported_track));
+    }
+}
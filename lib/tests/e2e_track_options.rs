@@ -0,0 +1,112 @@
+use midi_to_mml::{MmlProject, MmlSong, MmlSongOptions, TrackOptionsOverride, utils};
+
+const MIDI_PATH: &str = "../assets/heart_beat-band.mid";
+
+fn get_mml(song: &MmlSong) -> Vec<String> {
+    song.tracks.iter().map(|track| track.to_mml()).collect()
+}
+
+#[test]
+fn test_override_one_track() {
+    let mut song = MmlSong::from_path(MIDI_PATH, MmlSongOptions::default()).unwrap();
+    let before = get_mml(&song);
+
+    song.set_track_options(
+        0,
+        TrackOptionsOverride {
+            smallest_unit: Some(16),
+            ..TrackOptionsOverride::default()
+        },
+    )
+    .unwrap();
+    let after = get_mml(&song);
+
+    assert_ne!(after[0], before[0]);
+    assert_eq!(after[1..], before[1..]);
+    assert_eq!(song.tracks[0].song_options.smallest_unit, 16);
+    assert_eq!(song.options.smallest_unit, 64);
+
+    // The override is kept when the song options change
+    song.set_song_options(MmlSongOptions {
+        smallest_unit: 32,
+        ..MmlSongOptions::default()
+    })
+    .unwrap();
+    let with_song_options = get_mml(&song);
+
+    assert_eq!(with_song_options[0], after[0]);
+    assert_ne!(with_song_options[1..], after[1..]);
+    assert!(
+        song.tracks[1..]
+            .iter()
+            .all(|track| track.song_options.smallest_unit == 32)
+    );
+
+    assert!(song.undo().is_some());
+    assert_eq!(get_mml(&song), after);
+    assert!(song.undo().is_some());
+    assert_eq!(get_mml(&song), before);
+}
+
+#[test]
+fn test_override_velocity_range_with_boost() {
+    let mut song = MmlSong::from_path(
+        MIDI_PATH,
+        MmlSongOptions {
+            auto_boot_velocity: true,
+            ..MmlSongOptions::default()
+        },
+    )
+    .unwrap();
+
+    song.set_track_options(
+        2,
+        TrackOptionsOverride {
+            velocity_min: Some(2),
+            velocity_max: Some(9),
+            ..TrackOptionsOverride::default()
+        },
+    )
+    .unwrap();
+
+    let highest: Vec<u8> = song
+        .tracks
+        .iter()
+        .map(|track| utils::get_highest_velocity(&track.events))
+        .collect();
+    assert!(highest[2] <= 9);
+
+    // Options that are not used by the conversion do not regenerate the tracks
+    let before = get_mml(&song);
+    song.set_song_options(MmlSongOptions {
+        auto_equalize_note_length: true,
+        ..song.options.to_owned()
+    })
+    .unwrap();
+    assert_eq!(get_mml(&song), before);
+}
+
+#[test]
+fn test_override_in_project() {
+    let bytes = std::fs::read(MIDI_PATH).unwrap();
+    let mut song = MmlSong::from_bytes(bytes.to_owned(), MmlSongOptions::default()).unwrap();
+    song.set_track_options(
+        1,
+        TrackOptionsOverride {
+            min_gap_for_chord: Some(2),
+            ..TrackOptionsOverride::default()
+        },
+    )
+    .unwrap();
+    song.split_track(1).unwrap();
+
+    let json = song.project().unwrap().to_json().unwrap();
+    let project = MmlProject::from_json(&json).unwrap();
+    let reopened = MmlSong::from_project(&project, bytes).unwrap();
+
+    assert_eq!(get_mml(&reopened), get_mml(&song));
+    assert_eq!(
+        reopened.tracks[2].options_override.min_gap_for_chord,
+        Some(2)
+    );
+}
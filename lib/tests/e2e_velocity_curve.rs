@@ -0,0 +1,68 @@
+use std::collections::BTreeSet;
+
+use midi_to_mml::{MmlEvent, MmlSong, MmlSongOptions, VelocityCurve, utils};
+
+const MIDI_PATH: &str = "../assets/Stay_With_Me_-_Miki_Matsubara.mid";
+
+fn get_velocities(song: &MmlSong) -> BTreeSet<u8> {
+    song.tracks
+        .iter()
+        .flat_map(|track| track.events.iter())
+        .filter_map(|event| match event {
+            MmlEvent::Velocity(velocity) => Some(*velocity),
+            _ => None,
+        })
+        .collect()
+}
+
+#[test]
+fn test_percentile_uses_whole_range() {
+    let path = "../assets/heart_beat-band.mid";
+    let linear = MmlSong::from_path(path, MmlSongOptions::default()).unwrap();
+    let percentile = MmlSong::from_path(
+        path,
+        MmlSongOptions {
+            velocity_curve: VelocityCurve::Percentile {
+                low: 5,
+                high: 95,
+                fitted: None,
+            },
+            ..MmlSongOptions::default()
+        },
+    )
+    .unwrap();
+
+    let linear_velocities = get_velocities(&linear);
+    let percentile_velocities = get_velocities(&percentile);
+
+    assert!(percentile_velocities.len() > linear_velocities.len());
+    assert_eq!(percentile_velocities.first(), Some(&0));
+    assert_eq!(percentile_velocities.last(), Some(&15));
+    assert!(matches!(
+        percentile.options.velocity_curve,
+        VelocityCurve::Percentile {
+            fitted: Some(_),
+            ..
+        }
+    ));
+}
+
+#[test]
+fn test_auto_boot_on_top_of_curve() {
+    let options = MmlSongOptions {
+        auto_boot_velocity: true,
+        velocity_max: 13,
+        velocity_curve: VelocityCurve::Exponential { strength: 4. },
+        ..MmlSongOptions::default()
+    };
+    let song = MmlSong::from_path(MIDI_PATH, options.to_owned()).unwrap();
+
+    for track in song.tracks.iter() {
+        assert!(utils::get_highest_velocity(&track.events) <= 13);
+    }
+    assert_eq!(get_velocities(&song).last(), Some(&13));
+
+    let mut reopened = MmlSong::from_path(MIDI_PATH, MmlSongOptions::default()).unwrap();
+    reopened.set_song_options(options).unwrap();
+    assert_eq!(get_velocities(&reopened), get_velocities(&song));
+}
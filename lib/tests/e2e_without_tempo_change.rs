@@ -1,4 +1,7 @@
-use midi_to_mml::{MmlEvent, MmlSong, MmlSongOptions, utils::compute_position_in_smallest_unit};
+use midi_to_mml::{
+    MmlEvent, MmlSong, MmlSongOptions, VerificationOptions,
+    utils::compute_position_in_smallest_unit,
+};
 use rayon::prelude::*;
 
 const MIDI_FILE_PATH: &str = "../assets/cloudless-yorushika.mid";
@@ -9,6 +12,16 @@ fn test_e2e() {
     let song = MmlSong::from_path(MIDI_FILE_PATH, options).unwrap();
     assert_only_1_tempo_change(&song);
     assert_notes(&song);
+    assert_timing(&song);
+}
+
+/// With one tempo, the MML only drifts by the quantization
+fn assert_timing(song: &MmlSong) {
+    for verification in song.verify(&VerificationOptions::default()) {
+        assert!(verification.pitch_errors.is_empty());
+        assert!(verification.mean_drift_ms < 5.);
+        assert!(verification.max_drift_ms < 60.);
+    }
 }
 
 fn assert_notes(song: &MmlSong) {
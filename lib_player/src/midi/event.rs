@@ -1,5 +1,5 @@
 use crate::utils;
-use anyhow::Result;
+use midi_to_mml::syntax::TimedNote;
 
 #[derive(Debug, Clone)]
 pub struct NoteEvent {
@@ -14,48 +14,24 @@ pub struct NoteEvent {
     pub char_length: usize,
 }
 impl NoteEvent {
-    pub fn from_mml(
-        mml: String,
-        octave: u8,
-        velocity: u8,
-        tempo: usize,
-        is_connected_to_prev_note: bool,
-        char_index: usize,
-    ) -> Result<Self> {
-        let parts = mml.split('&');
-        let mut mml_key: Option<String> = None;
-        let mut key_length: usize = 1;
-        let mut midi_key: Option<u8> = None;
-        let mut duration_in_smallest_unit: usize = 0;
-        let midi_velocity = utils::mml_velocity_to_midi_velocity(velocity);
+    pub fn from_timed_note(note: TimedNote, raw_mml: &str) -> Self {
+        let tempo = note.tempo as usize;
+        let duration_in_ms =
+            utils::duration_in_smallest_unit_to_ms(note.duration_in_smallest_unit, tempo);
 
-        for part in parts {
-            if mml_key.is_none() {
-                let mml_key_value = utils::get_mml_key(part)?;
-
-                key_length = mml_key_value.len();
-                midi_key = utils::mml_to_midi_key(&mml_key_value, octave)?;
-                mml_key = Some(mml_key_value);
-            }
-
-            let duration_part = &part[key_length..];
-            let duration = utils::mml_duration_to_duration_in_smallest_unit(duration_part)?;
-            duration_in_smallest_unit += duration;
-        }
-
-        let duration_in_ms: usize =
-            utils::duration_in_smallest_unit_to_ms(duration_in_smallest_unit, tempo);
-
-        Ok(Self {
+        Self {
+            raw_mml: raw_mml
+                .get(note.span.to_owned())
+                .unwrap_or_default()
+                .to_string(),
             tempo,
-            midi_key,
-            midi_velocity,
-            duration_in_smallest_unit,
+            midi_key: note.midi_key,
+            midi_velocity: utils::mml_velocity_to_midi_velocity(note.velocity),
+            duration_in_smallest_unit: note.duration_in_smallest_unit,
             duration_in_ms,
-            is_connected_to_prev_note,
-            char_index,
-            char_length: mml.len(),
-            raw_mml: mml,
-        })
+            is_connected_to_prev_note: note.is_part_of_chord,
+            char_index: note.span.start,
+            char_length: note.span.len(),
+        }
     }
 }
@@ -1,2 +1 @@
-pub mod event;
 pub mod parser;
@@ -1,10 +1,6 @@
 use crate::NoteEvent;
-use anyhow::{Context, Result};
-
-use super::event::MmlEvent;
-
-const NOTE_NAMES: [char; 8] = ['c', 'd', 'e', 'f', 'g', 'a', 'b', 'r'];
-const NOTE_EXTRAS: [char; 3] = ['&', '.', '+'];
+use anyhow::{Result, anyhow};
+use midi_to_mml::syntax::{self, MAX_SMALLEST_UNIT, ParseErrorKind};
 
 #[derive(Debug, Clone)]
 pub struct Parser {
@@ -25,141 +21,27 @@ impl Parser {
 }
 
 fn parse_note_events(mml: &str) -> Result<Vec<NoteEvent>> {
-    let mut index = 0;
-    let mut current_mml_velocity = 12u8;
-    let mut current_octave = 4u8;
-    let mut current_tempo = 120usize;
-    let mut is_connect_chord = false;
-    let mut notes: Vec<NoteEvent> = Vec::with_capacity(mml.len() / 2);
-
-    while let Some(event) = parse_event(
-        mml,
-        &mut index,
-        current_mml_velocity,
-        current_octave,
-        current_tempo,
-        &mut is_connect_chord,
-    )? {
-        match event {
-            MmlEvent::SetNote(note) => notes.push(note),
-            MmlEvent::SetTempo(tempo) => current_tempo = tempo,
-            MmlEvent::SetOctave(octave) => current_octave = octave,
-            MmlEvent::IncreOctave => current_octave += 1,
-            MmlEvent::DecreOctave => {
-                current_octave = current_octave.saturating_sub(1);
-            }
-            MmlEvent::SetVelocity(velocity) => current_mml_velocity = velocity,
-            MmlEvent::ConnectChord => is_connect_chord = true,
-            MmlEvent::Empty => (),
-        }
-    }
-
-    Ok(notes)
-}
-
-fn parse_event(
-    raw_mml: &str,
-    index: &mut usize,
-    current_mml_velocity: u8,
-    current_mml_octave: u8,
-    current_tempo: usize,
-    is_connect_chord: &mut bool,
-) -> Result<Option<MmlEvent>> {
-    let char = match raw_mml.as_bytes().get(*index) {
-        Some(&c) => c as char,
-        None => return Ok(None),
-    };
-
-    match char {
-        't' => {
-            let (value, len) = get_first_mml_value(&raw_mml[*index..]);
-            *index += len + 1;
-            let tempo = value.parse::<usize>().context("Invalid tempo value")?;
-            Ok(Some(MmlEvent::SetTempo(tempo)))
-        }
-        'o' => {
-            let value = raw_mml
-                .get(*index + 1..*index + 2)
-                .context("Missing octave value")?;
-            *index += 2;
-            let octave = value.parse::<u8>().context("Invalid octave value")?;
-            Ok(Some(MmlEvent::SetOctave(octave)))
-        }
-        'v' => {
-            let (value, len) = get_first_mml_value(&raw_mml[*index..]);
-            *index += len + 1;
-            let velocity = value.parse::<u8>().context("Invalid velocity value")?;
-            Ok(Some(MmlEvent::SetVelocity(velocity)))
-        }
-        '>' => {
-            *index += 1;
-            Ok(Some(MmlEvent::IncreOctave))
-        }
-        '<' => {
-            *index += 1;
-            Ok(Some(MmlEvent::DecreOctave))
-        }
-        ':' => {
-            *index += 1;
-            Ok(Some(MmlEvent::ConnectChord))
-        }
-        c if NOTE_NAMES.contains(&c) => {
-            let (mml_note, len) = get_first_mml_note(&raw_mml[*index..])?;
-            let note = NoteEvent::from_mml(
-                mml_note.to_string(),
-                current_mml_octave,
-                current_mml_velocity,
-                current_tempo,
-                *is_connect_chord,
-                *index,
-            )?;
-            *is_connect_chord = false;
-            *index += len;
-            Ok(Some(MmlEvent::SetNote(note)))
-        }
-        _ => {
-            *index += 1;
-            Ok(Some(MmlEvent::Empty))
-        }
+    let parsed = syntax::parse(mml);
+
+    // Unknown characters are skipped, like the game does
+    if let Some(error) = parsed
+        .errors
+        .iter()
+        .find(|error| !matches!(error.kind, ParseErrorKind::UnknownCommand(_)))
+    {
+        return Err(anyhow!("Invalid MML: {error}"));
     }
-}
-
-fn get_first_mml_note(mml: &str) -> Result<(&str, usize)> {
-    let mut len = 1;
-    let mut is_note_extra_checked = false;
-    let mut before_char = mml.chars().next().context("Empty MML string")?;
-    let note_name = before_char;
-
-    for (i, char) in mml.chars().enumerate().skip(1) {
-        if !is_note_extra_checked {
-            if char == '+' {
-                len += 1;
-                continue;
-            }
-            is_note_extra_checked = true;
-        }
 
-        let is_break = !(char.is_ascii_digit()
-            || NOTE_EXTRAS.contains(&char)
-            || (char == note_name && before_char == '&'));
+    let mut timed_notes = syntax::timed_notes(&parsed.nodes, MAX_SMALLEST_UNIT);
 
-        if is_break {
-            return Ok((mml.get(..i).context("Invalid slice")?, i));
-        }
-        before_char = char;
-        len += 1;
-    }
+    // The track player plays notes one after another.
+    // A chord note written after a rest, like `c4:r8e4`, must be played with its chord first.
+    timed_notes.sort_by_key(|note| note.position_in_smallest_unit);
 
-    Ok((mml.get(..len).context("Invalid slice")?, len))
-}
+    let notes = timed_notes
+        .into_iter()
+        .map(|note| NoteEvent::from_timed_note(note, mml))
+        .collect();
 
-fn get_first_mml_value(mml: &str) -> (&str, usize) {
-    let mut len = 0;
-    for (i, char) in mml.chars().skip(1).enumerate() {
-        if !char.is_ascii_digit() {
-            return (mml.get(1..=i).unwrap_or(""), i);
-        }
-        len = i + 1;
-    }
-    (mml.get(1..=len).unwrap_or(""), len)
+    Ok(notes)
 }
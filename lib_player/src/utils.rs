@@ -1,79 +1,17 @@
 use crate::{NoteEvent, SynthOutputConnection};
-use anyhow::{Context, Ok, Result};
+use anyhow::{Ok, Result};
+use midi_to_mml::syntax::MAX_SMALLEST_UNIT;
 use std::time::Duration;
 use tracing::trace;
 
-const SMALLEST_UNIT: usize = 256;
-
 pub fn mml_velocity_to_midi_velocity(mml_velocity: u8) -> u8 {
     let mml_f64: f64 = mml_velocity as f64;
     (mml_f64 / 15.0 * 127.0) as u8
 }
 
-pub fn mml_to_midi_key(mml: &str, octave: u8) -> Result<Option<u8>> {
-    let mml = mml.to_lowercase();
-    let chars = mml.chars();
-    let mut midi_key: i8 = -1;
-
-    for char in chars {
-        match char {
-            'c' => midi_key = 12,
-            'd' => midi_key = 14,
-            'e' => midi_key = 16,
-            'f' => midi_key = 17,
-            'g' => midi_key = 19,
-            'a' => midi_key = 21,
-            'b' => midi_key = 23,
-            '+' => midi_key += 1,
-            _ => (),
-        };
-    }
-
-    if midi_key < 0 {
-        return Ok(None);
-    }
-
-    let mut result: u8 = midi_key.try_into()?;
-    result += octave * 12;
-    Ok(Some(result))
-}
-
-/// `c+4&c+32.` => `c+`
-pub fn get_mml_key(mml: &str) -> Result<String> {
-    let mut chars = mml.chars();
-    let first = chars.next().context("Failed to get first character")?;
-    let second = chars.next().context("Failed to get second character")?;
-
-    Ok(if second == '+' {
-        format!("{}{}", first, second)
-    } else {
-        first.to_string()
-    })
-}
-
-pub fn mml_duration_to_duration_in_smallest_unit(mml_duration: &str) -> Result<usize> {
-    let mut is_has_a_dot = false;
-    let mut mml = mml_duration;
-    let last = mml.chars().last().context("Failed to get last character")?;
-
-    if last == '.' {
-        mml = &mml[..mml.len() - 1];
-        is_has_a_dot = true;
-    }
-
-    let mml_duration = mml.parse::<usize>()?;
-    let mut result = SMALLEST_UNIT / mml_duration;
-
-    if is_has_a_dot {
-        result += result / 2;
-    }
-
-    Ok(result)
-}
-
 pub fn duration_in_smallest_unit_to_ms(duration_in_smallest_unit: usize, tempo: usize) -> usize {
     let tempo_f64 = tempo as f64;
-    let smallest_unit_f64 = SMALLEST_UNIT as f64;
+    let smallest_unit_f64 = MAX_SMALLEST_UNIT as f64;
     let dur_per_smallest_unit_in_ms = 240000.0 / (smallest_unit_f64 * tempo_f64);
 
     let result = duration_in_smallest_unit as f64 * dur_per_smallest_unit_in_ms;